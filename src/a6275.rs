@@ -5,29 +5,95 @@
 //! and a rising LATCH edge copies the register to the relay outputs. The register
 //! also clocks back out of its serial output, which is how the state is read.
 //!
+//! The CH341A can drive those lines three ways, picked by [`Drive`]: one transfer
+//! per line change; through its SPI engine, which clocks a whole byte in and the
+//! register's previous contents out in a single round trip; or as one UIO stream
+//! that waits for the lines to settle between its states.
+//!
 //! [`A6275`] is generic over [`Gpio`] rather than talking to a `Ch341a` directly,
 //! which is what lets every path here be tested against a simulated shift register
 //! instead of hardware.
 
-use crate::Verify;
-use crate::ch341a::{Gpio, SAMPLES};
-use crate::errors::{Error, Result};
+use crate::ch341a::{Gpio, SAMPLES, Step};
+use crate::errors::{Error, Result, Suspect};
 use crate::relays::Relays;
+use crate::{Drive, Verify};
 
 // Allegro A6275 pin mapping on the CH341A D0–D7 GPIO lines.
-const LATCH: u8 = 0x01; // D0 → A6275 Latch
-const CLK: u8 = 0x08; // D3 → A6275 CLK
-const DATA: u8 = 0x20; // D5 → A6275 Serial in
-const READ: u8 = 0x80; // D7 ← A6275 Serial out
+pub const LATCH: u8 = 0x01; // D0 → A6275 Latch
+pub const CLK: u8 = 0x08; // D3 → A6275 CLK
+pub const DATA: u8 = 0x20; // D5 → A6275 Serial in
+pub const READ: u8 = 0x80; // D7 ← A6275 Serial out
+
+/// How long a streamed write holds each line change before the next, in
+/// microseconds.
+///
+/// Without a wait the CH341A runs stream states faster than the DATA line
+/// settles, and every rising CLK edge takes in the bit before the one meant. The
+/// bit-banged write has only ever been run with one transfer, ~41 µs, between
+/// changes, so this keeps to that spacing rather than a shorter one nobody has
+/// measured.
+const SETTLE_MICROS: u8 = 40;
+
+/// Appends the line changes that shift `status` in, as [`A6275::shift_out_bits`]
+/// makes them, each followed by [`SETTLE_MICROS`].
+///
+/// If `sample`, each bit is preceded by a reading of the serial output, as
+/// [`Gpio::sample_clocked`] takes them: the register comes out while `status`
+/// goes in, and a read is its own restore.
+fn push_shift(steps: &mut Vec<Step>, status: u8, sample: bool) {
+    for bit in (0..8).rev() {
+        let data = if status & (1 << bit) != 0 { DATA } else { 0 };
+
+        if sample {
+            steps.push(Step::Sample);
+        }
+
+        for lines in [data, CLK | data, data] {
+            steps.extend([Step::Output(lines), Step::Wait(SETTLE_MICROS)]);
+        }
+    }
+
+    steps.extend([Step::Output(0), Step::Wait(SETTLE_MICROS)]);
+}
+
+/// Appends the line changes that shift `status` in and latch it, each followed by
+/// [`SETTLE_MICROS`].
+fn push_latch(steps: &mut Vec<Step>, status: u8) {
+    push_shift(steps, status, false);
+    steps.extend([
+        Step::Output(LATCH),
+        Step::Wait(SETTLE_MICROS),
+        Step::Output(0),
+        Step::Wait(SETTLE_MICROS),
+    ]);
+}
+
+/// Folds one reading per bit into the register they were clocked out of, first
+/// reading into the most significant bit.
+fn fold(samples: impl IntoIterator<Item = u8>) -> u8 {
+    samples.into_iter().fold(0, |status, sample| {
+        status << 1 | u8::from(sample & READ != 0)
+    })
+}
 
 /// The Allegro A6275 shift register protocol, driven over a set of GPIO lines.
 pub struct A6275<T> {
     gpio: T,
+    drive: Drive,
 }
 
 impl<T: Gpio> A6275<T> {
     pub fn new(gpio: T) -> Self {
-        Self { gpio }
+        Self {
+            gpio,
+            drive: Drive::default(),
+        }
+    }
+
+    /// Drives the register through `drive` from now on.
+    pub fn with_drive(self, drive: Drive) -> Self {
+        Self { drive, ..self }
     }
 
     /// Shifts 8 bits into the A6275 shift register (MSB first) without latching.
@@ -58,19 +124,63 @@ impl<T: Gpio> A6275<T> {
         // bit, folded into the `u8` below, which a wider read would silently truncate.
         const { assert!(SAMPLES == u8::BITS as usize, "the register is one byte") };
 
-        let mut status = 0;
-
         // The register presents its most significant bit first.
-        for sample in self.gpio.sample_clocked(CLK)? {
-            status = status << 1 | u8::from(sample & READ != 0);
+        Ok(fold(self.gpio.sample_clocked(CLK)?))
+    }
+
+    /// Puts `status` in the register without latching it, by whichever drive the
+    /// board uses.
+    fn write(&self, status: u8) -> Result<()> {
+        match self.drive {
+            Drive::Gpio => self.shift_out_bits(status),
+            Drive::Spi => self.gpio.exchange(status).map(drop),
+            Drive::Stream => {
+                let mut steps = Vec::new();
+
+                push_shift(&mut steps, status, false);
+
+                self.gpio.stream(&steps).map(drop)
+            }
         }
+    }
 
-        Ok(status)
+    /// Reads the register, by whichever drive the board uses.
+    ///
+    /// Destructive either way: the clocked read shifts zeros in, and so does the
+    /// exchange, because zeros are what it sends. A streamed board reads with the
+    /// clocked read, which has never needed a wait.
+    fn read(&self) -> Result<u8> {
+        match self.drive {
+            Drive::Gpio | Drive::Stream => self.read_shift_register(),
+            Drive::Spi => self.gpio.exchange(0),
+        }
+    }
+
+    /// Reads the register and leaves it holding `status`.
+    ///
+    /// A read followed by a restore when bit-banging. Over SPI the two are one
+    /// exchange — what it sends is what it puts back — and in a stream one pass
+    /// that samples each bit before shifting in its replacement. Either still gets
+    /// its restore when it fails, because it may have clocked any part of the byte.
+    fn read_restoring(&self, status: u8) -> Result<u8> {
+        let read = match self.drive {
+            Drive::Gpio => return self.restoring(status, || self.read_shift_register()),
+            Drive::Spi => self.gpio.exchange(status),
+            Drive::Stream => {
+                let mut steps = Vec::new();
+
+                push_shift(&mut steps, status, true);
+
+                self.gpio.stream(&steps).map(fold)
+            }
+        };
+
+        read.or_else(|e| self.restore(status).and(Err(e)))
     }
 
     /// Puts `status` back into the register after a read consumed it.
     fn restore(&self, status: u8) -> Result<()> {
-        self.shift_out_bits(status).map_err(Error::out_of_sync)
+        self.write(status).map_err(Error::out_of_sync)
     }
 
     /// Runs `op`, then puts `status` back into the register whether it succeeded or not.
@@ -87,38 +197,115 @@ impl<T: Gpio> A6275<T> {
         result
     }
 
+    /// Puts `status` in the register without latching it, so that the next read
+    /// reports it and no relay moves.
+    pub fn load(&self, status: u8) -> Result<()> {
+        self.restore(status)
+    }
+
     /// Shifts `status` into the A6275 and latches it to the relay outputs.
     ///
     /// If `verify` is [`Verify::Enabled`], reads back the shift register and returns
-    /// [`Error::VerificationFailed`] if it doesn't match.
+    /// [`Error::VerificationFailed`] if it doesn't match. With [`Verify::Retry`],
+    /// writes and reads back again on a mismatch, and once the attempts run out
+    /// runs the self-test to say which half of the board is at fault.
     pub fn set_status(&self, status: u8, verify: Verify) -> Result<()> {
-        self.shift_out_bits(status)?;
+        let attempts = match verify {
+            Verify::Disabled => return self.latch(status),
+            Verify::Enabled => 1,
+            Verify::Retry { attempts } => attempts.max(1),
+        };
 
-        self.gpio.set_output(LATCH)?;
-        self.gpio.set_output(0)?;
+        let mut reads = Vec::new();
 
-        if verify == Verify::Enabled {
-            // The outputs hold `status` now, so that is what the register has to be
-            // left holding, on a mismatch as much as on a match. Putting the
-            // read-back value there instead would leave the register carrying a
-            // figure that came from the very path the mismatch implicates, and the
-            // next read would report relays nobody asked for.
-            let read = self.restoring(status, || self.read_shift_register())?;
-
-            if read != status {
-                return Err(Error::VerificationFailed {
-                    expected: Relays::from_bits(status),
-                    actual: Relays::from_bits(read),
-                });
+        for _ in 0..attempts {
+            let read = self.latch_verified(status)?;
+
+            if read == status {
+                return Ok(());
             }
+
+            reads.push(Relays::from_bits(read));
+        }
+
+        let expected = Relays::from_bits(status);
+
+        if verify == Verify::Enabled {
+            return Err(Error::VerificationFailed {
+                expected,
+                actual: reads[0],
+            });
+        }
+
+        // Every attempt left the register holding `status`, so the self-test finds
+        // it there, inverts it and puts it back: no relay moves and nothing is
+        // lost, whichever way it comes out.
+        let suspect = match self.self_test() {
+            Ok(_) => Suspect::WritePath,
+            Err(Error::SelfTestFailed) => Suspect::ReadPath,
+            Err(e) => return Err(e),
+        };
+
+        Err(Error::VerificationRetriesExhausted {
+            expected,
+            actual: reads,
+            suspect,
+        })
+    }
+
+    /// Shifts `status` in and latches it, without reading anything back.
+    fn latch(&self, status: u8) -> Result<()> {
+        if self.drive == Drive::Stream {
+            let mut steps = Vec::new();
+
+            push_latch(&mut steps, status);
+
+            return self.gpio.stream(&steps).map(drop);
+        }
+
+        self.write(status)?;
+
+        self.gpio.set_output(LATCH)?;
+        self.gpio.set_output(0)
+    }
+
+    /// Shifts `status` in, latches it and returns what the register reads back,
+    /// leaving it holding `status` again.
+    ///
+    /// The outputs hold `status` by the time of the read, so that is what the
+    /// register has to be left holding, on a mismatch as much as on a match.
+    /// Putting the read-back value there instead would leave the register carrying
+    /// a figure that came from the very path the mismatch implicates, and the next
+    /// read would report relays nobody asked for.
+    fn latch_verified(&self, status: u8) -> Result<u8> {
+        if self.drive == Drive::Stream {
+            return self.stream_verified(status);
         }
 
-        Ok(())
+        self.latch(status)?;
+        self.read_restoring(status)
+    }
+
+    /// [`latch_verified`](A6275::latch_verified) as a single stream: the shift, the
+    /// latch and the verifying read that puts `status` back, in one transfer out
+    /// and one back for each packet of it that samples.
+    ///
+    /// A stream that fails may have stopped anywhere in there, so nothing is
+    /// vouched for — not the register, not the outputs — and the failure is
+    /// reported as the register being lost, whose remedy, writing a known state,
+    /// is the right one for both.
+    fn stream_verified(&self, status: u8) -> Result<u8> {
+        let mut steps = Vec::new();
+
+        push_latch(&mut steps, status);
+        push_shift(&mut steps, status, true);
+
+        Ok(fold(self.gpio.stream(&steps).map_err(Error::out_of_sync)?))
     }
 
     /// Reads the shift register and puts back what reading it consumed.
     pub fn status(&self) -> Result<u8> {
-        let status = self.read_shift_register().map_err(Error::out_of_sync)?;
+        let status = self.read().map_err(Error::out_of_sync)?;
 
         self.restore(status)?;
 
@@ -133,13 +320,13 @@ impl<T: Gpio> A6275<T> {
     /// contents back on every path out, so that neither a failed check nor a failed
     /// transfer leaves the register disagreeing with the latched outputs.
     pub fn self_test(&self) -> Result<u8> {
-        let status = self.read_shift_register().map_err(Error::out_of_sync)?;
+        let status = self.read().map_err(Error::out_of_sync)?;
         let test_status = !status;
 
-        let read = self.restoring(status, || {
-            self.shift_out_bits(test_status)?;
-            self.read_shift_register()
-        })?;
+        self.write(test_status)
+            .or_else(|e| self.restore(status).and(Err(e)))?;
+
+        let read = self.read_restoring(status)?;
 
         if read != test_status {
             return Err(Error::SelfTestFailed);
@@ -155,59 +342,8 @@ mod tests {
     use std::error::Error as _;
 
     use super::*;
-
-    /// The register bit presented on the A6275 serial output.
-    const MSB: u8 = 0b1000_0000;
-
-    /// A simulated A6275 driven through the same GPIO lines as the real one.
-    ///
-    /// The register shifts left on each rising CLK edge, taking in the DATA line,
-    /// and presents its most significant bit on the serial output (D7). A rising
-    /// LATCH edge copies the register to the relay outputs.
-    #[derive(Default)]
-    struct FakeA6275 {
-        lines: Cell<u8>,
-        register: Cell<u8>,
-        outputs: Cell<u8>,
-    }
-
-    impl Gpio for FakeA6275 {
-        fn set_output(&self, data: u8) -> Result<()> {
-            let previous = self.lines.replace(data);
-            let rising = |pin: u8| previous & pin == 0 && data & pin != 0;
-
-            if rising(CLK) {
-                let bit = u8::from(data & DATA != 0);
-                self.register.set(self.register.get() << 1 | bit);
-            }
-
-            if rising(LATCH) {
-                self.outputs.set(self.register.get());
-            }
-
-            Ok(())
-        }
-
-        /// Drives the sampling one state at a time, exactly as the pin states a
-        /// UIO stream runs would: batching it on the wire must not change what the
-        /// device sees.
-        fn sample_clocked(&self, clock: u8) -> Result<[u8; SAMPLES]> {
-            let mut samples = [0; SAMPLES];
-
-            self.set_output(0)?;
-
-            for sample in &mut samples {
-                let serial_out = self.register.get() & MSB != 0;
-
-                *sample = if serial_out { READ } else { 0 };
-
-                self.set_output(clock)?;
-                self.set_output(0)?;
-            }
-
-            Ok(samples)
-        }
-    }
+    use crate::ch341a::step_message;
+    use crate::sim::FakeA6275;
 
     /// A device that never drives its serial output, so every read-back mismatches.
     struct StuckLow;
@@ -220,6 +356,20 @@ mod tests {
         fn sample_clocked(&self, _clock: u8) -> Result<[u8; SAMPLES]> {
             Ok([0; SAMPLES])
         }
+
+        fn exchange(&self, _data: u8) -> Result<u8> {
+            Ok(0)
+        }
+
+        fn stream(&self, steps: &[Step]) -> Result<Vec<u8>> {
+            Ok(vec![
+                0;
+                steps
+                    .iter()
+                    .filter(|&&step| step == Step::Sample)
+                    .count()
+            ])
+        }
     }
 
     /// A board that drops the most significant bit of every read — the first bit
@@ -242,10 +392,71 @@ mod tests {
 
             Ok(samples)
         }
+
+        fn exchange(&self, data: u8) -> Result<u8> {
+            Ok(self.0.exchange(data)? & 0b0111_1111)
+        }
+
+        fn stream(&self, steps: &[Step]) -> Result<Vec<u8>> {
+            let mut samples = self.0.stream(steps)?;
+
+            if let Some(first) = samples.first_mut() {
+                *first &= !READ;
+            }
+
+            Ok(samples)
+        }
+    }
+
+    /// A board whose first `misreads` clocked reads come back as zeros, after
+    /// which it answers truly: a marginal connection rather than a dead one.
+    struct Marginal {
+        gpio: FakeA6275,
+        misreads: Cell<usize>,
+    }
+
+    impl Gpio for Marginal {
+        fn set_output(&self, data: u8) -> Result<()> {
+            self.gpio.set_output(data)
+        }
+
+        fn sample_clocked(&self, clock: u8) -> Result<[u8; SAMPLES]> {
+            let samples = self.gpio.sample_clocked(clock)?;
+
+            if self.misreads.get() == 0 {
+                return Ok(samples);
+            }
+
+            self.misreads.set(self.misreads.get() - 1);
+
+            Ok([0; SAMPLES])
+        }
+    }
+
+    /// A board whose latch pulse upsets the register: the outputs take the value,
+    /// then the register drops to zeros. Everything short of a latch round trips,
+    /// so only a write that latches shows the fault.
+    struct NoisyLatch(FakeA6275);
+
+    impl Gpio for NoisyLatch {
+        fn set_output(&self, data: u8) -> Result<()> {
+            self.0.set_output(data)?;
+
+            if data & LATCH != 0 {
+                self.0.register.set(0);
+            }
+
+            Ok(())
+        }
+
+        fn sample_clocked(&self, clock: u8) -> Result<[u8; SAMPLES]> {
+            self.0.sample_clocked(clock)
+        }
     }
 
     /// Counts USB transfers on their way to a simulated board: one for a line
-    /// change, and one out plus one back for a clocked read.
+    /// change, one out plus one back for a clocked read or an exchange, and one
+    /// out for a stream, plus one back for each of its packets that samples.
     struct Counting {
         gpio: FakeA6275,
         transfers: Cell<usize>,
@@ -263,15 +474,29 @@ mod tests {
 
             self.gpio.sample_clocked(clock)
         }
+
+        fn exchange(&self, data: u8) -> Result<u8> {
+            self.transfers.set(self.transfers.get() + 2);
+
+            self.gpio.exchange(data)
+        }
+
+        fn stream(&self, steps: &[Step]) -> Result<Vec<u8>> {
+            let (_, answers) = step_message(steps);
+
+            self.transfers.set(self.transfers.get() + 1 + answers.len());
+
+            self.gpio.stream(steps)
+        }
     }
 
     /// A board whose transport gives out part way through, so that the error paths
     /// can be driven without a flaky board to hand.
     ///
-    /// The clocked read hands its failure back *after* the simulated register has
-    /// been clocked out, which is the case that matters: on the wire the stream is
-    /// what clocks the register, so a response that never arrives has already cost
-    /// the contents.
+    /// The clocked read, and the exchange, hand their failure back *after* the
+    /// simulated register has been clocked, which is the case that matters: on the
+    /// wire the stream is what clocks the register, so a response that never
+    /// arrives has already cost the contents.
     struct FailingAfter {
         gpio: FakeA6275,
         reads: Cell<usize>,
@@ -305,6 +530,30 @@ mod tests {
 
             Ok(samples)
         }
+
+        fn exchange(&self, data: u8) -> Result<u8> {
+            let answer = self.gpio.exchange(data)?;
+
+            Self::spend(&self.reads)?;
+
+            Ok(answer)
+        }
+
+        /// A stream that samples fails as a clocked read does, after it has run;
+        /// one that does not is a write, and fails before it reaches the board.
+        fn stream(&self, steps: &[Step]) -> Result<Vec<u8>> {
+            if !steps.contains(&Step::Sample) {
+                Self::spend(&self.writes)?;
+
+                return self.gpio.stream(steps);
+            }
+
+            let samples = self.gpio.stream(steps)?;
+
+            Self::spend(&self.reads)?;
+
+            Ok(samples)
+        }
     }
 
     fn fake() -> A6275<FakeA6275> {
@@ -332,10 +581,19 @@ mod tests {
 
     /// Runs `call` against a counting board and returns the transfers it cost.
     fn transfers<R>(call: impl FnOnce(&A6275<Counting>) -> Result<R>) -> usize {
+        transfers_driven(Drive::Gpio, call)
+    }
+
+    /// As [`transfers`], on a board driven through `drive`.
+    fn transfers_driven<R>(
+        drive: Drive,
+        call: impl FnOnce(&A6275<Counting>) -> Result<R>,
+    ) -> usize {
         let board = A6275::new(Counting {
             gpio: FakeA6275::default(),
             transfers: Cell::new(0),
-        });
+        })
+        .with_drive(drive);
 
         call(&board).unwrap();
 
@@ -575,6 +833,140 @@ mod tests {
         assert!(board.self_test().is_err());
     }
 
+    #[test]
+    fn an_exchange_hands_back_the_register_it_replaces() {
+        let board = fake();
+        board.shift_out_bits(0b1100_1001).unwrap();
+
+        assert_eq!(board.gpio.exchange(0b0011_0110).unwrap(), 0b1100_1001);
+        assert_eq!(board.gpio.register.get(), 0b0011_0110);
+        assert_eq!(board.gpio.outputs.get(), 0);
+    }
+
+    #[test]
+    fn every_drive_leaves_the_board_in_the_same_state() {
+        for drive in [Drive::Gpio, Drive::Spi, Drive::Stream] {
+            let board = fake().with_drive(drive);
+
+            board.set_status(0b1100_1001, Verify::Enabled).unwrap();
+
+            assert_eq!(board.status().unwrap(), 0b1100_1001, "{drive:?}");
+            assert_eq!(board.self_test().unwrap(), 0b1100_1001, "{drive:?}");
+            assert_eq!(board.gpio.register.get(), 0b1100_1001, "{drive:?}");
+            assert_eq!(board.gpio.outputs.get(), 0b1100_1001, "{drive:?}");
+        }
+    }
+
+    #[test]
+    fn a_failed_verification_over_spi_leaves_the_latched_value_in_the_register() {
+        let board = flaky().with_drive(Drive::Spi);
+
+        let err = board.set_status(0b1000_0001, Verify::Enabled).unwrap_err();
+
+        assert!(matches!(
+            err,
+            Error::VerificationFailed { actual, .. } if actual == Relays::from_bits(0b0000_0001)
+        ));
+        assert_eq!(board.gpio.0.register.get(), 0b1000_0001);
+        assert!(flaky().with_drive(Drive::Spi).self_test().is_err());
+    }
+
+    #[test]
+    fn an_exchange_that_never_comes_back_reports_a_lost_register() {
+        // The write spends the only exchange; the verifying one clocks the register
+        // and never answers, and the restore behind it has no read left either.
+        let board = failing(0b0011_0101, 1, usize::MAX).with_drive(Drive::Spi);
+
+        let err = board.set_status(0b1100_1001, Verify::Enabled).unwrap_err();
+
+        // An exchange may have clocked any part of the byte before it failed, so
+        // nothing it leaves behind is vouched for, whatever the model holds.
+        assert!(matches!(err, Error::RegisterOutOfSync { .. }));
+        assert_eq!(board.gpio.gpio.outputs.get(), 0b1100_1001);
+    }
+
+    #[test]
+    fn a_transport_without_a_drive_reports_it_unsupported() {
+        struct LinesOnly;
+
+        impl Gpio for LinesOnly {
+            fn set_output(&self, _data: u8) -> Result<()> {
+                Ok(())
+            }
+
+            fn sample_clocked(&self, _clock: u8) -> Result<[u8; SAMPLES]> {
+                Ok([0; SAMPLES])
+            }
+        }
+
+        for drive in [Drive::Spi, Drive::Stream] {
+            let board = A6275::new(LinesOnly).with_drive(drive);
+
+            assert!(matches!(
+                board.set_status(0, Verify::Disabled),
+                Err(Error::Usb(rusb::Error::NotSupported))
+            ));
+        }
+    }
+
+    #[test]
+    fn a_streamed_write_holds_every_line_change_before_the_next() {
+        let mut steps = Vec::new();
+
+        push_shift(&mut steps, 0b1000_0000, false);
+
+        // Three line changes a bit and the low state after them, each one waited
+        // on, so no CLK edge follows the DATA change it is meant to take in.
+        assert_eq!(steps.len(), (8 * 3 + 1) * 2);
+        assert_eq!(
+            steps[..6],
+            [
+                Step::Output(DATA),
+                Step::Wait(SETTLE_MICROS),
+                Step::Output(CLK | DATA),
+                Step::Wait(SETTLE_MICROS),
+                Step::Output(DATA),
+                Step::Wait(SETTLE_MICROS),
+            ]
+        );
+        assert!(
+            steps
+                .chunks(2)
+                .all(|pair| pair[1] == Step::Wait(SETTLE_MICROS))
+        );
+    }
+
+    #[test]
+    fn a_failed_verification_in_a_stream_leaves_the_latched_value_in_the_register() {
+        let board = flaky().with_drive(Drive::Stream);
+
+        let err = board.set_status(0b1000_0001, Verify::Enabled).unwrap_err();
+
+        assert!(matches!(
+            err,
+            Error::VerificationFailed { actual, .. } if actual == Relays::from_bits(0b0000_0001)
+        ));
+        assert_eq!(board.gpio.0.register.get(), 0b1000_0001);
+        assert_eq!(board.gpio.0.outputs.get(), 0b1000_0001);
+    }
+
+    #[test]
+    fn a_verifying_stream_that_never_comes_back_reports_a_lost_register() {
+        let board = failing(0b0011_0101, 0, usize::MAX).with_drive(Drive::Stream);
+
+        let err = board.set_status(0b1100_1001, Verify::Enabled).unwrap_err();
+
+        assert!(matches!(err, Error::RegisterOutOfSync { .. }));
+
+        // Without the read-back the same stream is a plain write, and a failed
+        // write is a plain transport error, as it is when bit-banging.
+        let board = failing(0b0011_0101, 0, 0).with_drive(Drive::Stream);
+        let err = board.set_status(0b1100_1001, Verify::Disabled).unwrap_err();
+
+        assert!(matches!(err, Error::Usb(_)));
+        assert_eq!(board.gpio.gpio.outputs.get(), 0b0011_0101);
+    }
+
     #[test]
     fn the_protocol_costs_the_transfers_it_should() {
         // The point of the clocked read: 33 transfers as one write and one read per
@@ -583,9 +975,10 @@ mod tests {
         assert_eq!(transfers(|board| board.read_shift_register()), 2);
 
         // Writing is still one transfer per line change: 8 bits × 3 states, plus the
-        // low state either side. It stays that way deliberately: the CH341A emits
+        // low state either side. It stays that way by default: the CH341A emits
         // stream states faster than the DATA line settles, so a batched write clocks
-        // in the previous bit.
+        // in the previous bit unless it waits between them, which is what
+        // `Drive::Stream` does and what nobody has yet measured on a board.
         assert_eq!(transfers(|board| board.shift_out_bits(0b1010_1010)), 26);
 
         // Plus the two states that latch the outputs.
@@ -602,4 +995,135 @@ mod tests {
         // worth those 28 transfers — about 1.1 ms — on every call that only reads.
         assert_eq!(transfers(|board| board.self_test()), 56);
     }
+
+    #[test]
+    fn the_spi_drive_costs_the_transfers_it_should() {
+        // One exchange is one transfer out and one back, and it writes the whole
+        // register while reading what it replaces.
+        assert_eq!(
+            transfers_driven(Drive::Spi, |board| board.write(0b1010_1010)),
+            2
+        );
+
+        // Plus the same two line changes that latch the outputs.
+        let write = |board: &A6275<Counting>| board.set_status(0, Verify::Disabled);
+        assert_eq!(transfers_driven(Drive::Spi, write), 4);
+
+        // The read-back sends the value it expects to find, so reading and putting
+        // back are one exchange rather than a read and a restore.
+        let verified = |board: &A6275<Counting>| board.set_status(0, Verify::Enabled);
+        assert_eq!(transfers_driven(Drive::Spi, verified), 6);
+
+        // A plain read does not know what to send, so it reads with zeros and
+        // restores with a second exchange.
+        assert_eq!(transfers_driven(Drive::Spi, |board| board.status()), 4);
+
+        // Read, write the test pattern, read it back while putting the original in.
+        assert_eq!(transfers_driven(Drive::Spi, |board| board.self_test()), 6);
+    }
+
+    #[test]
+    fn the_stream_drive_costs_the_transfers_it_should() {
+        // Every line change of the write and the latch, one transfer out.
+        let write = |board: &A6275<Counting>| board.set_status(0, Verify::Disabled);
+        assert_eq!(transfers_driven(Drive::Stream, write), 1);
+
+        // The read-back rides the same stream, so verifying adds only the transfers
+        // that bring its samples home: one for each of the three packets they
+        // fall in.
+        let verified = |board: &A6275<Counting>| board.set_status(0, Verify::Enabled);
+        assert_eq!(transfers_driven(Drive::Stream, verified), 4);
+
+        // The clocked read, and the restore as a stream of its own.
+        assert_eq!(transfers_driven(Drive::Stream, |board| board.status()), 3);
+
+        // Read, write the test pattern, then read it back while putting the
+        // original in, which samples in two packets.
+        assert_eq!(
+            transfers_driven(Drive::Stream, |board| board.self_test()),
+            6
+        );
+    }
+
+    #[test]
+    fn a_retried_verification_gets_past_a_misread() {
+        let board = A6275::new(Marginal {
+            gpio: FakeA6275::default(),
+            misreads: Cell::new(1),
+        });
+
+        board
+            .set_status(0b0100_1001, Verify::Retry { attempts: 2 })
+            .unwrap();
+
+        assert_eq!(board.gpio.gpio.outputs.get(), 0b0100_1001);
+        assert_eq!(board.gpio.gpio.register.get(), 0b0100_1001);
+
+        // Where the first read is good, retrying costs nothing over verifying.
+        assert_eq!(
+            transfers(|board| board.set_status(0b0100_1001, Verify::Retry { attempts: 3 })),
+            transfers(|board| board.set_status(0b0100_1001, Verify::Enabled))
+        );
+    }
+
+    #[test]
+    fn exhausted_retries_report_every_read_back_and_blame_the_read_path() {
+        let err = A6275::new(StuckLow)
+            .set_status(0b1010_0110, Verify::Retry { attempts: 3 })
+            .unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "verification failed 3 times: expected 2 3 6 8, read back none, none, none; \
+             the read path is suspect"
+        );
+
+        let Error::VerificationRetriesExhausted {
+            expected,
+            actual,
+            suspect,
+        } = err
+        else {
+            panic!("expected exhausted retries, got {err:?}");
+        };
+
+        assert_eq!(expected, Relays::from_bits(0b1010_0110));
+        assert_eq!(actual, [Relays::NONE; 3]);
+        assert_eq!(suspect, Suspect::ReadPath);
+    }
+
+    #[test]
+    fn exhausted_retries_on_a_board_that_passes_the_self_test_blame_the_write_path() {
+        let board = A6275::new(NoisyLatch(FakeA6275::default()));
+
+        let err = board
+            .set_status(0b0001_1000, Verify::Retry { attempts: 2 })
+            .unwrap_err();
+
+        assert!(matches!(
+            err,
+            Error::VerificationRetriesExhausted {
+                suspect: Suspect::WritePath,
+                ..
+            }
+        ));
+        assert_eq!(board.gpio.0.outputs.get(), 0b0001_1000);
+    }
+
+    #[test]
+    fn a_single_verification_still_reports_a_single_mismatch() {
+        let board = A6275::new(Marginal {
+            gpio: FakeA6275::default(),
+            misreads: Cell::new(1),
+        });
+
+        assert!(matches!(
+            board.set_status(0b0100_1001, Verify::Enabled),
+            Err(Error::VerificationFailed { .. })
+        ));
+        assert!(matches!(
+            board.set_status(0b0100_1001, Verify::Retry { attempts: 0 }),
+            Ok(())
+        ));
+    }
 }
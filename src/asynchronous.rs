@@ -0,0 +1,307 @@
+//! Driving boards from tokio without blocking its workers.
+//!
+//! Every [`Board`] call blocks on bulk transfers, each allowed up to a second, so
+//! a tokio application would otherwise wrap each one in `spawn_blocking` itself.
+//! [`AsyncBoard`] does that, and does one thing more: calls on the same board
+//! queue behind each other rather than failing with [`Error::Busy`](crate::Error::Busy),
+//! so tasks sharing a board never have to retry against one another.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::panic;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use tokio::sync::Mutex as Queue;
+use tokio::task;
+
+use crate::errors::Result;
+use crate::find::Path;
+use crate::relays::Relays;
+use crate::{Board, Usb, Verify};
+
+/// Runs `op` on the blocking pool, handing back its panic if it had one.
+async fn blocking<R: Send + 'static>(op: impl FnOnce() -> R + Send + 'static) -> R {
+    match task::spawn_blocking(op).await {
+        Ok(result) => result,
+        Err(e) => panic::resume_unwind(e.into_panic()),
+    }
+}
+
+/// One queue per board, by where the board is, shared by every [`AsyncBoard`] of
+/// an [`AsyncUsb`].
+///
+/// Keyed by path rather than by how a board was named, so `board(Some(3))` and the
+/// enumerated board on port 3 queue together instead of finding each other busy.
+#[derive(Default)]
+struct Queues(Mutex<BTreeMap<Path, Arc<Queue<()>>>>);
+
+impl Queues {
+    /// The queue for the board at `path`.
+    fn get(&self, path: Path) -> Arc<Queue<()>> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(path)
+            .or_default()
+            .clone()
+    }
+}
+
+/// A [`Usb`] context for tokio applications.
+///
+/// Cheap to clone, and every clone shares the same queues: boards from any of them
+/// take turns. Boards driven through a plain [`Usb`], or by another application,
+/// still meet each other as [`Error::Busy`](crate::Error::Busy).
+///
+/// # Example
+///
+/// ```no_run
+/// # async fn run() -> arb::Result<()> {
+/// use arb::{AsyncUsb, Relay, Verify};
+///
+/// let usb = AsyncUsb::new().await?;
+/// let board = usb.board(None);
+///
+/// board.set_relays(Relay::One | Relay::Three, Verify::Enabled).await?;
+/// println!("active: {}", board.relays().await?);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct AsyncUsb {
+    usb: Usb,
+    queues: Arc<Queues>,
+}
+
+impl AsyncUsb {
+    /// Initialises libusb, on the blocking pool.
+    ///
+    /// # Errors
+    ///
+    /// * [`Error::Usb`](crate::Error::Usb) — libusb could not be initialised
+    pub async fn new() -> Result<Self> {
+        Ok(blocking(Usb::new).await?.into())
+    }
+
+    /// Returns the board on `port`, or the only attached board if `None`, as
+    /// [`Usb::board`] does.
+    pub fn board(&self, port: Option<u8>) -> AsyncBoard {
+        self.wrap(self.usb.board(port))
+    }
+
+    /// Returns every attached relay board, as [`Usb::boards`] does.
+    ///
+    /// # Errors
+    ///
+    /// * [`Error::Usb`](crate::Error::Usb) — the USB device list could not be read
+    pub async fn boards(&self) -> Result<Vec<AsyncBoard>> {
+        let usb = self.usb.clone();
+        let boards = blocking(move || usb.boards()).await?;
+
+        Ok(boards.into_iter().map(|board| self.wrap(board)).collect())
+    }
+
+    /// Returns the blocking context underneath, for anything this does not wrap.
+    pub fn usb(&self) -> &Usb {
+        &self.usb
+    }
+
+    fn wrap(&self, board: Board) -> AsyncBoard {
+        AsyncBoard {
+            board,
+            queues: self.queues.clone(),
+        }
+    }
+}
+
+/// Wraps a context built any other way — with a transport, simulated, or driven
+/// through another [`Drive`](crate::Drive).
+impl From<Usb> for AsyncUsb {
+    fn from(usb: Usb) -> Self {
+        Self {
+            usb,
+            queues: Arc::default(),
+        }
+    }
+}
+
+impl fmt::Debug for AsyncUsb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AsyncUsb").field(&self.usb).finish()
+    }
+}
+
+/// A [`Board`] whose calls run on tokio's blocking pool, one at a time.
+///
+/// Each call waits its turn behind the others on the same board from the same
+/// [`AsyncUsb`], then claims the board as the blocking call does. A board named by
+/// port, or by nothing, is located first to find its queue, which costs it one
+/// more enumeration per call.
+///
+/// Dropping a call's future gives up its place in the queue, but not a call that
+/// has already started: that one runs to the end on the blocking pool, holding
+/// its turn, because a transfer cut short would leave the register in doubt.
+#[derive(Clone)]
+pub struct AsyncBoard {
+    board: Board,
+    queues: Arc<Queues>,
+}
+
+impl AsyncBoard {
+    /// Returns the blocking board underneath, for anything this does not wrap.
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// Waits for this board's turn, then runs `op` on it.
+    async fn run<R: Send + 'static>(
+        &self,
+        op: impl FnOnce(&Board) -> Result<R> + Send + 'static,
+    ) -> Result<R> {
+        let path = match self.board.path() {
+            Some(path) => path.clone(),
+            None => {
+                let board = self.board.clone();
+
+                blocking(move || board.locate()).await?
+            }
+        };
+
+        let turn = self.queues.get(path).lock_owned().await;
+        let board = self.board.clone();
+
+        // The turn goes with the call, so that it is only given up once the call is
+        // over, whatever happens to this future meanwhile.
+        blocking(move || {
+            let _turn = turn;
+
+            op(&board)
+        })
+        .await
+    }
+
+    /// As [`Board::relays`].
+    pub async fn relays(&self) -> Result<Relays> {
+        self.run(Board::relays).await
+    }
+
+    /// As [`Board::set_relays`].
+    pub async fn set_relays(&self, relays: Relays, verify: Verify) -> Result<()> {
+        self.run(move |board| board.set_relays(relays, verify))
+            .await
+    }
+
+    /// As [`Board::self_test`].
+    pub async fn self_test(&self) -> Result<Relays> {
+        self.run(Board::self_test).await
+    }
+
+    /// As [`Board::reset_device`].
+    pub async fn reset_device(&self) -> Result<()> {
+        self.run(Board::reset_device).await
+    }
+}
+
+impl fmt::Debug for AsyncBoard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("AsyncBoard").field(&self.board).finish()
+    }
+}
+
+/// As [`Board`]'s.
+impl fmt::Display for AsyncBoard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.board.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{Error, Relay};
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn tasks_sharing_a_board_queue_instead_of_finding_it_busy() {
+        let (usb, sims) = Usb::simulated(1);
+        let usb = AsyncUsb::from(usb);
+
+        block_on(async {
+            let enumerated = usb.boards().await.unwrap().remove(0);
+            let (claimed, wait) = mpsc::channel();
+
+            // Holds the board well past the moment the second call asks for it.
+            let holder = tokio::spawn(async move {
+                enumerated
+                    .run(move |board| {
+                        let session = board.session()?;
+
+                        claimed.send(()).unwrap();
+                        thread::sleep(Duration::from_millis(50));
+
+                        session.set_relays(Relay::Two.into(), Verify::Enabled)
+                    })
+                    .await
+            });
+
+            blocking(move || wait.recv()).await.unwrap();
+
+            // Named another way, and still behind the holder rather than busy.
+            let read = usb.board(Some(1)).relays().await;
+
+            holder.await.unwrap().unwrap();
+            assert_eq!(read.unwrap(), Relay::Two.into());
+        });
+
+        assert_eq!(sims[0].outputs(), Relay::Two.into());
+    }
+
+    #[test]
+    fn an_async_board_reports_what_the_blocking_one_does() {
+        let (usb, sims) = Usb::simulated(2);
+        let usb = AsyncUsb::from(usb);
+
+        block_on(async {
+            let boards = usb.boards().await.unwrap();
+
+            boards[1]
+                .set_relays(Relay::Four | Relay::Six, Verify::Enabled)
+                .await
+                .unwrap();
+
+            assert_eq!(boards[1].relays().await.unwrap(), Relay::Four | Relay::Six);
+            assert_eq!(
+                boards[1].self_test().await.unwrap(),
+                Relay::Four | Relay::Six
+            );
+            assert!(boards[0].reset_device().await.is_ok());
+            assert!(matches!(
+                usb.board(None).relays().await,
+                Err(Error::MultipleFound)
+            ));
+        });
+
+        assert_eq!(sims[1].outputs(), Relay::Four | Relay::Six);
+        assert_eq!(boards_line(&usb), "port 1 (1-1), port 2 (1-2)");
+    }
+
+    fn boards_line(usb: &AsyncUsb) -> String {
+        block_on(usb.boards())
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
@@ -0,0 +1,638 @@
+//! `arb-mqtt`: a bridge between the relay boards and an MQTT broker.
+//!
+//! Each board is named by its path, the `1-1.3` spelling `arb --list` and `lsusb -t`
+//! use, under a prefix that defaults to `arb`:
+//!
+//! | Topic                           | Direction | Payload                          |
+//! | ------------------------------- | --------- | -------------------------------- |
+//! | `arb/status`                    | published | `online` or `offline`, retained  |
+//! | `arb/{path}/relay/{relay}`      | published | `ON` or `OFF`, retained          |
+//! | `arb/{path}/error`              | published | the error, as `arb` prints it    |
+//! | `arb/{path}/relays/set`         | command   | every relay to activate: `1 3`   |
+//! | `arb/{path}/relay/{relay}/set`  | command   | `ON`, `OFF` or `TOGGLE`          |
+//!
+//! The boards are read every `--interval`, so relays switched by anything other
+//! than the bridge are published too, and after every command. Only the relays
+//! that changed are published. A failure that says something about a board —
+//! verification, a register out of sync, a command that made no sense — goes to
+//! its error topic rather than only to stderr, because whoever sent the command is
+//! listening there and not here. [`arb::Error::Busy`] while polling is the one
+//! exception: another application had the board, and the next poll will read it.
+
+use clap::Parser;
+use rumqttc::{Client, Event, LastWill, MqttOptions, Packet, QoS};
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use arb::{Board, Relay, Relays, Usb, Verify};
+
+#[derive(Parser, Debug)]
+#[command(name = "abacom-relay-board MQTT bridge (arb-mqtt)")]
+struct Args {
+    /// The broker's host name
+    #[arg(long, default_value = "localhost")]
+    host: String,
+
+    /// The broker's port
+    #[arg(long, default_value_t = 1883)]
+    port: u16,
+
+    /// The client ID to connect with
+    #[arg(long, default_value = "arb-mqtt")]
+    client_id: String,
+
+    /// The topic every other topic hangs off
+    #[arg(long, default_value = "arb")]
+    prefix: String,
+
+    // Zero would make every poll due at once, and the commands queued between
+    // polls would never be carried out.
+    /// How often to read the boards, in seconds
+    #[arg(long, value_name = "SECONDS", default_value_t = 5, value_parser = clap::value_parser!(u64).range(1..))]
+    interval: u64,
+
+    /// Disables the verification after activating relays
+    #[arg(short, long)]
+    disable_verification: bool,
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("arb-mqtt: {e}");
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+
+    let verify = if args.disable_verification {
+        Verify::Disabled
+    } else {
+        Verify::Enabled
+    };
+
+    let bridge = Bridge::new(Usb::new()?, args.prefix.clone(), verify);
+    let status = bridge.status_topic();
+
+    let mut options = MqttOptions::new(&args.client_id, &args.host, args.port);
+    options.set_keep_alive(Duration::from_secs(30));
+    options.set_last_will(LastWill::new(&status, "offline", QoS::AtLeastOnce, true));
+
+    let (client, mut connection) = Client::new(options, 64);
+    let subscriptions = bridge.subscriptions();
+
+    // One thread owns the boards and does every transfer, so a command never finds
+    // a board claimed by the bridge's own poll and fails with `Busy`, and a board
+    // that takes its full timeout to answer never holds up the thread below that
+    // drains the connection, keep-alives included.
+    let (jobs, queued) = mpsc::channel();
+    let publishing = client.clone();
+    let interval = Duration::from_secs(args.interval);
+
+    thread::spawn(move || work(bridge, &queued, &publishing, interval));
+
+    for event in connection.iter() {
+        match event {
+            Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                eprintln!("arb-mqtt: connected to {}:{}", args.host, args.port);
+
+                // A clean session forgets subscriptions and the broker may have
+                // restarted without the retained state, so every connection
+                // subscribes afresh and the next poll republishes everything.
+                jobs.send(Job::Connected)?;
+
+                for topic in &subscriptions {
+                    if let Err(e) = client.try_subscribe(topic, QoS::AtLeastOnce) {
+                        eprintln!("arb-mqtt: could not subscribe: {e}");
+                    }
+                }
+
+                send(
+                    &client,
+                    vec![Message::retained(status.clone(), "online".into())],
+                );
+            }
+
+            Ok(Event::Incoming(Packet::Publish(publish))) => {
+                jobs.send(Job::Command {
+                    topic: publish.topic,
+                    payload: publish.payload.to_vec(),
+                })?;
+            }
+
+            Ok(_) => {}
+
+            // rumqttc reconnects on the next iteration. The pause keeps a broker
+            // that is down from turning this into a busy loop.
+            Err(e) => {
+                eprintln!("arb-mqtt: {e}");
+                thread::sleep(Duration::from_secs(1));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// What the thread that drains the connection hands to the one that owns the
+/// boards.
+#[derive(Debug)]
+enum Job {
+    /// A connection was made: everything is published again at the next poll.
+    Connected,
+    /// A message arrived on a command topic.
+    Command { topic: String, payload: Vec<u8> },
+}
+
+/// Polls the boards every `interval` and carries out the jobs `queued` between
+/// polls, until the thread that drains the connection is gone.
+///
+/// A poll falls due before anything queued is looked at, so a steady stream of
+/// commands never starves it.
+fn work(mut bridge: Bridge, queued: &Receiver<Job>, client: &Client, interval: Duration) {
+    let mut due = Instant::now();
+
+    loop {
+        let left = due.saturating_duration_since(Instant::now());
+
+        let messages = if left.is_zero() {
+            due = Instant::now() + interval;
+
+            bridge.poll()
+        } else {
+            match queued.recv_timeout(left) {
+                Ok(Job::Connected) => {
+                    bridge.forget();
+
+                    continue;
+                }
+                Ok(Job::Command { topic, payload }) => bridge.command(&topic, &payload),
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => return,
+            }
+        };
+
+        for message in messages {
+            // Blocking is fine here: this thread is not the one that drains the
+            // queue.
+            if let Err(e) = client.publish(
+                message.topic,
+                QoS::AtLeastOnce,
+                message.retain,
+                message.payload,
+            ) {
+                eprintln!("arb-mqtt: could not publish: {e}");
+            }
+        }
+    }
+}
+
+/// Queues `messages` from the thread that drains the client's queue, which is why
+/// it must not block: a full queue would wait on this very thread forever.
+fn send(client: &Client, messages: Vec<Message>) {
+    for message in messages {
+        let queued = client.try_publish(
+            message.topic,
+            QoS::AtLeastOnce,
+            message.retain,
+            message.payload,
+        );
+
+        if let Err(e) = queued {
+            eprintln!("arb-mqtt: could not queue a message: {e}");
+        }
+    }
+}
+
+/// A message for the broker.
+#[derive(Debug, PartialEq)]
+struct Message {
+    topic: String,
+    payload: String,
+    retain: bool,
+}
+
+impl Message {
+    fn retained(topic: String, payload: String) -> Self {
+        Self {
+            topic,
+            payload,
+            retain: true,
+        }
+    }
+
+    fn transient(topic: String, payload: String) -> Self {
+        Self {
+            topic,
+            payload,
+            retain: false,
+        }
+    }
+}
+
+/// What a command topic asks for.
+#[derive(Debug, PartialEq)]
+enum Command {
+    /// `relays/set`: exactly these relays, every other one off.
+    Set(Relays),
+    /// `relay/{relay}/set ON`.
+    On(Relay),
+    /// `relay/{relay}/set OFF`.
+    Off(Relay),
+    /// `relay/{relay}/set TOGGLE`.
+    Toggle(Relay),
+}
+
+/// The boards, and what has been published about them.
+///
+/// Knows nothing of the broker: it takes topics and payloads in and hands messages
+/// back, which is what lets it be driven without one.
+struct Bridge {
+    usb: Usb,
+    prefix: String,
+    verify: Verify,
+    /// The relays last published for each board, by path.
+    published: BTreeMap<String, Relays>,
+}
+
+impl Bridge {
+    fn new(usb: Usb, prefix: String, verify: Verify) -> Self {
+        Self {
+            usb,
+            prefix,
+            verify,
+            published: BTreeMap::new(),
+        }
+    }
+
+    fn status_topic(&self) -> String {
+        format!("{}/status", self.prefix)
+    }
+
+    fn subscriptions(&self) -> [String; 2] {
+        [
+            format!("{}/+/relays/set", self.prefix),
+            format!("{}/+/relay/+/set", self.prefix),
+        ]
+    }
+
+    /// Forgets what was published, so that the next poll publishes every relay.
+    fn forget(&mut self) {
+        self.published.clear();
+    }
+
+    /// Reads every attached board and returns what changed since it was last
+    /// published.
+    fn poll(&mut self) -> Vec<Message> {
+        let boards = match self.usb.boards() {
+            Ok(boards) => boards,
+            Err(e) => {
+                return vec![Message::transient(
+                    format!("{}/error", self.prefix),
+                    e.to_string(),
+                )];
+            }
+        };
+
+        let mut messages = Vec::new();
+        let mut attached = Vec::new();
+
+        for board in boards {
+            let path = path(&board);
+
+            match board.relays() {
+                Ok(relays) => messages.extend(self.publish(&path, relays)),
+                // Someone else's turn, and not a fault: the next poll reads it.
+                Err(arb::Error::Busy) => {}
+                Err(e) => messages.push(self.error(&path, &e)),
+            }
+
+            attached.push(path);
+        }
+
+        // A board that comes back is published in full, since nothing says its
+        // relays are as they were.
+        self.published.retain(|path, _| attached.contains(path));
+
+        messages
+    }
+
+    /// Carries out the command published on `topic`, returning what it changed or
+    /// why it failed.
+    fn command(&mut self, topic: &str, payload: &[u8]) -> Vec<Message> {
+        let Some(rest) = topic
+            .strip_prefix(&self.prefix)
+            .and_then(|rest| rest.strip_prefix('/'))
+        else {
+            return Vec::new();
+        };
+
+        let Some((path, command)) = rest.split_once('/') else {
+            return Vec::new();
+        };
+
+        let command = match parse(command, payload) {
+            Ok(command) => command,
+            Err(reason) => return vec![Message::transient(self.error_topic(path), reason)],
+        };
+
+        let board = match self.find(path) {
+            Ok(board) => board,
+            Err(e) => return vec![self.error(path, &e)],
+        };
+
+        let verify = self.verify;
+
+        let result = match command {
+            Command::Set(relays) => board.set_relays(relays, verify).map(|()| relays),
+            Command::On(relay) => board.switch_on(relay.into(), verify),
+            Command::Off(relay) => board.switch_off(relay.into(), verify),
+            Command::Toggle(relay) => board.toggle(relay.into(), verify),
+        };
+
+        match result {
+            Ok(relays) => self.publish(path, relays),
+            Err(e) => {
+                // The relays are in doubt after a failed write, so what was
+                // published about them no longer stands: the next poll publishes
+                // whatever the board then reports, in full.
+                self.published.remove(path);
+
+                vec![self.error(path, &e)]
+            }
+        }
+    }
+
+    /// The attached board at `path`.
+    fn find(&self, path: &str) -> arb::Result<Board> {
+        self.usb
+            .boards()?
+            .into_iter()
+            .find(|board| self::path(board) == path)
+            .ok_or(arb::Error::NotFound)
+    }
+
+    /// Records `relays` as the state of the board at `path`, returning a message
+    /// for every relay whose state that changes.
+    fn publish(&mut self, path: &str, relays: Relays) -> Vec<Message> {
+        let previous = self.published.insert(path.to_owned(), relays);
+
+        Relay::ALL
+            .into_iter()
+            .filter(|&relay| {
+                previous.is_none_or(|previous| previous.contains(relay) != relays.contains(relay))
+            })
+            .map(|relay| {
+                let state = if relays.contains(relay) { "ON" } else { "OFF" };
+
+                Message::retained(
+                    format!("{}/{path}/relay/{relay}", self.prefix),
+                    state.into(),
+                )
+            })
+            .collect()
+    }
+
+    fn error(&self, path: &str, error: &arb::Error) -> Message {
+        Message::transient(self.error_topic(path), error.to_string())
+    }
+
+    fn error_topic(&self, path: &str) -> String {
+        format!("{}/{path}/error", self.prefix)
+    }
+}
+
+fn path(board: &Board) -> String {
+    board.path().map(ToString::to_string).unwrap_or_default()
+}
+
+/// Reads a command from the part of its topic after the board, and its payload.
+fn parse(command: &str, payload: &[u8]) -> Result<Command, String> {
+    let payload = std::str::from_utf8(payload)
+        .map_err(|_| "the payload is not UTF-8".to_owned())?
+        .trim();
+
+    if command == "relays/set" {
+        let mut relays = Relays::NONE;
+
+        for number in payload
+            .split([' ', ','])
+            .filter(|number| !number.is_empty())
+        {
+            relays.insert(relay(number)?);
+        }
+
+        return Ok(Command::Set(relays));
+    }
+
+    let Some(relay) = command
+        .strip_prefix("relay/")
+        .and_then(|rest| rest.strip_suffix("/set"))
+    else {
+        return Err(format!("unknown command topic {command:?}"));
+    };
+
+    let relay = self::relay(relay)?;
+
+    match payload.to_ascii_uppercase().as_str() {
+        "ON" => Ok(Command::On(relay)),
+        "OFF" => Ok(Command::Off(relay)),
+        "TOGGLE" => Ok(Command::Toggle(relay)),
+        _ => Err(format!("expected ON, OFF or TOGGLE, got {payload:?}")),
+    }
+}
+
+/// A relay from its number, failing the way the library fails one out of range.
+fn relay(number: &str) -> Result<Relay, String> {
+    let number: u8 = number
+        .parse()
+        .map_err(|_| format!("{number:?} is not a relay number"))?;
+
+    Relay::try_from(number).map_err(|e| e.to_string())
+}
+
+#[cfg(all(test, feature = "sim"))]
+mod tests {
+    use super::*;
+    use arb::sim::Fault;
+
+    fn bridge(boards: usize) -> (Bridge, Vec<arb::sim::SimBoard>) {
+        let (usb, sims) = Usb::simulated(boards);
+
+        (Bridge::new(usb, "arb".into(), Verify::Enabled), sims)
+    }
+
+    fn topics(messages: &[Message]) -> Vec<(&str, &str)> {
+        messages
+            .iter()
+            .map(|message| (message.topic.as_str(), message.payload.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn the_boards_are_read_at_least_a_second_apart() {
+        let interval = |value| {
+            Args::try_parse_from(["arb-mqtt", "--interval", value]).map(|args| args.interval)
+        };
+
+        assert_eq!(interval("1").unwrap(), 1);
+        assert!(interval("0").is_err());
+    }
+
+    #[test]
+    fn the_first_poll_publishes_every_relay_of_every_board() {
+        let (mut bridge, sims) = bridge(2);
+
+        sims[1].latch(Relay::Three.into());
+
+        let messages = bridge.poll();
+
+        assert_eq!(messages.len(), 16);
+        assert!(messages.iter().all(|message| message.retain));
+        assert!(messages.contains(&Message::retained("arb/1-2/relay/3".into(), "ON".into())));
+        assert!(messages.contains(&Message::retained("arb/1-1/relay/3".into(), "OFF".into())));
+    }
+
+    #[test]
+    fn later_polls_publish_only_what_changed() {
+        let (mut bridge, sims) = bridge(1);
+
+        bridge.poll();
+        assert!(bridge.poll().is_empty());
+
+        sims[0].latch(Relay::One | Relay::Eight);
+
+        assert_eq!(
+            topics(&bridge.poll()),
+            [("arb/1-1/relay/1", "ON"), ("arb/1-1/relay/8", "ON")]
+        );
+
+        bridge.forget();
+        assert_eq!(bridge.poll().len(), 8);
+    }
+
+    #[test]
+    fn commands_switch_relays_and_publish_the_change() {
+        let (mut bridge, sims) = bridge(2);
+
+        bridge.poll();
+
+        let messages = bridge.command("arb/1-2/relays/set", b"1 3");
+
+        assert_eq!(sims[1].outputs(), Relay::One | Relay::Three);
+        assert_eq!(sims[0].outputs(), Relays::NONE);
+        assert_eq!(
+            topics(&messages),
+            [("arb/1-2/relay/1", "ON"), ("arb/1-2/relay/3", "ON")]
+        );
+
+        let messages = bridge.command("arb/1-2/relay/1/set", b"off");
+
+        assert_eq!(sims[1].outputs(), Relay::Three.into());
+        assert_eq!(topics(&messages), [("arb/1-2/relay/1", "OFF")]);
+
+        bridge.command("arb/1-2/relay/8/set", b"TOGGLE");
+        bridge.command("arb/1-2/relay/3/set", b"ON");
+
+        assert_eq!(sims[1].outputs(), Relay::Three | Relay::Eight);
+        assert_eq!(
+            topics(&bridge.command("arb/1-2/relays/set", b"")),
+            [("arb/1-2/relay/3", "OFF"), ("arb/1-2/relay/8", "OFF")]
+        );
+    }
+
+    #[test]
+    fn a_failed_verification_is_published_as_an_error() {
+        let (mut bridge, sims) = bridge(1);
+
+        bridge.poll();
+        sims[0].set_fault(Some(Fault::StuckLow));
+
+        let messages = bridge.command("arb/1-1/relay/2/set", b"ON");
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].topic, "arb/1-1/error");
+        assert!(!messages[0].retain);
+        assert!(messages[0].payload.starts_with("verification failed"));
+    }
+
+    #[test]
+    fn a_register_out_of_sync_is_published_as_an_error() {
+        let (mut bridge, sims) = bridge(1);
+
+        sims[0].set_fault(Some(Fault::TimeoutAfter {
+            reads: 0,
+            writes: 0,
+        }));
+
+        let messages = bridge.poll();
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].topic, "arb/1-1/error");
+        assert!(
+            messages[0]
+                .payload
+                .starts_with("the shift register is out of sync")
+        );
+    }
+
+    #[test]
+    fn a_busy_board_is_left_for_the_next_poll() {
+        let (mut bridge, _sims) = bridge(1);
+
+        let held = bridge.usb.boards().unwrap()[0].session().unwrap();
+
+        assert!(bridge.poll().is_empty());
+
+        drop(held);
+
+        assert_eq!(bridge.poll().len(), 8);
+    }
+
+    #[test]
+    fn nonsense_commands_are_published_as_errors() {
+        let (mut bridge, sims) = bridge(1);
+
+        for (topic, payload) in [
+            ("arb/1-1/relays/set", &b"1 9"[..]),
+            ("arb/1-1/relays/set", b"one"),
+            ("arb/1-1/relay/9/set", b"ON"),
+            ("arb/1-1/relay/1/set", b"MAYBE"),
+            ("arb/1-1/relay/1/set", b"\xff"),
+            ("arb/1-9/relay/1/set", b"ON"),
+        ] {
+            let messages = bridge.command(topic, payload);
+
+            assert_eq!(messages.len(), 1, "{topic}");
+            assert_eq!(messages[0].topic.rsplit('/').next(), Some("error"));
+        }
+
+        assert_eq!(sims[0].outputs(), Relays::NONE);
+
+        // Nothing this bridge subscribes to.
+        assert!(
+            bridge
+                .command("elsewhere/1-1/relay/1/set", b"ON")
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn an_unplugged_board_is_republished_in_full_when_it_returns() {
+        let (mut bridge, sims) = bridge(1);
+
+        bridge.poll();
+        sims[0].unplug();
+
+        assert!(bridge.poll().is_empty());
+
+        sims[0].plug();
+
+        assert_eq!(bridge.poll().len(), 8);
+    }
+}
@@ -1,40 +1,259 @@
-use clap::{ArgGroup, CommandFactory, Parser, value_parser};
+use clap::error::ErrorKind;
+use clap::{ArgGroup, CommandFactory, Parser, Subcommand, ValueEnum};
+use serde_json::{Map, Value, json};
 
+use std::env;
 use std::error::Error;
+use std::ffi::OsString;
+use std::fmt;
 use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use arb::{Relay, Relays, Verify};
+use arb::trace::Trace;
+use arb::{Board, BoardEvent, Config, Drift, Path, Relay, Relays, Usb, Verify};
 
 // The modes are mutually exclusive, which a group states once rather than pairwise
-// on each of them. `disable_verification` and `port` are modifiers, not modes, so
-// they name the modes they do not apply to.
+// on each of them. The subcommands are modes too, but no group can name them, so
+// `Args::try_parse` refuses them beside the others. `disable_verification`, `port`,
+// `path`, `all`, `board` and `config` are modifiers, not modes, and global so that
+// a subcommand takes them on either side of it. A global argument cannot name
+// top-level ones in its own conflicts, so the modes a modifier does not apply to
+// name it instead.
 #[derive(Parser, Debug)]
 #[command(name = "abacom-relay-board (arb)")]
-#[command(group(ArgGroup::new("mode").args(["status", "list", "reset", "relays"])))]
+#[command(group(ArgGroup::new("mode").args(["status", "list", "watch_boards", "reset", "relays"])))]
 struct Args {
     /// Gets relays status
-    #[arg(short, long)]
+    #[arg(short, long, conflicts_with = "disable_verification")]
     status: bool,
 
     /// Lists the attached relay boards
-    #[arg(short, long, conflicts_with = "port")]
+    #[arg(short, long, conflicts_with_all = ["port", "path", "all", "board", "disable_verification"])]
     list: bool,
 
+    // `requires` alone lets `--status --probe` through, so the other modes are
+    // refused by name.
+    /// With --list, lists only the devices that answer like a relay board, leaving
+    /// out flash programmers and other adapters built on the same chip
+    #[arg(long, requires = "list", conflicts_with_all = ["status", "watch_boards", "reset", "relays"])]
+    probe: bool,
+
+    /// Prints a line whenever a relay board is plugged in or unplugged, starting
+    /// with the ones already attached, until interrupted
+    #[arg(long, conflicts_with_all = ["port", "path", "all", "board", "disable_verification"])]
+    watch_boards: bool,
+
     /// Performs a USB reset on the relay board
-    #[arg(short, long)]
+    #[arg(short, long, conflicts_with = "disable_verification")]
     reset: bool,
 
     /// Disables the verification after activating relays
-    #[arg(short, long, conflicts_with_all = ["status", "list", "reset"])]
+    #[arg(short, long, global = true)]
     disable_verification: bool,
 
     /// Custom USB Port
-    #[arg(short, long)]
+    #[arg(short, long, global = true, conflicts_with = "board")]
     port: Option<u8>,
 
-    /// The relays to activate
-    #[arg(value_name = "RELAYS", value_parser = value_parser!(u8).range(0..=8))]
-    relays: Vec<u8>,
+    /// The board to use, by where it is on the USB tree, as --list spells it: 1-1.3
+    #[arg(long, value_name = "PATH", global = true, conflicts_with_all = ["port", "board"])]
+    path: Option<Path>,
+
+    /// Every attached relay board at once, each whatever the others come to: with
+    /// --status, --reset, relays by number, or a subcommand other than hold
+    #[arg(short, long, global = true, conflicts_with_all = ["port", "path", "board"])]
+    all: bool,
+
+    /// The board to use, by its name in the configuration file
+    #[arg(short, long, value_name = "NAME", global = true)]
+    board: Option<String>,
+
+    /// The configuration file naming boards and labelling relays [default:
+    /// ~/.config/arb/config.toml, if it exists]
+    #[arg(short, long, value_name = "FILE", global = true)]
+    config: Option<PathBuf>,
+
+    /// How to print results and errors
+    #[arg(long, value_enum, global = true, default_value_t = Format::Text)]
+    format: Format,
+
+    /// Records every USB transfer to FILE, to see what went over the wire
+    #[arg(long, value_name = "FILE", global = true)]
+    trace: Option<PathBuf>,
+
+    /// The relays to activate, by number or by label
+    #[arg(value_name = "RELAYS", value_parser = relay_or_zero)]
+    relays: Vec<Target>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+// Changes to some relays that leave the others as they are: what a script that owns
+// one relay wants, since `arb 3` turns every other relay off and `arb on 3` does
+// not. Each reads and writes the board within a single claim. Plain comments, as a
+// doc comment here would become the help text of `arb` itself.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Activates relays, leaving the others as they are
+    On(Change),
+    /// Deactivates relays, leaving the others as they are
+    Off(Change),
+    /// Flips relays, leaving the others as they are
+    Toggle(Change),
+    /// Activates relays for a while, then deactivates them
+    Pulse(Pulse),
+    /// Activates relays, deactivating the others, and keeps them that way until
+    /// interrupted, writing them back whenever the board drifts from them
+    Hold(Hold),
+}
+
+#[derive(clap::Args, Debug)]
+struct Change {
+    /// The relays to change, by number or by label
+    #[arg(value_name = "RELAYS", required = true, value_parser = relay)]
+    relays: Vec<Target>,
+}
+
+#[derive(clap::Args, Debug)]
+struct Pulse {
+    #[command(flatten)]
+    change: Change,
+
+    /// How long to keep the relays active, such as 500ms or 2s
+    #[arg(long = "for", value_name = "DURATION", value_parser = parse_duration)]
+    duration: Duration,
+}
+
+#[derive(clap::Args, Debug)]
+struct Hold {
+    /// The relays to hold active, by number or by label; 0 holds every relay off
+    #[arg(value_name = "RELAYS", required = true, value_parser = relay_or_zero)]
+    relays: Vec<Target>,
+
+    /// How often to check the board, such as 500ms or 5s
+    #[arg(long, value_name = "DURATION", value_parser = parse_duration, default_value = "5s")]
+    interval: Duration,
+}
+
+/// How results and errors are printed.
+///
+/// JSON puts everything a run has to say on stdout as one document, errors
+/// included, so a tool reads one stream and not two — or, for a run that never
+/// ends, one document a line. The exit code is the same either way.
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Format {
+    /// For people: `Active relays: 1 3`
+    Text,
+    /// For tools: one JSON document per run
+    Json,
+}
+
+/// Which change a [`Command`] makes, apart from the relays it makes it to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Switch {
+    On,
+    Off,
+    Toggle,
+    Pulse(Duration),
+}
+
+impl Command {
+    fn mode(&self) -> Mode {
+        let switch = match self {
+            Command::On(_) => Switch::On,
+            Command::Off(_) => Switch::Off,
+            Command::Toggle(_) => Switch::Toggle,
+            Command::Pulse(pulse) => Switch::Pulse(pulse.duration),
+            Command::Hold(hold) => return Mode::Hold(hold.interval),
+        };
+
+        Mode::Switch(switch)
+    }
+
+    fn relays(&self) -> &[Target] {
+        match self {
+            Command::On(change) | Command::Off(change) | Command::Toggle(change) => &change.relays,
+            Command::Pulse(pulse) => &pulse.change.relays,
+            Command::Hold(hold) => &hold.relays,
+        }
+    }
+}
+
+/// A relay as given on the command line: by its number, or by its label in the
+/// configuration file.
+#[derive(Clone, Debug, PartialEq)]
+enum Target {
+    Number(u8),
+    Label(String),
+}
+
+impl Target {
+    fn number(&self) -> Option<u8> {
+        match self {
+            Target::Number(number) => Some(*number),
+            Target::Label(_) => None,
+        }
+    }
+
+    fn label(&self) -> Option<&str> {
+        match self {
+            Target::Number(_) => None,
+            Target::Label(label) => Some(label),
+        }
+    }
+}
+
+/// Reads a relay for the relays mode, where `0` means "every relay off".
+fn relay_or_zero(text: &str) -> Result<Target, String> {
+    target(text, 0)
+}
+
+/// Reads a relay for a subcommand, which has no use for `0`.
+fn relay(text: &str) -> Result<Target, String> {
+    target(text, 1)
+}
+
+/// Anything that is all digits is a number, and has to be a relay's; anything else
+/// is a label. The configuration refuses labels that are numbers, so no label is
+/// ever shadowed by this.
+fn target(text: &str, lowest: u8) -> Result<Target, String> {
+    if text.is_empty() || !text.bytes().all(|byte| byte.is_ascii_digit()) {
+        return Ok(Target::Label(text.to_owned()));
+    }
+
+    match text.parse() {
+        Ok(number) if (lowest..=8).contains(&number) => Ok(Target::Number(number)),
+        _ => Err(format!("{text} is not in {lowest}..=8")),
+    }
+}
+
+/// Parses a duration written as a whole number and a unit: `500ms`, `2s`, `1m`.
+///
+/// The unit is required. A bare `500` reads as milliseconds to some and seconds to
+/// others, and holding a door strike for 500 seconds is not a typo to find out
+/// about from the door.
+fn parse_duration(text: &str) -> Result<Duration, String> {
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("expected a whole number and a unit, such as 500ms, got {text:?}"))?;
+
+    match unit {
+        "ms" => Ok(Duration::from_millis(number)),
+        "s" => Ok(Duration::from_secs(number)),
+        "m" => number
+            .checked_mul(60)
+            .map(Duration::from_secs)
+            .ok_or_else(|| format!("{text:?} is too long")),
+        "" => Err(format!("{text:?} needs a unit: ms, s or m")),
+        unit => Err(format!("unknown unit {unit:?}: expected ms, s or m")),
+    }
 }
 
 /// What an invocation asks for, which is exactly one thing.
@@ -45,18 +264,92 @@ struct Args {
 enum Mode {
     Status,
     List,
+    WatchBoards,
     Reset,
     Relays,
+    Switch(Switch),
+    Hold(Duration),
 }
 
 impl Args {
+    /// Parses `args`, refusing a subcommand given beside another mode.
+    ///
+    /// clap can only make arguments conflict with subcommands wholesale, which would
+    /// also refuse `--port` written before one, so the check clap cannot express is
+    /// made here, reported the way clap reports its own conflicts.
+    fn try_parse<I, T>(args: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let parsed = Self::try_parse_from(args)?;
+        let another_mode = parsed.status
+            || parsed.list
+            || parsed.watch_boards
+            || parsed.reset
+            || !parsed.relays.is_empty();
+
+        // Labels are words, so a subcommand written after relays reads as one more
+        // label rather than as a subcommand: `arb 1 on 3`.
+        let command = Self::command();
+        let subcommand_as_label = parsed
+            .relays
+            .iter()
+            .filter_map(Target::label)
+            .any(|label| command.find_subcommand(label).is_some());
+
+        if parsed.command.is_some() && another_mode || subcommand_as_label {
+            return Err(Self::command().error(
+                ErrorKind::ArgumentConflict,
+                "a subcommand cannot be used with another mode",
+            ));
+        }
+
+        // `--all` is global, so clap cannot refuse it beside the one subcommand it
+        // does not apply to, nor beside a board given on the other side of a
+        // subcommand from it. Labels are refused beside it too: each names a board of
+        // its own, which is not every board.
+        let targets = match &parsed.command {
+            Some(command) => command.relays(),
+            None => &parsed.relays,
+        };
+
+        if parsed.all && matches!(parsed.command, Some(Command::Hold(_))) {
+            return Err(Self::command().error(
+                ErrorKind::ArgumentConflict,
+                "--all cannot be used with hold",
+            ));
+        }
+
+        if parsed.all && (parsed.port.is_some() || parsed.path.is_some() || parsed.board.is_some())
+        {
+            return Err(Self::command().error(
+                ErrorKind::ArgumentConflict,
+                "--all cannot be used with a board given by --port, --path or --board",
+            ));
+        }
+
+        if parsed.all && targets.iter().any(|target| target.label().is_some()) {
+            return Err(Self::command().error(
+                ErrorKind::ArgumentConflict,
+                "--all takes relays by number: a label names a board of its own",
+            ));
+        }
+
+        Ok(parsed)
+    }
+
     /// The mode given, if any. The `mode` group makes them mutually exclusive, so a
     /// parsed `Args` names at most one.
     fn mode(&self) -> Option<Mode> {
-        if self.status {
+        if let Some(command) = &self.command {
+            Some(command.mode())
+        } else if self.status {
             Some(Mode::Status)
         } else if self.list {
             Some(Mode::List)
+        } else if self.watch_boards {
+            Some(Mode::WatchBoards)
         } else if self.reset {
             Some(Mode::Reset)
         } else if !self.relays.is_empty() {
@@ -68,9 +361,17 @@ impl Args {
 }
 
 fn main() {
-    if let Err(e) = run() {
-        eprintln!("arb: {e}");
-        std::process::exit(1);
+    let args = Args::try_parse(std::env::args_os()).unwrap_or_else(|e| e.exit());
+
+    if let Err(e) = run(&args) {
+        match args.format {
+            Format::Text => eprintln!("arb: {e}"),
+            // Each board's failure is in the document already.
+            Format::Json if e.is::<Failed>() => {}
+            Format::Json => println!("{}", error_json(&*e)),
+        }
+
+        std::process::exit(exit_code(&*e));
     }
 }
 
@@ -88,29 +389,551 @@ fn requested_relays(numbers: &[u8]) -> arb::Result<Relays> {
         .collect()
 }
 
+/// The configuration file `--config` names, or else the one in the default
+/// location. No file in the default location is no configuration, which names
+/// nothing; a file named with `--config` has to exist.
+fn config(args: &Args) -> Result<Config, Box<dyn Error>> {
+    if let Some(path) = &args.config {
+        return Ok(Config::load(path)?);
+    }
+
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .filter(|base| !base.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")));
+
+    match base.map(|base| base.join("arb").join("config.toml")) {
+        Some(path) if path.exists() => Ok(Config::load(path)?),
+        _ => Ok(Config::default()),
+    }
+}
+
+/// The board `--board`, `--path` or `--port` selects, or the only attached one.
+fn selected(usb: &Usb, config: &Config, args: &Args) -> Result<Board, Box<dyn Error>> {
+    match (&args.board, &args.path) {
+        (Some(name), _) => config
+            .board(usb, name)
+            .ok_or_else(|| format!("no board is named {name:?}").into()),
+        (None, Some(path)) => Ok(usb.board_at(path.clone())),
+        (None, None) => Ok(usb.board(args.port)),
+    }
+}
+
+/// The board `targets` are on, and the relays they name.
+///
+/// A label names its board as well as its relay, so labels settle the board on
+/// their own and only numbers need `--board`, `--path` or `--port`. Mixing the
+/// two is fine as long as they agree on the board.
+fn resolve(
+    usb: &Usb,
+    config: &Config,
+    args: &Args,
+    targets: &[Target],
+) -> Result<(Board, Relays), Box<dyn Error>> {
+    let numbers: Vec<u8> = targets.iter().filter_map(Target::number).collect();
+    let mut relays = requested_relays(&numbers)?;
+    let mut labelled: Option<Board> = None;
+
+    for label in targets.iter().filter_map(Target::label) {
+        let (board, relay) = config
+            .relay(usb, label)
+            .ok_or_else(|| format!("no relay is labelled {label:?}"))?;
+
+        if labelled
+            .as_ref()
+            .is_some_and(|other| other.path() != board.path())
+        {
+            return Err("the labelled relays are on different boards".into());
+        }
+
+        relays.insert(relay);
+        labelled = Some(board);
+    }
+
+    let Some(board) = labelled else {
+        return Ok((selected(usb, config, args)?, relays));
+    };
+
+    if args.port.is_some() || args.path.is_some() || args.board.is_some() {
+        let selected = selected(usb, config, args)?;
+
+        if selected.path() != board.path() {
+            return Err(format!("the labelled relays are on {board}, not {selected}").into());
+        }
+    }
+
+    Ok((board, relays))
+}
+
+/// Spells `relays` as `1 (pump) 3`, with the label of each that has one.
+fn labelled(relays: Relays, config: &Config, path: Option<&Path>) -> String {
+    if relays.is_empty() {
+        return relays.to_string();
+    }
+
+    let label = |relay| path.and_then(|path| config.label(path, relay));
+
+    relays
+        .iter()
+        .map(|relay| match label(relay) {
+            Some(label) => format!("{relay} ({label})"),
+            None => relay.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A board as JSON: where it is, and its name if the configuration gives it one.
+fn board_json(path: Option<&Path>, port: Option<u8>, config: &Config) -> Value {
+    json!({
+        "path": path.map(ToString::to_string),
+        "bus": path.map(Path::bus),
+        "hops": path.map(Path::hops),
+        "port": path.and_then(Path::port).or(port),
+        "name": path.and_then(|path| config.name(path)),
+    })
+}
+
+/// A board and its relays as JSON: a boolean for every relay, rather than the
+/// active ones listed, so a tool looks a relay up instead of searching for it.
+fn relays_json(relays: Relays, path: Option<&Path>, port: Option<u8>, config: &Config) -> Value {
+    let mut states = Map::new();
+    let mut labels = Map::new();
+
+    for relay in Relay::ALL {
+        states.insert(relay.to_string(), relays.contains(relay).into());
+
+        if let Some(label) = path.and_then(|path| config.label(path, relay)) {
+            labels.insert(relay.to_string(), label.into());
+        }
+    }
+
+    let mut board = board_json(path, port, config);
+    board["relays"] = Value::Object(states);
+    board["labels"] = Value::Object(labels);
+    board
+}
+
+/// Spells `at` as RFC 3339 does, in UTC and to the second: `2024-05-01T12:00:00Z`.
+fn timestamp(at: SystemTime) -> String {
+    let seconds = at
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let (days, second) = (seconds / 86_400, seconds % 86_400);
+
+    // Days since 1970 to a civil date, after Howard Hinnant's `civil_from_days`:
+    // counted in 400-year eras of March-based years, so leap days fall last.
+    let days = days + 719_468;
+    let (era, day_of_era) = (days / 146_097, days % 146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        second / 3_600,
+        second / 60 % 60,
+        second % 60
+    )
+}
+
+/// What a hold put right, or failed to, as one line of text.
+fn drift_text(drift: &Drift, config: &Config) -> Option<String> {
+    let path = drift.path();
+    let what = match drift {
+        Drift::Corrected { found, desired, .. } => format!(
+            "found {}, wrote {}",
+            found.map_or("a lost register".to_owned(), |found| labelled(
+                found,
+                config,
+                Some(path)
+            )),
+            labelled(*desired, config, Some(path))
+        ),
+        Drift::Failed { error, .. } => format!("failed: {error}"),
+        Drift::Missing { .. } => "missing".to_owned(),
+        // A drift added after this list was written.
+        _ => return None,
+    };
+
+    Some(format!(
+        "{} {}: {what}",
+        timestamp(drift.at()),
+        spelled(path, config)
+    ))
+}
+
+/// What a hold put right, or failed to, as JSON: the board, with an `event`
+/// naming what happened and `at` when.
+fn drift_json(drift: &Drift, config: &Config) -> Option<Value> {
+    let mut object = board_json(Some(drift.path()), None, config);
+
+    match drift {
+        Drift::Corrected { found, desired, .. } => {
+            object["event"] = "corrected".into();
+            object["found"] = found.map(numbers).into();
+            object["desired"] = numbers(*desired);
+        }
+        Drift::Failed { error, .. } => {
+            object["event"] = "failed".into();
+            object["error"] = error_json(error)["error"].take();
+        }
+        Drift::Missing { .. } => object["event"] = "missing".into(),
+        // A drift added after this list was written.
+        _ => return None,
+    }
+
+    object["at"] = timestamp(drift.at()).into();
+
+    Some(object)
+}
+
+/// The exit code a failed run ends with, by what the failure leaves a script to do
+/// about it.
+///
+/// * 1 — anything not below, the library's or the CLI's own
+/// * 2 — the command line made no sense, as clap reports it
+/// * 3 — the board was busy or a transfer timed out, and running again may work
+/// * 4 — the relays may be in any state: write them again
+/// * 5 — the relays are as they were, but the board can no longer report them:
+///   write them again before trusting a read
+///
+/// An `--all` run that failed on some boards exits with the highest code any of
+/// them calls for, so a script that writes again on 4 or 5 does so whenever one
+/// board needs it.
+///
+/// A timeout is both 3 and 4, and is reported as 4: running the command again is
+/// the remedy only where the command writes every relay.
+fn exit_code(error: &(dyn Error + 'static)) -> i32 {
+    if let Some(failed) = error.downcast_ref::<Failed>() {
+        return failed.code;
+    }
+
+    match error.downcast_ref::<arb::Error>() {
+        Some(e) if !e.relay_state_known() => 4,
+        Some(e) if e.requires_rewrite() => 5,
+        Some(e) if e.is_retryable() => 3,
+        _ => 1,
+    }
+}
+
+/// A failure as JSON, naming the [`arb::Error`] variant behind it and its code.
+/// Failures that are not the library's, such as an unreadable configuration, have
+/// neither.
+fn error_json(error: &(dyn Error + 'static)) -> Value {
+    let variant = error.downcast_ref::<arb::Error>();
+
+    let mut object = json!({
+        "variant": variant.map(variant_name),
+        "code": variant.map(arb::Error::code),
+        "message": error.to_string(),
+    });
+
+    match variant {
+        Some(arb::Error::VerificationFailed { expected, actual }) => {
+            object["expected"] = numbers(*expected);
+            object["actual"] = numbers(*actual);
+        }
+        Some(arb::Error::VerificationRetriesExhausted {
+            expected,
+            actual,
+            suspect,
+        }) => {
+            object["expected"] = numbers(*expected);
+            object["actual"] = actual.iter().copied().map(numbers).collect();
+            object["suspect"] = suspect.to_string().into();
+        }
+        _ => {}
+    }
+
+    json!({ "error": object })
+}
+
+fn numbers(relays: Relays) -> Value {
+    relays.iter().map(Relay::number).collect()
+}
+
+fn variant_name(error: &arb::Error) -> &'static str {
+    match error {
+        arb::Error::Usb(_) => "Usb",
+        arb::Error::NotFound => "NotFound",
+        arb::Error::MultipleFound => "MultipleFound",
+        arb::Error::Busy => "Busy",
+        arb::Error::VerificationFailed { .. } => "VerificationFailed",
+        arb::Error::VerificationRetriesExhausted { .. } => "VerificationRetriesExhausted",
+        arb::Error::InvalidRelay(_) => "InvalidRelay",
+        arb::Error::UnexpectedTransferLength { .. } => "UnexpectedTransferLength",
+        arb::Error::SelfTestFailed => "SelfTestFailed",
+        arb::Error::RegisterOutOfSync { .. } => "RegisterOutOfSync",
+        // A variant added after this list was written.
+        _ => "Unknown",
+    }
+}
+
+/// Spells the board at `path` as `--list` does, `port 3 (1-1.3)`, followed by its
+/// name if the configuration gives it one.
+fn spelled(path: &Path, config: &Config) -> String {
+    let mut spelled = match path.port() {
+        Some(port) => format!("port {port} ({path})"),
+        None => path.to_string(),
+    };
+
+    if let Some(name) = config.name(path) {
+        spelled = format!("{spelled} {name}");
+    }
+
+    spelled
+}
+
+/// Where `board` is, for output that names it: its own path, or the one it
+/// resolves to. A board that cannot be located by now has just answered anyway,
+/// so it goes out unnamed rather than failing a run that succeeded.
+fn located(board: &Board) -> Option<Path> {
+    board.path().cloned().or_else(|| board.locate().ok())
+}
+
+/// Prints what a write left active. Nothing as text, as it always has been, and
+/// the relays as JSON: a tool would otherwise have to ask for them again.
+fn report(args: &Args, config: &Config, board: &Board, active: Relays) -> io::Result<()> {
+    if args.format == Format::Json {
+        let path = located(board);
+
+        writeln!(
+            io::stdout(),
+            "{}",
+            relays_json(active, path.as_ref(), board.port(), config)
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Makes `switch` to `relays` on `board`, and returns what it left active.
+fn switched(board: &Board, switch: Switch, relays: Relays, verify: Verify) -> arb::Result<Relays> {
+    match switch {
+        Switch::On => board.switch_on(relays, verify),
+        Switch::Off => board.switch_off(relays, verify),
+        Switch::Toggle => board.toggle(relays, verify),
+        Switch::Pulse(duration) => board.pulse(relays, duration, verify),
+    }
+}
+
+/// The boards an `--all` run failed on, out of how many it ran on.
+///
+/// Each failure is printed beside its board as it is found, so this only ends the
+/// run, with the exit code the worst of them calls for.
+#[derive(Debug)]
+struct Failed {
+    boards: usize,
+    of: usize,
+    code: i32,
+}
+
+impl fmt::Display for Failed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} of {} boards failed", self.boards, self.of)
+    }
+}
+
+impl Error for Failed {}
+
+/// What `--all` came to on the board at `path`, as one line of text: the relays
+/// for a status, nothing for a write that went through, and the failure for one
+/// that did not. Failures go to stderr, as a single board's do.
+fn every_text(
+    path: &Path,
+    done: &arb::Result<Option<Relays>>,
+    config: &Config,
+    mode: &Mode,
+) -> Option<String> {
+    match done {
+        Ok(Some(relays)) if *mode == Mode::Status => Some(format!(
+            "{}: Active relays: {}",
+            spelled(path, config),
+            labelled(*relays, config, Some(path))
+        )),
+        Ok(_) => None,
+        Err(e) => Some(format!("{}: {e}", spelled(path, config))),
+    }
+}
+
+/// What `--all` came to on the board at `path`, as JSON: the board, with its
+/// relays where the mode left them known and its failure where it failed.
+fn every_json(path: &Path, done: &arb::Result<Option<Relays>>, config: &Config) -> Value {
+    match done {
+        Ok(Some(relays)) => relays_json(*relays, Some(path), None, config),
+        Ok(None) => board_json(Some(path), None, config),
+        Err(e) => {
+            let mut object = board_json(Some(path), None, config);
+            object["error"] = error_json(e)["error"].take();
+            object
+        }
+    }
+}
+
+/// Runs `mode` on every attached board at once, and prints what it came to on
+/// each.
+///
+/// Every board is tried however many others fail: an "all off" that stopped at
+/// the first busy board would leave the rest switched on.
+fn every(
+    usb: &Usb,
+    config: &Config,
+    args: &Args,
+    mode: &Mode,
+    verify: Verify,
+) -> Result<(), Box<dyn Error>> {
+    let targets = match &args.command {
+        Some(command) => command.relays(),
+        None => &args.relays,
+    };
+    let numbers: Vec<u8> = targets.iter().filter_map(Target::number).collect();
+    let relays = requested_relays(&numbers)?;
+
+    let done = usb.for_each_board(|board| match mode {
+        // Checked as for one board, and for the same reason.
+        Mode::Status => board.self_test().map(Some),
+        Mode::Reset => board.reset_device().map(|()| None),
+        Mode::Relays => board.set_relays(relays, verify).map(|()| Some(relays)),
+        Mode::Switch(switch) => switched(board, *switch, relays, verify).map(Some),
+        Mode::List | Mode::WatchBoards | Mode::Hold(_) => {
+            unreachable!("--all is refused beside {mode:?}")
+        }
+    })?;
+
+    // Where every board is meant to go off, finding none is not a success to
+    // report quietly.
+    if done.is_empty() {
+        return Err(arb::Error::NotFound.into());
+    }
+
+    match args.format {
+        Format::Text => {
+            for (path, done) in &done {
+                match (done, every_text(path, done, config, mode)) {
+                    (Ok(_), Some(line)) => writeln!(io::stdout(), "{line}")?,
+                    (Err(_), Some(line)) => eprintln!("arb: {line}"),
+                    (_, None) => {}
+                }
+            }
+        }
+        Format::Json => {
+            let boards = done
+                .iter()
+                .map(|(path, done)| every_json(path, done, config))
+                .collect();
+
+            writeln!(io::stdout(), "{}", Value::Array(boards))?;
+        }
+    }
+
+    let failures: Vec<&arb::Error> = done
+        .values()
+        .filter_map(|done| done.as_ref().err())
+        .collect();
+
+    match failures.iter().map(|&e| exit_code(e)).max() {
+        Some(code) => Err(Failed {
+            boards: failures.len(),
+            of: done.len(),
+            code,
+        }
+        .into()),
+        None => Ok(()),
+    }
+}
+
 /// The CLI's errors: `arb::Error` from the library, `io::Error` from writing to
 /// stdout. Both are only ever displayed, so a boxed trait object is enough and
 /// the library needs no I/O variant of its own.
-fn run() -> Result<(), Box<dyn Error>> {
-    let args = Args::parse();
-
+fn run(args: &Args) -> Result<(), Box<dyn Error>> {
     let Some(mode) = args.mode() else {
         Args::command().print_help()?;
         std::process::exit(2);
     };
 
+    let config = config(args)?;
+
+    let trace = match &args.trace {
+        Some(file) => Some(Trace::create(file).map_err(|e| traced(file, e))?),
+        None => None,
+    };
+
     // After the help branch: initialising libusb here would make a bare `arb`
     // fail with a USB error instead of printing its help.
-    let usb = arb::Usb::new()?;
+    let usb = match &trace {
+        Some(trace) => Usb::traced(trace.clone())?,
+        None => Usb::new()?,
+    };
 
-    let board = usb.board(args.port);
+    let verify = if args.disable_verification {
+        Verify::Disabled
+    } else {
+        Verify::Enabled
+    };
 
     match mode {
+        mode if args.all => every(&usb, &config, args, &mode, verify)?,
+
         Mode::List => {
+            let boards = if args.probe {
+                usb.boards_verified()?
+            } else {
+                usb.boards()?
+            };
+
             // No board prints nothing rather than erroring, so the output stays
             // something a script can read line by line.
-            for found in usb.boards()? {
-                writeln!(io::stdout(), "{found}")?;
+            if args.format == Format::Json {
+                let boards: Vec<_> = boards
+                    .iter()
+                    .map(|found| board_json(found.path(), found.port(), &config))
+                    .collect();
+
+                writeln!(io::stdout(), "{}", Value::Array(boards))?;
+            } else {
+                for found in boards {
+                    match found.path().and_then(|path| config.name(path)) {
+                        Some(name) => writeln!(io::stdout(), "{found} {name}")?,
+                        None => writeln!(io::stdout(), "{found}")?,
+                    }
+                }
+            }
+        }
+
+        Mode::WatchBoards => {
+            // Never ends by itself: each event is printed as it happens, and a failed
+            // look at the bus ends the run as any other failure does.
+            for event in usb.watch()? {
+                let (happened, path) = match event? {
+                    BoardEvent::Arrived(board) => (
+                        "arrived",
+                        board.path().cloned().expect("an arrival names its path"),
+                    ),
+                    BoardEvent::Left(path) => ("left", path),
+                    // An event added after this list was written.
+                    _ => continue,
+                };
+
+                match args.format {
+                    Format::Text => {
+                        writeln!(io::stdout(), "{happened} {}", spelled(&path, &config))?
+                    }
+                    Format::Json => {
+                        let mut board = board_json(Some(&path), None, &config);
+                        board["event"] = happened.into();
+
+                        writeln!(io::stdout(), "{board}")?;
+                    }
+                }
             }
         }
 
@@ -122,33 +945,115 @@ fn run() -> Result<(), Box<dyn Error>> {
             // exactly the failure a person reading it wants caught.
             //
             // One claim: the relays printed are the ones the check vouched for.
+            let board = selected(&usb, &config, args)?;
             let relays = board.self_test()?;
+            let path = located(&board);
 
-            writeln!(io::stdout(), "Active relays: {relays}")?;
+            match args.format {
+                Format::Text => writeln!(
+                    io::stdout(),
+                    "Active relays: {}",
+                    labelled(relays, &config, path.as_ref())
+                )?,
+                Format::Json => writeln!(
+                    io::stdout(),
+                    "{}",
+                    relays_json(relays, path.as_ref(), board.port(), &config)
+                )?,
+            }
         }
 
-        Mode::Reset => board.reset_device()?,
+        Mode::Reset => {
+            let board = selected(&usb, &config, args)?;
+
+            // Located first: the reset re-enumerates the board, and for a moment
+            // afterwards there is nothing to locate.
+            let path = located(&board);
+
+            board.reset_device()?;
+
+            if args.format == Format::Json {
+                writeln!(
+                    io::stdout(),
+                    "{}",
+                    board_json(path.as_ref(), board.port(), &config)
+                )?;
+            }
+        }
 
         Mode::Relays => {
-            let verify = if args.disable_verification {
-                Verify::Disabled
-            } else {
-                Verify::Enabled
-            };
+            let (board, relays) = resolve(&usb, &config, args, &args.relays)?;
+
+            board.set_relays(relays, verify)?;
+            report(args, &config, &board, relays)?;
+        }
+
+        Mode::Switch(switch) => {
+            let command = args.command.as_ref().expect("a switch mode has a command");
+            let (board, relays) = resolve(&usb, &config, args, command.relays())?;
+
+            let active = switched(&board, switch, relays, verify)?;
+
+            report(args, &config, &board, active)?;
+        }
+
+        Mode::Hold(interval) => {
+            let command = args.command.as_ref().expect("a hold mode has a command");
+            let (board, relays) = resolve(&usb, &config, args, command.relays())?;
+
+            // Held where the board is now: a board named by port, or by nothing, is
+            // resolved once, and what turns up there later is the board held.
+            let path = board.locate()?;
+
+            board.set_relays(relays, verify)?;
+            report(args, &config, &board, relays)?;
 
-            board.set_relays(requested_relays(&args.relays)?, verify)?;
+            let mut reconciler = usb
+                .reconciler()?
+                .with_interval(interval)
+                .with_verify(verify);
+
+            reconciler.hold(path, relays);
+
+            // Never ends by itself: a failure is printed and tried again next time,
+            // since a hold that gave up would be no hold at all.
+            for drift in reconciler {
+                let line = match args.format {
+                    Format::Text => drift_text(&drift, &config),
+                    Format::Json => drift_json(&drift, &config).map(|json| json.to_string()),
+                };
+
+                if let Some(line) = line {
+                    writeln!(io::stdout(), "{line}")?;
+                }
+            }
         }
     }
 
+    // Every transfer is in the file already; this only says if one is missing. A
+    // run that failed says why it failed instead, which matters more.
+    if let (Some(file), Some(trace)) = (&args.trace, trace) {
+        trace.flush().map_err(|e| traced(file, e))?;
+    }
+
     Ok(())
 }
 
+/// A trace that could not be written, by the file it was meant for.
+fn traced(file: &std::path::Path, error: io::Error) -> String {
+    format!("cannot record a trace to {}: {error}", file.display())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     fn parse(args: &[&str]) -> Result<Args, clap::Error> {
-        Args::try_parse_from(std::iter::once("arb").chain(args.iter().copied()))
+        Args::try_parse(std::iter::once("arb").chain(args.iter().copied()))
+    }
+
+    fn numbers(numbers: &[u8]) -> Vec<Target> {
+        numbers.iter().copied().map(Target::Number).collect()
     }
 
     #[test]
@@ -187,23 +1092,43 @@ mod tests {
         assert!(parse(&["--list", "-d"]).is_err());
     }
 
+    #[test]
+    fn watching_boards_conflicts_with_every_other_mode() {
+        // It watches every board, so naming one is as meaningless as for `--list`.
+        assert!(parse(&["--watch-boards"]).unwrap().watch_boards);
+        assert!(parse(&["--watch-boards", "--list"]).is_err());
+        assert!(parse(&["--watch-boards", "--port", "3"]).is_err());
+        assert!(parse(&["--watch-boards", "--board", "garden"]).is_err());
+        assert!(parse(&["--watch-boards", "1"]).is_err());
+        assert!(parse(&["--watch-boards", "on", "3"]).is_err());
+        assert!(parse(&["--watch-boards", "--format", "json"]).is_ok());
+    }
+
+    #[test]
+    fn a_watched_board_is_spelled_as_the_list_spells_it() {
+        let config = garden();
+
+        assert_eq!(spelled(&Path::new(1, [2]), &config), "port 2 (1-2) garden");
+        assert_eq!(spelled(&Path::new(1, [1, 3]), &config), "port 3 (1-1.3)");
+    }
+
     #[test]
     fn relay_args() {
         let args = parse(&["1", "3", "5"]).unwrap();
-        assert_eq!(args.relays, vec![1, 3, 5]);
+        assert_eq!(args.relays, numbers(&[1, 3, 5]));
     }
 
     #[test]
     fn relay_zero_deactivates_all() {
         let args = parse(&["0"]).unwrap();
-        assert_eq!(args.relays, vec![0]);
+        assert_eq!(args.relays, numbers(&[0]));
     }
 
     #[test]
     fn disable_verification_with_relays() {
         let args = parse(&["-d", "1", "2"]).unwrap();
         assert!(args.disable_verification);
-        assert_eq!(args.relays, vec![1, 2]);
+        assert_eq!(args.relays, numbers(&[1, 2]));
     }
 
     #[test]
@@ -246,11 +1171,124 @@ mod tests {
     fn every_flag_names_the_mode_it_runs() {
         assert_eq!(parse(&["--status"]).unwrap().mode(), Some(Mode::Status));
         assert_eq!(parse(&["--list"]).unwrap().mode(), Some(Mode::List));
+        assert_eq!(
+            parse(&["--watch-boards"]).unwrap().mode(),
+            Some(Mode::WatchBoards)
+        );
         assert_eq!(parse(&["--reset"]).unwrap().mode(), Some(Mode::Reset));
         assert_eq!(parse(&["1", "2"]).unwrap().mode(), Some(Mode::Relays));
         assert_eq!(parse(&["0"]).unwrap().mode(), Some(Mode::Relays));
     }
 
+    #[test]
+    fn every_subcommand_names_the_switch_it_makes() {
+        assert_eq!(
+            parse(&["on", "3"]).unwrap().mode(),
+            Some(Mode::Switch(Switch::On))
+        );
+        assert_eq!(
+            parse(&["off", "2", "5"]).unwrap().mode(),
+            Some(Mode::Switch(Switch::Off))
+        );
+        assert_eq!(
+            parse(&["toggle", "7"]).unwrap().mode(),
+            Some(Mode::Switch(Switch::Toggle))
+        );
+    }
+
+    #[test]
+    fn a_pulse_carries_its_duration() {
+        assert_eq!(
+            parse(&["pulse", "4", "--for", "500ms"]).unwrap().mode(),
+            Some(Mode::Switch(Switch::Pulse(Duration::from_millis(500))))
+        );
+
+        let args = parse(&["pulse", "1", "3", "--for", "2s", "-p", "2"]).unwrap();
+
+        assert_eq!(args.command.unwrap().relays(), numbers(&[1, 3]));
+        assert_eq!(args.port, Some(2));
+    }
+
+    #[test]
+    fn a_pulse_needs_a_duration() {
+        assert!(parse(&["pulse", "4"]).is_err());
+        assert!(parse(&["pulse", "--for", "1s"]).is_err());
+    }
+
+    #[test]
+    fn a_hold_carries_its_relays_and_how_often_it_checks() {
+        let args = parse(&["hold", "1", "3", "--interval", "500ms"]).unwrap();
+
+        assert_eq!(args.mode(), Some(Mode::Hold(Duration::from_millis(500))));
+        assert_eq!(args.command.unwrap().relays(), numbers(&[1, 3]));
+
+        // Every five seconds unless told otherwise, and everything off is a state
+        // worth holding too.
+        assert_eq!(
+            parse(&["hold", "0"]).unwrap().mode(),
+            Some(Mode::Hold(Duration::from_secs(5)))
+        );
+        assert!(parse(&["hold"]).is_err());
+        assert!(parse(&["--status", "hold", "3"]).is_err());
+    }
+
+    #[test]
+    fn durations_parse_in_every_unit() {
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("2s"), Ok(Duration::from_secs(2)));
+        assert_eq!(parse_duration("1m"), Ok(Duration::from_secs(60)));
+        assert_eq!(parse_duration("0s"), Ok(Duration::ZERO));
+    }
+
+    #[test]
+    fn a_duration_without_a_unit_is_rejected() {
+        assert!(parse_duration("500").is_err());
+        assert!(parse_duration("ms").is_err());
+        assert!(parse_duration("1.5s").is_err());
+        assert!(parse_duration("5h").is_err());
+        assert!(parse_duration("-1s").is_err());
+        assert!(parse_duration(&format!("{}m", u64::MAX)).is_err());
+    }
+
+    #[test]
+    fn a_subcommand_carries_the_relays_it_changes() {
+        let args = parse(&["off", "2", "5"]).unwrap();
+
+        assert_eq!(args.command.unwrap().relays(), numbers(&[2, 5]));
+    }
+
+    #[test]
+    fn a_subcommand_takes_the_modifiers_on_either_side() {
+        for args in [
+            &["on", "3", "--port", "2", "-d"][..],
+            &["--port", "2", "-d", "on", "3"][..],
+            &["-p", "2", "on", "3", "-d"][..],
+        ] {
+            let args = parse(args).unwrap();
+
+            assert_eq!(args.port, Some(2));
+            assert!(args.disable_verification);
+        }
+    }
+
+    #[test]
+    fn a_subcommand_needs_real_relays() {
+        // `0` means "everything off" to the relays mode, which is a whole state;
+        // a change to some relays has no use for it.
+        assert!(parse(&["on"]).is_err());
+        assert!(parse(&["on", "0"]).is_err());
+        assert!(parse(&["toggle", "9"]).is_err());
+    }
+
+    #[test]
+    fn a_subcommand_conflicts_with_every_other_mode() {
+        assert!(parse(&["--status", "on", "3"]).is_err());
+        assert!(parse(&["--reset", "off", "3"]).is_err());
+        assert!(parse(&["--list", "toggle", "3"]).is_err());
+        assert!(parse(&["on", "3", "--status"]).is_err());
+        assert!(parse(&["1", "on", "3"]).is_err());
+    }
+
     #[test]
     fn an_invocation_without_a_mode_names_none() {
         // What makes a bare `arb` print its help, and `--port` alone with it: a
@@ -274,7 +1312,7 @@ mod tests {
         let mut ids: Vec<_> = group.get_args().map(|id| id.as_str()).collect();
         ids.sort_unstable();
 
-        assert_eq!(ids, ["list", "relays", "reset", "status"]);
+        assert_eq!(ids, ["list", "relays", "reset", "status", "watch_boards"]);
     }
 
     fn requested(numbers: &[u8]) -> Relays {
@@ -316,4 +1354,403 @@ mod tests {
             Err(arb::Error::InvalidRelay(9))
         ));
     }
+
+    #[test]
+    fn relays_are_given_by_number_or_by_label() {
+        let args = parse(&["1", "pump"]).unwrap();
+
+        assert_eq!(
+            args.relays,
+            [Target::Number(1), Target::Label("pump".into())]
+        );
+
+        let args = parse(&["on", "door", "--board", "hall"]).unwrap();
+
+        assert_eq!(
+            args.command.unwrap().relays(),
+            [Target::Label("door".into())]
+        );
+        assert_eq!(args.board.as_deref(), Some("hall"));
+    }
+
+    #[test]
+    fn anything_all_digits_is_a_number_and_has_to_be_a_relay() {
+        assert!(parse(&["9"]).is_err());
+        assert!(parse(&["300"]).is_err());
+        assert!(parse(&["on", "0"]).is_err());
+        assert_eq!(parse(&["3x"]).unwrap().relays, [Target::Label("3x".into())]);
+    }
+
+    #[test]
+    fn a_board_is_named_or_given_by_port_but_not_both() {
+        assert!(parse(&["--board", "hall", "--port", "2", "1"]).is_err());
+        assert!(parse(&["--board", "hall", "--path", "1-1.3", "1"]).is_err());
+        assert!(parse(&["--path", "1-1.3", "--port", "3", "1"]).is_err());
+        assert!(parse(&["--list", "--path", "1-1.3"]).is_err());
+        assert!(parse(&["--list", "--board", "hall"]).is_err());
+        assert!(parse(&["--status", "--board", "hall"]).is_ok());
+        assert!(parse(&["--config", "arb.toml", "--list"]).is_ok());
+    }
+
+    #[test]
+    fn a_board_is_given_by_path_on_either_side_of_a_subcommand() {
+        for args in [
+            &["--path", "1-1.3", "on", "3"][..],
+            &["on", "3", "--path", "1-1.3"][..],
+        ] {
+            assert_eq!(parse(args).unwrap().path, Some(Path::new(1, [1, 3])));
+        }
+
+        assert!(parse(&["--path", "1.3", "--status"]).is_err());
+    }
+
+    #[test]
+    fn every_board_is_given_by_all_for_the_modes_that_apply_to_each() {
+        for args in [
+            &["--all", "--status"][..],
+            &["--all", "--reset"][..],
+            &["--all", "0"][..],
+            &["-a", "off", "1", "2"][..],
+            &["pulse", "3", "--for", "1s", "--all"][..],
+        ] {
+            assert!(parse(args).unwrap().all);
+        }
+
+        assert!(parse(&["--all", "--list"]).is_err());
+        assert!(parse(&["--all", "--watch-boards"]).is_err());
+        assert!(parse(&["--all", "--port", "3", "0"]).is_err());
+        assert!(parse(&["--all", "--path", "1-1.3", "0"]).is_err());
+        assert!(parse(&["--board", "hall", "on", "1", "--all"]).is_err());
+        assert!(parse(&["--all", "hold", "0"]).is_err());
+    }
+
+    #[test]
+    fn every_board_takes_relays_by_number_only() {
+        assert!(parse(&["--all", "pump"]).is_err());
+        assert!(parse(&["on", "pump", "--all"]).is_err());
+        assert!(parse(&["--all", "on", "1", "pump"]).is_err());
+    }
+
+    /// A context that finds no boards: resolving names looks nothing up, so it
+    /// never has to.
+    #[derive(Debug)]
+    struct Nowhere;
+
+    impl arb::transport::Transport for Nowhere {
+        fn devices(&self) -> arb::Result<Vec<Box<dyn arb::transport::Device>>> {
+            Ok(Vec::new())
+        }
+    }
+
+    fn garden() -> Config {
+        r#"
+            [boards.garden]
+            path = "1-2"
+            relays = { 1 = "pump", 3 = "sprinkler" }
+
+            [boards.hall]
+            path = "1-1"
+            relays = { 2 = "door" }
+        "#
+        .parse()
+        .unwrap()
+    }
+
+    fn resolved(args: &[&str]) -> Result<(Option<Path>, Relays), String> {
+        let usb = Usb::with_transport(Nowhere);
+        let args = parse(args).unwrap();
+        let targets = match &args.command {
+            Some(command) => command.relays(),
+            None => &args.relays,
+        };
+
+        resolve(&usb, &garden(), &args, targets)
+            .map(|(board, relays)| (board.path().cloned(), relays))
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn labels_settle_the_board_on_their_own() {
+        assert_eq!(
+            resolved(&["pump", "sprinkler"]),
+            Ok((Some(Path::new(1, [2])), Relay::One | Relay::Three))
+        );
+
+        // Numbers beside a label are on the labelled board.
+        assert_eq!(
+            resolved(&["on", "door", "4"]),
+            Ok((Some(Path::new(1, [1])), Relay::Two | Relay::Four))
+        );
+
+        // A name that agrees with the label is only saying it twice.
+        assert_eq!(
+            resolved(&["on", "door", "--board", "hall"]),
+            Ok((Some(Path::new(1, [1])), Relay::Two.into()))
+        );
+    }
+
+    #[test]
+    fn numbers_alone_need_the_board_selected() {
+        assert_eq!(resolved(&["1", "3"]), Ok((None, Relay::One | Relay::Three)));
+        assert_eq!(
+            resolved(&["on", "4", "--board", "garden"]),
+            Ok((Some(Path::new(1, [2])), Relay::Four.into()))
+        );
+    }
+
+    #[test]
+    fn labels_that_cannot_be_carried_out_are_refused() {
+        assert_eq!(
+            resolved(&["lawnmower"]),
+            Err("no relay is labelled \"lawnmower\"".into())
+        );
+        assert_eq!(
+            resolved(&["pump", "door"]),
+            Err("the labelled relays are on different boards".into())
+        );
+        assert_eq!(
+            resolved(&["on", "pump", "--board", "hall"]),
+            Err("the labelled relays are on port 2 (1-2), not port 1 (1-1)".into())
+        );
+        // A port never names the board a label does: it could be either of two.
+        assert!(resolved(&["on", "pump", "--port", "2"]).is_err());
+        assert_eq!(
+            resolved(&["on", "4", "--board", "shed"]),
+            Err("no board is named \"shed\"".into())
+        );
+    }
+
+    #[test]
+    fn status_prints_labels_beside_numbers() {
+        let config = garden();
+        let path = Path::new(1, [2]);
+
+        assert_eq!(
+            labelled(Relay::One | Relay::Two | Relay::Three, &config, Some(&path)),
+            "1 (pump) 2 3 (sprinkler)"
+        );
+        assert_eq!(labelled(Relay::One.into(), &config, None), "1");
+        assert_eq!(labelled(Relays::NONE, &config, Some(&path)), "none");
+    }
+
+    #[test]
+    fn the_format_defaults_to_text_and_goes_on_either_side() {
+        assert_eq!(parse(&["--status"]).unwrap().format, Format::Text);
+        assert_eq!(
+            parse(&["--format", "json", "--list"]).unwrap().format,
+            Format::Json
+        );
+        assert_eq!(
+            parse(&["on", "3", "--format", "json"]).unwrap().format,
+            Format::Json
+        );
+        assert!(parse(&["--format", "yaml", "--list"]).is_err());
+    }
+
+    #[test]
+    fn probing_is_a_way_of_listing() {
+        assert!(parse(&["--list", "--probe"]).unwrap().probe);
+        assert!(parse(&["--probe"]).is_err());
+        assert!(parse(&["--status", "--probe"]).is_err());
+        assert!(parse(&["1", "--probe"]).is_err());
+        assert!(parse(&["on", "1", "--probe"]).is_err());
+    }
+
+    #[test]
+    fn a_trace_is_recorded_on_request_from_either_side() {
+        assert_eq!(parse(&["--status"]).unwrap().trace, None);
+
+        for args in [
+            &["--trace", "board.trace", "on", "3"][..],
+            &["on", "3", "--trace", "board.trace"][..],
+        ] {
+            assert_eq!(
+                parse(args).unwrap().trace,
+                Some(PathBuf::from("board.trace"))
+            );
+        }
+    }
+
+    #[test]
+    fn a_board_in_json_says_where_it_is() {
+        let config = garden();
+
+        assert_eq!(
+            board_json(Some(&Path::new(1, [2])), None, &config),
+            json!({ "path": "1-2", "bus": 1, "hops": [2], "port": 2, "name": "garden" })
+        );
+        assert_eq!(
+            board_json(Some(&Path::new(3, [1, 4])), Some(4), &config),
+            json!({ "path": "3-1.4", "bus": 3, "hops": [1, 4], "port": 4, "name": null })
+        );
+
+        // Named by port and never located: the port is all there is to say.
+        assert_eq!(
+            board_json(None, Some(5), &config),
+            json!({ "path": null, "bus": null, "hops": null, "port": 5, "name": null })
+        );
+    }
+
+    #[test]
+    fn relays_in_json_are_a_boolean_for_every_relay() {
+        let json = relays_json(
+            Relay::One | Relay::Eight,
+            Some(&Path::new(1, [2])),
+            None,
+            &garden(),
+        );
+
+        assert_eq!(
+            json["relays"],
+            json!({
+                "1": true, "2": false, "3": false, "4": false,
+                "5": false, "6": false, "7": false, "8": true,
+            })
+        );
+        assert_eq!(json["labels"], json!({ "1": "pump", "3": "sprinkler" }));
+        assert_eq!(json["path"], "1-2");
+    }
+
+    #[test]
+    fn an_error_in_json_names_its_variant() {
+        let error: Box<dyn Error> = Box::new(arb::Error::VerificationFailed {
+            expected: Relay::One | Relay::Two,
+            actual: Relay::One.into(),
+        });
+
+        assert_eq!(
+            error_json(&*error),
+            json!({ "error": {
+                "variant": "VerificationFailed",
+                "code": "verification_failed",
+                "message": "verification failed: expected 1 2, read back 1",
+                "expected": [1, 2],
+                "actual": [1],
+            }})
+        );
+
+        let error: Box<dyn Error> = Box::new(arb::Error::Busy);
+
+        assert_eq!(error_json(&*error)["error"]["variant"], "Busy");
+
+        // Not the library's, so no variant to name.
+        let error: Box<dyn Error> = "no relay is labelled \"lawnmower\"".into();
+
+        assert_eq!(error_json(&*error)["error"]["variant"], Value::Null);
+        assert_eq!(error_json(&*error)["error"]["code"], Value::Null);
+    }
+
+    #[test]
+    fn timestamps_are_rfc_3339_in_utc() {
+        let at = |seconds| timestamp(UNIX_EPOCH + Duration::from_secs(seconds));
+
+        assert_eq!(at(0), "1970-01-01T00:00:00Z");
+        assert_eq!(at(951_782_400), "2000-02-29T00:00:00Z");
+        assert_eq!(at(1_700_000_000), "2023-11-14T22:13:20Z");
+    }
+
+    #[test]
+    fn a_drift_in_json_says_where_and_what_happened() {
+        let config = garden();
+        let at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        let corrected = Drift::Corrected {
+            path: Path::new(1, [2]),
+            at,
+            found: None,
+            desired: Relay::One | Relay::Three,
+        };
+
+        assert_eq!(
+            drift_json(&corrected, &config),
+            Some(json!({
+                "path": "1-2", "bus": 1, "hops": [2], "port": 2, "name": "garden",
+                "event": "corrected", "found": null, "desired": [1, 3],
+                "at": "2023-11-14T22:13:20Z",
+            }))
+        );
+
+        let failed = Drift::Failed {
+            path: Path::new(1, [2]),
+            at,
+            error: arb::Error::Busy,
+        };
+
+        assert_eq!(
+            drift_json(&failed, &config).unwrap()["error"]["code"],
+            "busy"
+        );
+        assert_eq!(
+            drift_text(&failed, &config).unwrap(),
+            format!(
+                "2023-11-14T22:13:20Z port 2 (1-2) garden: failed: {}",
+                arb::Error::Busy
+            )
+        );
+    }
+
+    #[test]
+    fn every_board_is_reported_on_its_own_line_or_object() {
+        let config = garden();
+        let path = Path::new(1, [2]);
+        let read: arb::Result<_> = Ok(Some(Relay::One | Relay::Three));
+        let busy: arb::Result<_> = Err(arb::Error::Busy);
+
+        assert_eq!(
+            every_text(&path, &read, &config, &Mode::Status).as_deref(),
+            Some("port 2 (1-2) garden: Active relays: 1 (pump) 3 (sprinkler)")
+        );
+        assert_eq!(every_text(&path, &read, &config, &Mode::Relays), None);
+        assert_eq!(
+            every_text(&path, &busy, &config, &Mode::Reset).as_deref(),
+            Some("port 2 (1-2) garden: the relay board is in use by another application")
+        );
+
+        let json = every_json(&path, &read, &config);
+
+        assert_eq!(json["path"], "1-2");
+        assert_eq!(json["relays"]["3"], true);
+
+        let json = every_json(&path, &busy, &config);
+
+        assert_eq!(json["name"], "garden");
+        assert_eq!(json["error"]["variant"], "Busy");
+        assert_eq!(every_json(&path, &Ok(None), &config)["relays"], Value::Null);
+    }
+
+    #[test]
+    fn each_kind_of_failure_exits_with_a_code_of_its_own() {
+        let code = |error: arb::Error| exit_code(&error);
+
+        assert_eq!(code(arb::Error::NotFound), 1);
+        assert_eq!(code(arb::Error::SelfTestFailed), 1);
+        assert_eq!(code(arb::Error::Busy), 3);
+        assert_eq!(code(arb::Error::Usb(rusb::Error::Timeout)), 4);
+        assert_eq!(
+            code(arb::Error::VerificationFailed {
+                expected: Relays::ALL,
+                actual: Relays::NONE,
+            }),
+            4
+        );
+        assert_eq!(
+            code(arb::Error::RegisterOutOfSync {
+                source: Box::new(arb::Error::Usb(rusb::Error::Timeout)),
+            }),
+            5
+        );
+
+        let error: Box<dyn Error> = "no relay is labelled \"lawnmower\"".into();
+
+        assert_eq!(exit_code(&*error), 1);
+
+        let failed: Box<dyn Error> = Box::new(Failed {
+            boards: 2,
+            of: 4,
+            code: 4,
+        });
+
+        assert_eq!(exit_code(&*failed), 4);
+        assert_eq!(failed.to_string(), "2 of 4 boards failed");
+    }
 }
@@ -0,0 +1,620 @@
+//! `arbd`: one process that owns the relay boards, and an HTTP/JSON API in front of
+//! them.
+//!
+//! A board's interface is claimed exclusively, so services sharing one through the
+//! library take turns by failing: whichever call loses gets `Error::Busy`. Through
+//! the daemon they queue instead. It answers one request at a time, which is what
+//! makes it the only client the boards ever see, at the cost of a pulse holding up
+//! every request behind it for as long as it lasts — which is why a pulse is
+//! refused past [`MAX_PULSE`].
+//!
+//! Boards are named by path, the `1-1.3` spelling `arb --list` and `lsusb -t` use:
+//!
+//! | Method | Route                                   | Body                          |
+//! | ------ | --------------------------------------- | ----------------------------- |
+//! | GET    | `/boards`                               |                               |
+//! | GET    | `/boards/{path}/relays`                 |                               |
+//! | PUT    | `/boards/{path}/relays`                 | `{"relays": [1, 3]}`          |
+//! | POST   | `/boards/{path}/relays/{relay}/on`      |                               |
+//! | POST   | `/boards/{path}/relays/{relay}/off`     |                               |
+//! | POST   | `/boards/{path}/relays/{relay}/toggle`  |                               |
+//! | POST   | `/boards/{path}/relays/{relay}/pulse`   | `{"duration_ms": 500}`        |
+//! | POST   | `/boards/{path}/self-test`              |                               |
+//!
+//! Every write verifies unless its body says `"verify": false`, and answers with the
+//! relays active afterwards, as `{"board": "1-1.3", "relays": [1, 3]}`. A failure
+//! answers `{"error": {"code": "busy", "message": ".."}}`, where the code is the
+//! one `arb::Error::code` gives and each variant has a status of its own; see
+//! [`Failure::status_and_code`].
+
+use clap::Parser;
+use serde_json::{Map, Value, json};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use std::error::Error;
+use std::time::Duration;
+
+use arb::{Board, Relay, Relays, Usb, Verify};
+
+#[derive(Parser, Debug)]
+#[command(name = "abacom-relay-board daemon (arbd)")]
+struct Args {
+    /// The address to serve the API on
+    #[arg(short, long, value_name = "ADDRESS", default_value = "127.0.0.1:7878")]
+    listen: String,
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("arbd: {e}");
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error + Send + Sync>> {
+    let args = Args::parse();
+
+    let mut usb = Usb::new()?;
+    let server = Server::http(&args.listen)?;
+
+    eprintln!("arbd: listening on {}", args.listen);
+
+    for mut request in server.incoming_requests() {
+        let mut body = String::new();
+
+        let answer = match request.as_reader().read_to_string(&mut body) {
+            Ok(_) => handle(&usb, request.method(), request.url(), &body),
+            Err(e) => Err(Failure::BadRequest(format!("unreadable body: {e}"))),
+        };
+
+        // The policy `Usb` documents for a context that may have soured: drop it
+        // and build another after a USB failure, rather than trying to tell a dead
+        // context from a transient fault. A daemon is the one client that holds a
+        // context long enough for it to matter.
+        if let Err(Failure::Arb(arb::Error::Usb(e))) = &answer {
+            eprintln!("arbd: rebuilding the USB context after: {e}");
+
+            match Usb::new() {
+                Ok(fresh) => usb = fresh,
+                Err(e) => eprintln!("arbd: keeping the old USB context: {e}"),
+            }
+        }
+
+        respond(request, answer);
+    }
+
+    Ok(())
+}
+
+/// Sends `answer` as JSON. A client that hung up first is not the daemon's problem,
+/// so that failure is only logged.
+fn respond(request: Request, answer: Result<Value, Failure>) {
+    let (status, body) = match answer {
+        Ok(body) => (200, body),
+        Err(failure) => (failure.status(), failure.body()),
+    };
+
+    let content_type =
+        Header::from_bytes("Content-Type", "application/json").expect("a valid header");
+
+    let response = Response::from_string(body.to_string())
+        .with_status_code(status)
+        .with_header(content_type);
+
+    if let Err(e) = request.respond(response) {
+        eprintln!("arbd: could not answer a request: {e}");
+    }
+}
+
+/// The longest pulse a request may ask for. Every other client waits it out, on
+/// every board, so anything longer is two requests: one to switch on, one to
+/// switch off.
+const MAX_PULSE: Duration = Duration::from_secs(10);
+
+/// The status of an `arb::Error` variant [`Failure::status_and_code`] does not
+/// know yet: what this daemon has not been taught to answer.
+const UNMAPPED: u16 = 501;
+
+/// Why a request was not carried out.
+#[derive(Debug)]
+enum Failure {
+    /// The library refused or failed it.
+    Arb(arb::Error),
+    /// The body, or a number in the route, was not what the route takes.
+    BadRequest(String),
+    /// No route has this shape.
+    NoRoute,
+    /// The route exists, but not for this method.
+    MethodNotAllowed,
+}
+
+impl From<arb::Error> for Failure {
+    fn from(error: arb::Error) -> Self {
+        Failure::Arb(error)
+    }
+}
+
+impl Failure {
+    /// The HTTP status and the machine-readable code this failure answers with.
+    ///
+    /// Each `arb::Error` variant has a status no other variant uses, so a client
+    /// that only looks at the status still tells them apart. The faults of the
+    /// board itself are the 5xx a gateway answers with when what stands behind it
+    /// misbehaves: 502 for an answer of the wrong shape, 503 for a bus that would
+    /// not carry one, and 504 for a board that never settled on what was written,
+    /// however many times it was asked. A variant added after this table was
+    /// written answers [`UNMAPPED`], which no mapped variant uses. The daemon's own
+    /// failures borrow the statuses HTTP gives them and differ by code.
+    fn status_and_code(&self) -> (u16, &'static str) {
+        match self {
+            Failure::Arb(error) => {
+                let status = match error {
+                    arb::Error::MultipleFound => 400,
+                    arb::Error::NotFound => 404,
+                    arb::Error::RegisterOutOfSync { .. } => 409,
+                    arb::Error::InvalidRelay(_) => 422,
+                    arb::Error::Busy => 423,
+                    arb::Error::SelfTestFailed => 424,
+                    arb::Error::VerificationFailed { .. } => 500,
+                    arb::Error::VerificationRetriesExhausted { .. } => 504,
+                    arb::Error::UnexpectedTransferLength { .. } => 502,
+                    arb::Error::Usb(_) => 503,
+                    // A variant added after this table was written, which still
+                    // has a code of its own.
+                    _ => UNMAPPED,
+                };
+
+                (status, error.code())
+            }
+            Failure::BadRequest(_) => (400, "bad_request"),
+            Failure::NoRoute => (404, "no_route"),
+            Failure::MethodNotAllowed => (405, "method_not_allowed"),
+        }
+    }
+
+    fn status(&self) -> u16 {
+        self.status_and_code().0
+    }
+
+    fn body(&self) -> Value {
+        let (_, code) = self.status_and_code();
+
+        let mut error = json!({ "code": code, "message": self.to_string() });
+
+        // A failed verification is the one error a client acts on by value: it
+        // says which relays may now be in which state.
+        match self {
+            Failure::Arb(arb::Error::VerificationFailed { expected, actual }) => {
+                error["expected"] = numbers(*expected);
+                error["actual"] = numbers(*actual);
+            }
+            Failure::Arb(arb::Error::VerificationRetriesExhausted {
+                expected,
+                actual,
+                suspect,
+            }) => {
+                error["expected"] = numbers(*expected);
+                error["actual"] = actual.iter().copied().map(numbers).collect();
+                error["suspect"] = suspect.to_string().into();
+            }
+            _ => {}
+        }
+
+        json!({ "error": error })
+    }
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Failure::Arb(error) => error.fmt(f),
+            Failure::BadRequest(reason) => f.write_str(reason),
+            Failure::NoRoute => f.write_str("no such route"),
+            Failure::MethodNotAllowed => f.write_str("method not allowed on this route"),
+        }
+    }
+}
+
+/// Carries out one request against the boards `usb` finds.
+fn handle(usb: &Usb, method: &Method, url: &str, body: &str) -> Result<Value, Failure> {
+    // No route takes a query; ignoring one beats refusing a cache-buster.
+    let path = url.split('?').next().unwrap_or_default();
+    let route: Vec<&str> = path.split('/').filter(|part| !part.is_empty()).collect();
+
+    match (method, route.as_slice()) {
+        (Method::Get, ["boards"]) => list(usb),
+
+        (Method::Get, ["boards", path, "relays"]) => {
+            let board = find(usb, path)?;
+
+            answer(&board, board.relays()?)
+        }
+
+        (Method::Put, ["boards", path, "relays"]) => {
+            let body = parse(body)?;
+            let board = find(usb, path)?;
+            let relays = relays(&body)?;
+
+            board.set_relays(relays, verify(&body)?)?;
+
+            answer(&board, relays)
+        }
+
+        (Method::Post, ["boards", path, "relays", relay, action]) => {
+            let body = parse(body)?;
+            let relay: Relays = Relay::try_from(number(relay)?)?.into();
+            let verify = verify(&body)?;
+
+            // The duration is checked before the board is looked up, so a pulse
+            // that could never run does not claim a board to find that out.
+            let duration = match *action {
+                "pulse" => Some(duration(&body)?),
+                "on" | "off" | "toggle" => None,
+                _ => return Err(Failure::NoRoute),
+            };
+
+            let board = find(usb, path)?;
+
+            let active = match (*action, duration) {
+                (_, Some(duration)) => board.pulse(relay, duration, verify)?,
+                ("on", _) => board.switch_on(relay, verify)?,
+                ("off", _) => board.switch_off(relay, verify)?,
+                _ => board.toggle(relay, verify)?,
+            };
+
+            answer(&board, active)
+        }
+
+        (Method::Post, ["boards", path, "self-test"]) => {
+            let board = find(usb, path)?;
+
+            answer(&board, board.self_test()?)
+        }
+
+        (_, ["boards"] | ["boards", _, "relays" | "self-test"] | ["boards", _, "relays", _, _]) => {
+            Err(Failure::MethodNotAllowed)
+        }
+
+        _ => Err(Failure::NoRoute),
+    }
+}
+
+/// Every attached board, by path and port.
+fn list(usb: &Usb) -> Result<Value, Failure> {
+    let boards = usb
+        .boards()?
+        .iter()
+        .map(|board| json!({ "path": label(board), "port": board.port() }))
+        .collect();
+
+    Ok(Value::Array(boards))
+}
+
+/// The attached board at `path`, spelled as [`list`] spells it.
+///
+/// Only enumerated boards have paths, and enumeration is what finds them: a board
+/// that is not attached is [`arb::Error::NotFound`], as it would be for any call.
+fn find(usb: &Usb, path: &str) -> Result<Board, Failure> {
+    usb.boards()?
+        .into_iter()
+        .find(|board| label(board) == path)
+        .ok_or(Failure::Arb(arb::Error::NotFound))
+}
+
+fn label(board: &Board) -> String {
+    board.path().map(ToString::to_string).unwrap_or_default()
+}
+
+fn answer(board: &Board, relays: Relays) -> Result<Value, Failure> {
+    Ok(json!({ "board": label(board), "relays": numbers(relays) }))
+}
+
+fn numbers(relays: Relays) -> Value {
+    relays.iter().map(Relay::number).collect()
+}
+
+/// The request body as an object. An empty body is an empty object, so the routes
+/// whose fields are all optional can be called without one.
+fn parse(body: &str) -> Result<Map<String, Value>, Failure> {
+    if body.trim().is_empty() {
+        return Ok(Map::new());
+    }
+
+    match serde_json::from_str(body) {
+        Ok(Value::Object(fields)) => Ok(fields),
+        Ok(_) => Err(Failure::BadRequest("the body is not a JSON object".into())),
+        Err(e) => Err(Failure::BadRequest(format!("the body is not JSON: {e}"))),
+    }
+}
+
+/// A relay number from the route. One that is a number but not a relay is left to
+/// `Relay::try_from`, so it fails the way the library fails it.
+fn number(text: &str) -> Result<u8, Failure> {
+    text.parse()
+        .map_err(|_| Failure::BadRequest(format!("{text:?} is not a relay number")))
+}
+
+fn relays(body: &Map<String, Value>) -> Result<Relays, Failure> {
+    let Some(Value::Array(numbers)) = body.get("relays") else {
+        return Err(Failure::BadRequest(
+            "expected \"relays\": an array of relay numbers".into(),
+        ));
+    };
+
+    let mut relays = Relays::NONE;
+
+    for value in numbers {
+        let number = value
+            .as_u64()
+            .and_then(|number| u8::try_from(number).ok())
+            .ok_or_else(|| Failure::BadRequest(format!("{value} is not a relay number")))?;
+
+        relays.insert(Relay::try_from(number)?);
+    }
+
+    Ok(relays)
+}
+
+fn verify(body: &Map<String, Value>) -> Result<Verify, Failure> {
+    match body.get("verify") {
+        None | Some(Value::Bool(true)) => Ok(Verify::Enabled),
+        Some(Value::Bool(false)) => Ok(Verify::Disabled),
+        Some(_) => Err(Failure::BadRequest(
+            "\"verify\" must be true or false".into(),
+        )),
+    }
+}
+
+fn duration(body: &Map<String, Value>) -> Result<Duration, Failure> {
+    let duration = body
+        .get("duration_ms")
+        .and_then(Value::as_u64)
+        .map(Duration::from_millis)
+        .ok_or_else(|| {
+            Failure::BadRequest("expected \"duration_ms\": a whole number of milliseconds".into())
+        })?;
+
+    if duration > MAX_PULSE {
+        return Err(Failure::BadRequest(format!(
+            "\"duration_ms\" is at most {}: switch on and off instead",
+            MAX_PULSE.as_millis()
+        )));
+    }
+
+    Ok(duration)
+}
+
+#[cfg(all(test, feature = "sim"))]
+mod tests {
+    use super::*;
+    use arb::sim::Fault;
+
+    fn call(usb: &Usb, method: Method, url: &str, body: &str) -> (u16, Value) {
+        match handle(usb, &method, url, body) {
+            Ok(body) => (200, body),
+            Err(failure) => (failure.status(), failure.body()),
+        }
+    }
+
+    #[test]
+    fn boards_are_listed_by_path_and_port() {
+        let (usb, _sims) = Usb::simulated(2);
+
+        let (status, body) = call(&usb, Method::Get, "/boards", "");
+
+        assert_eq!(status, 200);
+        assert_eq!(
+            body,
+            json!([{ "path": "1-1", "port": 1 }, { "path": "1-2", "port": 2 }])
+        );
+    }
+
+    #[test]
+    fn relays_are_read_and_written_by_path() {
+        let (usb, sims) = Usb::simulated(2);
+
+        let (status, body) = call(
+            &usb,
+            Method::Put,
+            "/boards/1-2/relays",
+            r#"{"relays": [1, 3]}"#,
+        );
+
+        assert_eq!(status, 200);
+        assert_eq!(body, json!({ "board": "1-2", "relays": [1, 3] }));
+        assert_eq!(sims[1].outputs(), Relay::One | Relay::Three);
+        assert_eq!(sims[0].outputs(), Relays::NONE);
+
+        let (_, body) = call(&usb, Method::Get, "/boards/1-2/relays", "");
+
+        assert_eq!(body, json!({ "board": "1-2", "relays": [1, 3] }));
+    }
+
+    #[test]
+    fn single_relays_switch_without_touching_the_others() {
+        let (usb, sims) = Usb::simulated(1);
+
+        sims[0].latch(Relay::One.into());
+
+        let (_, body) = call(&usb, Method::Post, "/boards/1-1/relays/3/on", "");
+        assert_eq!(body["relays"], json!([1, 3]));
+
+        let (_, body) = call(&usb, Method::Post, "/boards/1-1/relays/1/toggle", "");
+        assert_eq!(body["relays"], json!([3]));
+
+        let (_, body) = call(&usb, Method::Post, "/boards/1-1/relays/3/off", "");
+        assert_eq!(body["relays"], json!([]));
+
+        sims[0].latch(Relay::Two.into());
+
+        let (status, body) = call(
+            &usb,
+            Method::Post,
+            "/boards/1-1/relays/4/pulse",
+            r#"{"duration_ms": 1}"#,
+        );
+
+        assert_eq!(status, 200);
+        assert_eq!(body["relays"], json!([2]));
+        assert_eq!(sims[0].outputs(), Relay::Two.into());
+    }
+
+    #[test]
+    fn the_self_test_answers_with_the_relays_it_vouched_for() {
+        let (usb, sims) = Usb::simulated(1);
+
+        sims[0].latch(Relay::Eight.into());
+
+        let (status, body) = call(&usb, Method::Post, "/boards/1-1/self-test", "");
+
+        assert_eq!(status, 200);
+        assert_eq!(body["relays"], json!([8]));
+
+        sims[0].set_fault(Some(Fault::DropMsb));
+
+        let (status, body) = call(&usb, Method::Post, "/boards/1-1/self-test", "");
+
+        assert_eq!(status, 424);
+        assert_eq!(body["error"]["code"], "self_test_failed");
+    }
+
+    #[test]
+    fn a_failed_verification_says_what_it_read() {
+        let (usb, sims) = Usb::simulated(1);
+
+        sims[0].set_fault(Some(Fault::StuckLow));
+
+        let (status, body) = call(
+            &usb,
+            Method::Put,
+            "/boards/1-1/relays",
+            r#"{"relays": [2]}"#,
+        );
+
+        assert_eq!(status, 500);
+        assert_eq!(body["error"]["code"], "verification_failed");
+        assert_eq!(body["error"]["expected"], json!([2]));
+        assert_eq!(body["error"]["actual"], json!([]));
+
+        // Unverified, the same write is taken at its word.
+        let (status, _) = call(
+            &usb,
+            Method::Put,
+            "/boards/1-1/relays",
+            r#"{"relays": [2], "verify": false}"#,
+        );
+
+        assert_eq!(status, 200);
+    }
+
+    #[test]
+    fn library_errors_answer_with_their_own_status_and_code() {
+        let (usb, sims) = Usb::simulated(1);
+
+        let (status, body) = call(&usb, Method::Get, "/boards/1-9/relays", "");
+        assert_eq!((status, &body["error"]["code"]), (404, &json!("not_found")));
+
+        let (status, body) = call(&usb, Method::Post, "/boards/1-1/relays/9/on", "");
+        assert_eq!(
+            (status, &body["error"]["code"]),
+            (422, &json!("invalid_relay"))
+        );
+
+        let held = usb.boards().unwrap()[0].session().unwrap();
+
+        let (status, body) = call(&usb, Method::Get, "/boards/1-1/relays", "");
+        assert_eq!((status, &body["error"]["code"]), (423, &json!("busy")));
+
+        drop(held);
+        sims[0].set_fault(Some(Fault::TimeoutAfter {
+            reads: 0,
+            writes: 0,
+        }));
+
+        let (status, body) = call(&usb, Method::Get, "/boards/1-1/relays", "");
+        assert_eq!(
+            (status, &body["error"]["code"]),
+            (409, &json!("register_out_of_sync"))
+        );
+    }
+
+    #[test]
+    fn every_library_error_has_a_status_of_its_own() {
+        let errors = [
+            arb::Error::Usb(rusb::Error::Timeout),
+            arb::Error::NotFound,
+            arb::Error::MultipleFound,
+            arb::Error::Busy,
+            arb::Error::VerificationFailed {
+                expected: Relays::ALL,
+                actual: Relays::NONE,
+            },
+            arb::Error::VerificationRetriesExhausted {
+                expected: Relays::ALL,
+                actual: vec![Relays::NONE],
+                suspect: arb::Suspect::ReadPath,
+            },
+            arb::Error::InvalidRelay(9),
+            arb::Error::UnexpectedTransferLength {
+                expected: 1,
+                actual: 0,
+            },
+            arb::Error::SelfTestFailed,
+            arb::Error::RegisterOutOfSync {
+                source: Box::new(arb::Error::Busy),
+            },
+        ];
+
+        let mut statuses: Vec<_> = errors
+            .into_iter()
+            .map(|e| Failure::Arb(e).status())
+            .collect();
+        statuses.sort_unstable();
+        statuses.dedup();
+
+        assert_eq!(statuses.len(), 10);
+        assert!(!statuses.contains(&UNMAPPED));
+    }
+
+    #[test]
+    fn malformed_requests_are_refused_before_touching_a_board() {
+        let (usb, sims) = Usb::simulated(1);
+
+        sims[0].set_fault(Some(Fault::TimeoutAfter {
+            reads: 0,
+            writes: 0,
+        }));
+
+        for (method, url, body) in [
+            (Method::Put, "/boards/1-1/relays", "not json"),
+            (Method::Put, "/boards/1-1/relays", r#"{"relays": "3"}"#),
+            (
+                Method::Put,
+                "/boards/1-1/relays",
+                r#"{"relays": [3], "verify": 1}"#,
+            ),
+            (Method::Post, "/boards/1-1/relays/x/on", ""),
+            (Method::Post, "/boards/1-1/relays/4/pulse", ""),
+            (
+                Method::Post,
+                "/boards/1-1/relays/4/pulse",
+                r#"{"duration_ms": 10001}"#,
+            ),
+        ] {
+            let (status, body) = call(&usb, method, url, body);
+
+            assert_eq!(status, 400, "{url} {body}");
+            assert_eq!(body["error"]["code"], "bad_request");
+        }
+
+        let (status, _) = call(&usb, Method::Get, "/nowhere", "");
+        assert_eq!(status, 404);
+
+        let (status, _) = call(&usb, Method::Post, "/boards/1-1/relays/4/explode", "");
+        assert_eq!(status, 404);
+
+        let (status, body) = call(&usb, Method::Delete, "/boards/1-1/relays", "");
+        assert_eq!(status, 405);
+        assert_eq!(body["error"]["code"], "method_not_allowed");
+    }
+}
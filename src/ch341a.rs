@@ -4,18 +4,36 @@
 //! - `ENDPOINT_OUT` (0x02): host-to-device commands
 //! - `ENDPOINT_IN` (0x82): device-to-host responses
 //!
-//! Two commands are used:
+//! Three commands are used:
 //! - `0xA1` (set output): sets the state of the D0–D5 GPIO lines, one transfer per
 //!   line change
 //! - `0xAB` (UIO stream): runs a short program of pin states, so that reading a
 //!   whole shift register — sample, clock, sample, … — costs one transfer out and
-//!   one back rather than one transfer per state
+//!   one back rather than one transfer per state. Streams that wait between
+//!   states can write one too, packet after packet in a single transfer
+//! - `0xA8` (SPI stream): clocks bytes through the chip's SPI engine, which drives
+//!   D3 as the clock and D5 as data out and samples D7 as data in — the very lines
+//!   the A6275 is wired to
 //!
 //! Each carries the line directions itself, so opening a board sends nothing.
+//!
+//! The transfers go through [`Bulk`], which libusb's device handle implements; the
+//! tests put an emulated chip behind it instead, which runs those commands byte by
+//! byte against a simulated A6275.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
 
-use std::time::Duration;
+use rusb::UsbContext;
 
 use crate::errors::{Error, Result};
+use crate::find::Path;
+use crate::trace::{Recorder, Trace};
+use crate::transport::{self, Changes, Link, Transport};
+
+#[cfg(test)]
+pub(crate) mod emulator;
 
 /// USB vendor ID for the WCH CH341A chip.
 const VENDOR_ID: u16 = 0x1a86;
@@ -48,13 +66,22 @@ const DRAIN_PACKETS: usize = 4;
 
 // The UIO stream command and the states that may appear in one. A stream is
 // `CMD_UIO_STREAM`, a sequence of states and `UIO_STM_END`, all in a single
-// packet: `OUT` drives the D0–D5 lines, `DIR` claims them as outputs, and `IN`
-// samples D0–D7 and appends one byte to the stream's response.
+// packet: `OUT` drives the D0–D5 lines, `DIR` claims them as outputs, `IN`
+// samples D0–D7 and appends one byte to the stream's response, and `US` holds
+// the lines as they are for up to 63 µs.
 const CMD_UIO_STREAM: u8 = 0xAB;
 const UIO_STM_IN: u8 = 0x00;
 const UIO_STM_END: u8 = 0x20;
 const UIO_STM_DIR: u8 = 0x40;
 const UIO_STM_OUT: u8 = 0x80;
+const UIO_STM_US: u8 = 0xC0;
+
+/// The longest wait one `UIO_STM_US` state holds for, in microseconds.
+const MAX_WAIT_MICROS: u8 = 0x3f;
+
+// The SPI stream command: `CMD_SPI_STREAM` followed by the bytes to send, each of
+// which clocks one byte back. The engine shifts least significant bit first.
+const CMD_SPI_STREAM: u8 = 0xA8;
 
 /// The D0–D5 lines that can be driven, as a direction mask.
 ///
@@ -76,8 +103,8 @@ const MAX_SAMPLES: usize = (PACKET_LENGTH - 4) / STATES_PER_SAMPLE;
 /// How many readings one clocked read takes.
 ///
 /// The fixed width of this driver's only clocked read. What makes eight the right
-/// number belongs to the device being clocked, so [`A6275`](crate::a6275::A6275)
-/// asserts it against the register it folds these samples into.
+/// number belongs to the device being clocked, so the A6275 protocol asserts it
+/// against the register it folds these samples into.
 pub const SAMPLES: usize = 8;
 
 pub type Device = rusb::Device<rusb::Context>;
@@ -118,6 +145,77 @@ fn sample_stream(clock: u8, samples: usize) -> ([u8; PACKET_LENGTH], usize) {
     (packet, end + 1)
 }
 
+/// Encodes the message behind [`Gpio::stream`]: the states `steps` become, cut into
+/// as many UIO streams as it takes, each padded to a packet of its own.
+///
+/// The first stream opens as [`sample_stream`] does, so the steps start from every
+/// line low and claimed. The padding is what keeps the streams apart: the chip
+/// takes each packet as a command of its own, and a stream cannot run on into the
+/// next one.
+///
+/// Returns the message and how many samples each stream that samples takes, in
+/// order. The chip answers each of those streams with an IN packet of its own, and
+/// a short packet ends a transfer, so each answer has to be read by itself.
+pub(crate) fn step_message(steps: &[Step]) -> (Vec<u8>, Vec<usize>) {
+    let mut states = vec![UIO_STM_OUT, UIO_STM_DIR | OUTPUT_LINES];
+
+    for step in steps {
+        match *step {
+            Step::Output(data) => states.push(UIO_STM_OUT | data & OUTPUT_LINES),
+            Step::Sample => states.push(UIO_STM_IN),
+            Step::Wait(mut micros) => {
+                while micros > 0 {
+                    let wait = micros.min(MAX_WAIT_MICROS);
+
+                    states.push(UIO_STM_US | wait);
+                    micros -= wait;
+                }
+            }
+        }
+    }
+
+    let mut msg = Vec::new();
+    let mut answers = Vec::new();
+
+    // Room for the command and the terminator in every packet.
+    for chunk in states.chunks(PACKET_LENGTH - 2) {
+        msg.resize(msg.len().next_multiple_of(PACKET_LENGTH), 0);
+        msg.push(CMD_UIO_STREAM);
+        msg.extend_from_slice(chunk);
+        msg.push(UIO_STM_END);
+
+        match chunk.iter().filter(|&&state| state == UIO_STM_IN).count() {
+            0 => {}
+            samples => answers.push(samples),
+        }
+    }
+
+    (msg, answers)
+}
+
+/// Encodes the message behind [`Gpio::exchange`]: a UIO stream that claims the
+/// lines, as [`sample_stream`] opens with, in a packet of its own, then the SPI
+/// stream carrying `data`.
+///
+/// The SPI engine drives the lines it uses but does not claim them, so on a board
+/// nothing else has touched since it was plugged in, D3 and D5 would still be
+/// inputs. Both packets go in one transfer, as flashrom sends its chip select
+/// with the command it frames.
+fn exchange_message(data: u8) -> [u8; PACKET_LENGTH + 2] {
+    let mut msg = [0u8; PACKET_LENGTH + 2];
+
+    msg[..4].copy_from_slice(&[
+        CMD_UIO_STREAM,
+        UIO_STM_OUT,
+        UIO_STM_DIR | OUTPUT_LINES,
+        UIO_STM_END,
+    ]);
+    msg[PACKET_LENGTH] = CMD_SPI_STREAM;
+    msg[PACKET_LENGTH + 1] = data.reverse_bits();
+
+    msg
+}
+
 /// Returns whether `device` is a CH341A in parallel/GPIO mode.
 pub fn is_ch341a(device: &Device) -> Result<bool> {
     let dd = device.device_descriptor()?;
@@ -125,17 +223,175 @@ pub fn is_ch341a(device: &Device) -> Result<bool> {
     Ok(dd.vendor_id() == VENDOR_ID && dd.product_id() == PRODUCT_ID)
 }
 
+/// The CH341As libusb can see, which is what [`Usb::new`](crate::Usb::new) finds
+/// boards through.
+#[derive(Debug)]
+pub struct Libusb {
+    context: rusb::Context,
+    trace: Option<Trace>,
+}
+
+impl Libusb {
+    /// Initialises libusb.
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            context: rusb::Context::new()?,
+            trace: None,
+        })
+    }
+
+    /// Returns this transport recording every transfer of every board it finds to
+    /// `trace`.
+    pub fn with_trace(self, trace: Trace) -> Self {
+        Self {
+            trace: Some(trace),
+            ..self
+        }
+    }
+}
+
+impl Transport for Libusb {
+    fn devices(&self) -> Result<Vec<Box<dyn transport::Device>>> {
+        let mut found: Vec<Box<dyn transport::Device>> = Vec::new();
+
+        for device in self.context.devices()?.iter() {
+            if !is_ch341a(&device)? {
+                continue;
+            }
+
+            match &self.trace {
+                Some(trace) => found.push(Box::new(Traced {
+                    device,
+                    trace: trace.clone(),
+                })),
+                None => found.push(Box::new(device)),
+            }
+        }
+
+        Ok(found)
+    }
+
+    /// libusb's hotplug callbacks, on the platforms that have them.
+    fn changes(&self) -> Result<Option<Box<dyn Changes>>> {
+        if !rusb::has_hotplug() {
+            return Ok(None);
+        }
+
+        let changed = Arc::new(AtomicBool::new(false));
+        let registration = rusb::HotplugBuilder::new()
+            .vendor_id(VENDOR_ID)
+            .product_id(PRODUCT_ID)
+            .register(self.context.clone(), Box::new(Changed(changed.clone())))?;
+
+        Ok(Some(Box::new(Hotplug {
+            context: self.context.clone(),
+            changed,
+            _registration: registration,
+        })))
+    }
+}
+
+/// Raises its flag whenever libusb reports a CH341A arriving or leaving.
+///
+/// Only a flag: libusb forbids most of its own API inside a hotplug callback, and
+/// working out what changed is left to the enumeration the flag prompts.
+struct Changed(Arc<AtomicBool>);
+
+impl rusb::Hotplug<rusb::Context> for Changed {
+    fn device_arrived(&mut self, _device: Device) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    fn device_left(&mut self, _device: Device) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// A registered hotplug callback, and the context whose events deliver it.
+/// Dropping it deregisters the callback.
+struct Hotplug {
+    context: rusb::Context,
+    changed: Arc<AtomicBool>,
+    _registration: rusb::Registration<rusb::Context>,
+}
+
+impl Changes for Hotplug {
+    /// libusb only runs callbacks from inside its event handling, so waiting is
+    /// handling events until the flag goes up or the time runs out. Events are
+    /// handled at least once, so a zero timeout still asks without blocking.
+    fn wait(&mut self, timeout: Duration) -> Result<bool> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            if self.changed.swap(false, Ordering::Relaxed) {
+                return Ok(true);
+            }
+
+            let left = deadline.saturating_duration_since(Instant::now());
+
+            self.context.handle_events(Some(left))?;
+
+            if left.is_zero() {
+                return Ok(self.changed.swap(false, Ordering::Relaxed));
+            }
+        }
+    }
+}
+
+impl transport::Device for Device {
+    fn path(&self) -> Result<Path> {
+        Path::of(self)
+    }
+
+    fn claim(&self) -> Result<Box<dyn Link>> {
+        Ok(Box::new(Ch341a::open(self)?))
+    }
+}
+
+/// A CH341A whose claims record their transfers to a [`Trace`].
+struct Traced {
+    device: Device,
+    trace: Trace,
+}
+
+impl transport::Device for Traced {
+    fn path(&self) -> Result<Path> {
+        Path::of(&self.device)
+    }
+
+    fn claim(&self) -> Result<Box<dyn Link>> {
+        let recorder = Recorder::new(claim(&self.device)?, self.path()?, self.trace.clone());
+
+        Ok(Box::new(Ch341a::new(recorder)))
+    }
+}
+
+/// One step of a sequence [`Gpio::stream`] runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Step {
+    /// Sets the output lines, as [`Gpio::set_output`] does.
+    Output(u8),
+    /// Reads the D0–D7 input lines, adding one byte to what the stream returns.
+    Sample,
+    /// Holds every line as it is for this many microseconds.
+    Wait(u8),
+}
+
 /// The D0–D7 GPIO lines the A6275 is driven through.
 ///
 /// Setting and reading those lines is all the relay board needs from the CH341A,
 /// so keeping it behind a trait lets the shift register protocol be exercised
-/// against a simulated A6275 instead of real hardware.
+/// against a simulated A6275 instead of real hardware, and lets a
+/// [`Transport`](crate::transport::Transport) carry it somewhere else entirely.
+///
+/// The board wires D0 to the A6275 latch, D3 to its clock, D5 to its serial input
+/// and D7 to its serial output. A transport forwarding these calls needs none of
+/// that; one simulating the board needs all of it.
 pub trait Gpio {
     /// Sets the output lines to `data`, one line per bit.
     ///
-    /// Only D0–D5 are driven (see [`OUTPUT_LINES`]), so bits 6 and 7 of `data`
-    /// change nothing. Which line does what is the protocol layer's business, not
-    /// this one's.
+    /// Only D0–D5 are driven, so bits 6 and 7 of `data` change nothing. Which line
+    /// does what is the protocol layer's business, not this one's.
     fn set_output(&self, data: u8) -> Result<()>;
 
     /// Takes [`SAMPLES`] readings of the D0–D7 input lines, one per pulse of `clock`.
@@ -144,45 +400,136 @@ pub trait Gpio {
     /// the rising edge is sampled once per bit, first bit first. Every line other
     /// than `clock` is held low throughout, and all of them are left low.
     fn sample_clocked(&self, clock: u8) -> Result<[u8; SAMPLES]>;
+
+    /// Clocks `data` out on D5, one bit per pulse of D3, while sampling D7, and
+    /// returns the byte sampled.
+    ///
+    /// Both go most significant bit first, and each bit of the answer is read
+    /// before the rising edge that shifts the next one out, as
+    /// [`sample_clocked`](Gpio::sample_clocked) reads them. A device that shifts
+    /// on that edge therefore hands back all it held while taking in all of
+    /// `data`, in one round trip. Every other line is held low throughout, and all of them
+    /// are left low.
+    ///
+    /// Optional: the CH341A does it in hardware, and a transport with no
+    /// equivalent keeps the default, which fails with `Error::Usb(NotSupported)`.
+    /// Only a board driven with [`Drive::Spi`](crate::Drive::Spi) calls it.
+    fn exchange(&self, data: u8) -> Result<u8> {
+        let _ = data;
+
+        Err(Error::Usb(rusb::Error::NotSupported))
+    }
+
+    /// Runs `steps` in order, starting from every output line low, and returns one
+    /// reading per [`Step::Sample`], in the order they were taken.
+    ///
+    /// The lines are left as the last [`Step::Output`] set them. The steps run back
+    /// to back, as fast as the transport can manage, so a device that needs time
+    /// between two line changes has to be given a [`Step::Wait`] for it.
+    ///
+    /// Optional, as [`exchange`](Gpio::exchange) is, with the same default. Only a
+    /// board driven with [`Drive::Stream`](crate::Drive::Stream) calls it.
+    fn stream(&self, steps: &[Step]) -> Result<Vec<u8>> {
+        let _ = steps;
+
+        Err(Error::Usb(rusb::Error::NotSupported))
+    }
+}
+
+impl<T: Gpio + ?Sized> Gpio for Box<T> {
+    fn set_output(&self, data: u8) -> Result<()> {
+        (**self).set_output(data)
+    }
+
+    fn sample_clocked(&self, clock: u8) -> Result<[u8; SAMPLES]> {
+        (**self).sample_clocked(clock)
+    }
+
+    fn exchange(&self, data: u8) -> Result<u8> {
+        (**self).exchange(data)
+    }
+
+    fn stream(&self, steps: &[Step]) -> Result<Vec<u8>> {
+        (**self).stream(steps)
+    }
+}
+
+/// The two bulk endpoints a [`Ch341a`] talks through, and the port reset beside
+/// them.
+///
+/// Shaped after libusb's device handle, which is the implementation that matters,
+/// so that everything between the [`Gpio`] calls and the wire — the packets, the
+/// length checks, the drain — runs unchanged against anything else that answers
+/// as the chip would.
+pub(crate) trait Bulk: Send {
+    /// Sends `buf` to `endpoint`, and returns how much of it went.
+    fn write_bulk(&self, endpoint: u8, buf: &[u8], timeout: Duration) -> rusb::Result<usize>;
+
+    /// Fills as much of `buf` as `endpoint` has to give, and returns how much.
+    fn read_bulk(&self, endpoint: u8, buf: &mut [u8], timeout: Duration) -> rusb::Result<usize>;
+
+    /// Performs a USB port reset on the device.
+    fn reset(&self) -> rusb::Result<()>;
+}
+
+impl Bulk for DeviceHandle {
+    fn write_bulk(&self, endpoint: u8, buf: &[u8], timeout: Duration) -> rusb::Result<usize> {
+        DeviceHandle::write_bulk(self, endpoint, buf, timeout)
+    }
+
+    fn read_bulk(&self, endpoint: u8, buf: &mut [u8], timeout: Duration) -> rusb::Result<usize> {
+        DeviceHandle::read_bulk(self, endpoint, buf, timeout)
+    }
+
+    fn reset(&self) -> rusb::Result<()> {
+        DeviceHandle::reset(self)
+    }
 }
 
 /// An opened CH341A with its bulk interface claimed.
-pub struct Ch341a {
-    handle: DeviceHandle,
+pub struct Ch341a<B = DeviceHandle> {
+    handle: B,
 }
 
 impl Ch341a {
-    /// Opens `device` and claims its bulk interface.
-    ///
-    /// Any kernel driver bound to the interface is detached when the interface
-    /// is claimed and re-attached when it is released, so the board is left as
-    /// it was found. Platforms whose libusb lacks that capability report
-    /// `NotSupported` and are ignored: rusb then behaves as if the call had
-    /// never been made, and there is no kernel driver to detach there anyway.
-    ///
-    /// The claim is exclusive, so opening a board another application is currently
-    /// talking to fails with [`Error::Busy`].
+    /// Opens `device` and claims its bulk interface, as [`claim`] does.
     pub fn open(device: &Device) -> Result<Self> {
-        let handle = device.open()?;
+        claim(device).map(Self::new)
+    }
+}
 
-        match handle.set_auto_detach_kernel_driver(true) {
-            Ok(()) | Err(rusb::Error::NotSupported) => {}
-            Err(e) => return Err(e.into()),
-        }
+/// Opens `device` and claims its bulk interface.
+///
+/// Any kernel driver bound to the interface is detached when the interface
+/// is claimed and re-attached when it is released, so the board is left as
+/// it was found. Platforms whose libusb lacks that capability report
+/// `NotSupported` and are ignored: rusb then behaves as if the call had
+/// never been made, and there is no kernel driver to detach there anyway.
+///
+/// The claim is exclusive, so opening a board another application is currently
+/// talking to fails with [`Error::Busy`].
+fn claim(device: &Device) -> Result<DeviceHandle> {
+    let handle = device.open()?;
+
+    match handle.set_auto_detach_kernel_driver(true) {
+        Ok(()) | Err(rusb::Error::NotSupported) => {}
+        Err(e) => return Err(e.into()),
+    }
 
-        // `Busy` here means the interface is already claimed, which is contention
-        // rather than a USB fault, so it is reported as its own error.
-        handle.claim_interface(INTERFACE).map_err(|e| match e {
-            rusb::Error::Busy => Error::Busy,
-            e => e.into(),
-        })?;
+    // `Busy` here means the interface is already claimed, which is contention
+    // rather than a USB fault, so it is reported as its own error.
+    handle.claim_interface(INTERFACE).map_err(|e| match e {
+        rusb::Error::Busy => Error::Busy,
+        e => e.into(),
+    })?;
 
-        Ok(Self { handle })
-    }
+    Ok(handle)
+}
 
-    /// Performs a USB port reset on the device.
-    pub fn reset(&self) -> Result<()> {
-        Ok(self.handle.reset()?)
+impl<B: Bulk> Ch341a<B> {
+    /// Talks to the chip through `handle`, which is already open and claimed.
+    pub(crate) fn new(handle: B) -> Self {
+        Self { handle }
     }
 
     /// Sends `msg` to the device, failing if it was not transferred whole.
@@ -216,7 +563,7 @@ impl Ch341a {
     /// the caller does not know. Draining *before* a read instead would pay
     /// [`TIMEOUT_DRAIN`] on every call to find the endpoint empty. Not yet measured
     /// against real hardware: a process killed between its write and its read leaves
-    /// the endpoint dirty either way, and only [`Ch341a::reset`] clears that.
+    /// the endpoint dirty either way, and only [`Link::reset`] clears that.
     fn drain(&self) {
         let mut discard = [0u8; PACKET_LENGTH];
 
@@ -232,7 +579,7 @@ impl Ch341a {
     }
 }
 
-impl Gpio for Ch341a {
+impl<B: Bulk> Gpio for Ch341a<B> {
     fn set_output(&self, data: u8) -> Result<()> {
         #[rustfmt::skip]
         let msg = [
@@ -258,10 +605,55 @@ impl Gpio for Ch341a {
 
         Ok(samples)
     }
+
+    /// One transfer out and one back, draining on failure as a clocked read does:
+    /// the SPI engine queues its answer exactly as a UIO stream does.
+    fn exchange(&self, data: u8) -> Result<u8> {
+        let mut answer = [0u8; 1];
+
+        self.write(&exchange_message(data))
+            .and_then(|()| self.read(&mut answer))
+            .inspect_err(|_| self.drain())?;
+
+        Ok(answer[0].reverse_bits())
+    }
+
+    /// One transfer out, however many packets the steps take, and one back for each
+    /// of them that samples.
+    fn stream(&self, steps: &[Step]) -> Result<Vec<u8>> {
+        let (msg, answers) = step_message(steps);
+        let mut answer = vec![0u8; answers.iter().sum()];
+
+        self.write(&msg)
+            .and_then(|()| {
+                let mut unread = &mut answer[..];
+
+                for &samples in &answers {
+                    let (packet, rest) = unread.split_at_mut(samples);
+
+                    self.read(packet)?;
+                    unread = rest;
+                }
+
+                Ok(())
+            })
+            .inspect_err(|_| self.drain())?;
+
+        Ok(answer)
+    }
+}
+
+impl<B: Bulk> Link for Ch341a<B> {
+    fn reset(&self) -> Result<()> {
+        Ok(self.handle.reset()?)
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::VecDeque;
+    use std::sync::Mutex;
+
     use super::*;
 
     /// A clock line. Which one is the protocol layer's business, not this module's.
@@ -291,6 +683,95 @@ mod tests {
         assert!(len <= PACKET_LENGTH, "{len} bytes exceeds {PACKET_LENGTH}");
     }
 
+    #[test]
+    fn an_exchange_claims_the_lines_then_sends_least_significant_bit_first() {
+        let msg = exchange_message(0b1000_0110);
+
+        assert_eq!(&msg[..4], &[0xAB, 0x80, 0x7f, 0x20]);
+        assert!(msg[4..PACKET_LENGTH].iter().all(|&byte| byte == 0));
+        // The engine shifts bit 0 first and the A6275 wants its bit 7 first.
+        assert_eq!(&msg[PACKET_LENGTH..], &[0xA8, 0b0110_0001]);
+    }
+
+    #[test]
+    fn steps_become_stream_states_after_the_opening_pair() {
+        let (msg, answers) =
+            step_message(&[Step::Output(CLOCK | 0x40), Step::Wait(100), Step::Sample]);
+
+        assert_eq!(answers, [1]);
+        assert_eq!(
+            msg,
+            [
+                0xAB, // UIO stream
+                0x80, // every line low
+                0x7f, // D0–D5 are outputs
+                0x88, // clock high; bit 6 is not a line that can be driven
+                0xff, 0xe5, // 63 µs, then the 37 left over
+                0x00, // read
+                0x20, // end
+            ]
+        );
+    }
+
+    #[test]
+    fn a_long_sequence_is_split_into_one_stream_per_packet() {
+        let (msg, answers) = step_message(&[Step::Output(CLOCK); 40]);
+
+        // 42 states: 30 in the first packet, 12 in the second, and none a sample.
+        assert!(answers.is_empty());
+        assert_eq!(msg.len(), PACKET_LENGTH + 14);
+        assert_eq!(msg[PACKET_LENGTH - 1], 0x20);
+        assert_eq!(msg[PACKET_LENGTH], 0xAB);
+        assert_eq!(msg[PACKET_LENGTH + 1], 0x88);
+        assert_eq!(msg.last(), Some(&0x20));
+    }
+
+    /// A chip that answers each stream in a packet of its own, as the CH341A does:
+    /// a read takes at most one of them, however much room it has.
+    struct Packets(Mutex<VecDeque<Vec<u8>>>);
+
+    impl Bulk for Packets {
+        fn write_bulk(&self, _: u8, buf: &[u8], _: Duration) -> rusb::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn read_bulk(&self, _: u8, buf: &mut [u8], _: Duration) -> rusb::Result<usize> {
+            let packet = self
+                .0
+                .lock()
+                .unwrap()
+                .pop_front()
+                .ok_or(rusb::Error::Timeout)?;
+            let len = packet.len().min(buf.len());
+
+            buf[..len].copy_from_slice(&packet[..len]);
+
+            Ok(len)
+        }
+
+        fn reset(&self) -> rusb::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn a_stream_that_samples_in_several_packets_reads_each_answer_by_itself() {
+        // 42 states, every one after the opening pair a sample: 28 in the first
+        // packet and 12 in the second, each answered on its own.
+        let (_, answers) = step_message(&[Step::Sample; 40]);
+        assert_eq!(answers, [28, 12]);
+
+        let chip = Ch341a::new(Packets(Mutex::new(VecDeque::from([
+            vec![1; 28],
+            vec![2; 12],
+        ]))));
+
+        let samples = chip.stream(&[Step::Sample; 40]).unwrap();
+
+        assert_eq!(samples[..28], [1; 28]);
+        assert_eq!(samples[28..], [2; 12]);
+    }
+
     #[test]
     fn expect_transfer_len_accepts_exact_length() {
         assert!(expect_transfer_len(6, 6).is_ok());
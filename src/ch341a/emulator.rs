@@ -0,0 +1,403 @@
+//! A CH341A emulated byte by byte, for checking what [`Ch341a`] puts on the wire.
+//!
+//! The protocol tests stop at [`Gpio`], so they take the packets built above on
+//! trust: a stream that claimed the lines in the wrong order, or an SPI byte sent
+//! the wrong way round, would pass every one of them. [`Emulator`] takes those
+//! packets as the chip does instead. It runs each command of each packet in a
+//! bulk write, drives a [`FakeA6275`] from the lines they leave, and queues what
+//! each packet samples on the IN endpoint as a packet of its own, as the chip
+//! answers — where it stays until a bulk read takes it, or until a read that never
+//! came leaves it for the next one to find. A read takes one packet at most,
+//! however much room it has: a short packet ends a transfer.
+//!
+//! What it knows of the chip is what this driver uses of it, as the module above
+//! and flashrom describe it. Anything else on the wire panics rather than being
+//! guessed at, since the test that sent it has found a mistake.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+use super::{
+    Bulk, CMD_SPI_STREAM, CMD_UIO_STREAM, Ch341a, ENDPOINT_IN, ENDPOINT_OUT, Gpio, OUTPUT_LINES,
+    PACKET_LENGTH, UIO_STM_DIR, UIO_STM_END, UIO_STM_IN, UIO_STM_OUT, UIO_STM_US,
+};
+use crate::relays::Relays;
+use crate::sim::FakeA6275;
+
+/// The set-output command, which [`Gpio::set_output`] sends as one 11-byte
+/// message.
+const CMD_SET_OUTPUT: u8 = 0xA1;
+/// What follows [`CMD_SET_OUTPUT`] for the parallel port's lines.
+const SET_OUTPUT_PARALLEL: u8 = 0x6a;
+/// The bits of the set-output enable byte that make its D0–D7 data, and its D0–D7
+/// directions, take effect. The others are for lines the board does not use.
+const ENABLE_DATA: u8 = 0x04;
+const ENABLE_DIRECTIONS: u8 = 0x08;
+
+/// The bits of a stream state that say what it is; the rest are its argument.
+const STATE_KIND: u8 = 0xc0;
+
+// The lines the SPI engine uses, whatever is wired to them: D3 clocks, D5 carries
+// the data out and D7 the data in.
+const SPI_CLOCK: u8 = 0x08;
+const SPI_OUT: u8 = 0x20;
+const SPI_IN: u8 = 0x80;
+
+/// The register bit the A6275 presents on its serial output, D7.
+const SERIAL_OUT: u8 = 0x80;
+
+/// The chip, the shift register wired to it and the transfers being tampered with.
+#[derive(Default)]
+struct Chip {
+    a6275: FakeA6275,
+    /// What the output latch holds for D0–D7, driven or not.
+    data: u8,
+    /// Which of D0–D7 are driven.
+    directions: u8,
+    /// What the IN endpoint holds, one answer per packet that sampled, oldest first.
+    queued: VecDeque<Vec<u8>>,
+    /// How many answers bulk reads have taken.
+    answered: usize,
+    /// How much of the next bulk write gets through, if not all of it.
+    cut: Option<usize>,
+    /// How many bulk reads still time out, whatever is queued.
+    stalls: usize,
+}
+
+impl Chip {
+    /// Presents the driven lines to the A6275. A line not driven floats, and the
+    /// board's pull-downs take it low.
+    fn drive(&self) {
+        self.a6275
+            .set_output(self.data & self.directions)
+            .expect("a model never fails");
+    }
+
+    /// What the D0–D7 pins read: the driven lines as driven, and D7 from the
+    /// A6275's serial output.
+    fn sample(&self) -> u8 {
+        let serial_out = self.a6275.register.get() & SERIAL_OUT != 0;
+
+        self.data & self.directions & OUTPUT_LINES | if serial_out { SPI_IN } else { 0 }
+    }
+
+    /// Runs the one command a packet carries.
+    fn run(&mut self, packet: &[u8]) {
+        match packet {
+            [CMD_SET_OUTPUT, rest @ ..] => self.set_output(rest),
+            [CMD_UIO_STREAM, states @ ..] => self.uio_stream(states),
+            [CMD_SPI_STREAM, bytes @ ..] => self.spi_stream(bytes),
+            _ => panic!("no command the chip runs: {packet:02x?}"),
+        }
+    }
+
+    fn set_output(&mut self, message: &[u8]) {
+        let [
+            SET_OUTPUT_PARALLEL,
+            enable,
+            _,
+            _,
+            data,
+            directions,
+            _,
+            _,
+            _,
+            _,
+        ] = *message
+        else {
+            panic!("not a set-output message: {message:02x?}");
+        };
+
+        if enable & ENABLE_DATA != 0 {
+            self.data = data;
+        }
+
+        if enable & ENABLE_DIRECTIONS != 0 {
+            self.directions = directions;
+        }
+
+        self.drive();
+    }
+
+    /// Queues `answer` as one packet on the IN endpoint, unless there is none.
+    fn answer(&mut self, answer: Vec<u8>) {
+        if !answer.is_empty() {
+            self.queued.push_back(answer);
+        }
+    }
+
+    /// Runs states until the terminator, or until the packet runs out: a stream
+    /// cut short runs what reached the chip, and answers with what it sampled.
+    fn uio_stream(&mut self, states: &[u8]) {
+        let mut answer = Vec::new();
+
+        for &state in states {
+            let argument = state & !STATE_KIND;
+
+            match state & STATE_KIND {
+                _ if state == UIO_STM_END => break,
+                _ if state == UIO_STM_IN => answer.push(self.sample()),
+                UIO_STM_DIR => self.directions = self.directions & !OUTPUT_LINES | argument,
+                UIO_STM_OUT => self.data = self.data & !OUTPUT_LINES | argument,
+                // The model settles at once, so a wait changes nothing here.
+                UIO_STM_US => continue,
+                _ => panic!("no stream state the chip runs: {state:02x}"),
+            }
+
+            self.drive();
+        }
+
+        self.answer(answer);
+    }
+
+    /// Clocks each byte out, least significant bit first, sampling before every
+    /// rising edge, and answers with one byte for each, first sample lowest.
+    fn spi_stream(&mut self, bytes: &[u8]) {
+        let mut answers = Vec::new();
+
+        for &byte in bytes {
+            let mut answer = 0;
+
+            for bit in 0..8 {
+                let out = if byte & 1 << bit != 0 { SPI_OUT } else { 0 };
+
+                self.data = self.data & !(SPI_CLOCK | SPI_OUT) | out;
+                self.drive();
+
+                answer |= u8::from(self.sample() & SPI_IN != 0) << bit;
+
+                self.data |= SPI_CLOCK;
+                self.drive();
+            }
+
+            answers.push(answer);
+        }
+
+        self.data &= !(SPI_CLOCK | SPI_OUT);
+        self.drive();
+        self.answer(answers);
+    }
+}
+
+/// A CH341A with an A6275 behind it, as far as its bulk endpoints can tell.
+///
+/// Cheap to clone, and every clone is the same chip: one goes to the [`Ch341a`]
+/// under test, another stays with the test to look at it.
+#[derive(Clone, Default)]
+pub(crate) struct Emulator(Arc<Mutex<Chip>>);
+
+impl Emulator {
+    fn chip(&self) -> MutexGuard<'_, Chip> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns the relays the board's outputs hold.
+    pub(crate) fn outputs(&self) -> Relays {
+        Relays::from_bits(self.chip().a6275.outputs.get())
+    }
+
+    /// Returns the relays the board's shift register holds.
+    pub(crate) fn register(&self) -> Relays {
+        Relays::from_bits(self.chip().a6275.register.get())
+    }
+
+    /// Returns how many bytes the IN endpoint holds that no read has taken.
+    pub(crate) fn queued(&self) -> usize {
+        self.chip().queued.iter().map(Vec::len).sum()
+    }
+
+    /// Returns how many answers bulk reads have taken from the IN endpoint.
+    pub(crate) fn answered(&self) -> usize {
+        self.chip().answered
+    }
+
+    /// Lets only the first `len` bytes of the next bulk write reach the chip, and
+    /// reports that many written, as a transfer cut short on the wire does.
+    pub(crate) fn cut_next_write(&self, len: usize) {
+        self.chip().cut = Some(len);
+    }
+
+    /// Times out the next bulk read without taking anything from the endpoint, as
+    /// a read given up on before the chip answered does.
+    pub(crate) fn stall_next_read(&self) {
+        self.chip().stalls += 1;
+    }
+}
+
+impl Bulk for Emulator {
+    fn write_bulk(&self, endpoint: u8, buf: &[u8], _timeout: Duration) -> rusb::Result<usize> {
+        assert_eq!(endpoint, ENDPOINT_OUT, "written to the IN endpoint");
+
+        let mut chip = self.chip();
+        let len = chip.cut.take().map_or(buf.len(), |cut| cut.min(buf.len()));
+
+        for packet in buf[..len].chunks(PACKET_LENGTH) {
+            chip.run(packet);
+        }
+
+        Ok(len)
+    }
+
+    /// Takes the oldest answer the endpoint holds; a read that finds nothing waits
+    /// for what never comes, and times out. An answer longer than `buf` overflows
+    /// it, as on the wire, and is lost.
+    fn read_bulk(&self, endpoint: u8, buf: &mut [u8], _timeout: Duration) -> rusb::Result<usize> {
+        assert_eq!(endpoint, ENDPOINT_IN, "read from the OUT endpoint");
+
+        let mut chip = self.chip();
+
+        if chip.stalls > 0 {
+            chip.stalls -= 1;
+
+            return Err(rusb::Error::Timeout);
+        }
+
+        let answer = chip.queued.pop_front().ok_or(rusb::Error::Timeout)?;
+
+        chip.answered += 1;
+
+        let into = buf.get_mut(..answer.len()).ok_or(rusb::Error::Overflow)?;
+        into.copy_from_slice(&answer);
+
+        Ok(answer.len())
+    }
+
+    /// Flushes the endpoint and lets go of the lines. The A6275 keeps its register
+    /// and its outputs: it never sees the reset.
+    fn reset(&self) -> rusb::Result<()> {
+        let mut chip = self.chip();
+
+        chip.queued.clear();
+        chip.data = 0;
+        chip.directions = 0;
+        chip.drive();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::a6275::{A6275, CLK};
+    use crate::ch341a::{SAMPLES, sample_stream};
+    use crate::errors::Error;
+    use crate::transport::Link;
+    use crate::{Drive, Relay, Verify};
+
+    fn board(drive: Drive) -> (A6275<Ch341a<Emulator>>, Emulator) {
+        let emulator = Emulator::default();
+        let board = A6275::new(Ch341a::new(emulator.clone())).with_drive(drive);
+
+        (board, emulator)
+    }
+
+    #[test]
+    fn every_drive_works_the_register_through_the_packets_it_sends() {
+        for drive in [Drive::Gpio, Drive::Spi, Drive::Stream] {
+            let (board, emulator) = board(drive);
+            let relays = Relay::Two | Relay::Five | Relay::Eight;
+
+            board.set_status(relays.bits(), Verify::Enabled).unwrap();
+
+            assert_eq!(emulator.outputs(), relays, "{drive:?}");
+            assert_eq!(board.status().unwrap(), relays.bits(), "{drive:?}");
+            assert_eq!(board.self_test().unwrap(), relays.bits(), "{drive:?}");
+            assert_eq!(emulator.register(), relays, "{drive:?}");
+            assert_eq!(emulator.queued(), 0, "{drive:?}");
+        }
+    }
+
+    #[test]
+    fn a_streamed_verify_and_self_test_take_one_answer_per_packet_that_samples() {
+        let (board, emulator) = board(Drive::Stream);
+
+        // Sampling while shifting a register out and back in takes more states
+        // than one packet holds, and the chip answers each packet on its own.
+        for relays in [Relay::One | Relay::Eight, Relays::NONE, Relays::ALL] {
+            let answered = emulator.answered();
+
+            board.set_status(relays.bits(), Verify::Enabled).unwrap();
+
+            assert!(emulator.answered() - answered > 1, "{relays}");
+            assert_eq!(emulator.outputs(), relays);
+
+            let answered = emulator.answered();
+
+            assert_eq!(board.self_test().unwrap(), relays.bits());
+            assert!(emulator.answered() - answered > 1, "{relays}");
+            assert_eq!(emulator.register(), relays);
+            assert_eq!(emulator.queued(), 0);
+        }
+    }
+
+    #[test]
+    fn a_read_left_in_the_endpoint_answers_the_next_one() {
+        let (board, emulator) = board(Drive::Gpio);
+        let ch341a = Ch341a::new(emulator.clone());
+
+        board.set_status(0b0000_0001, Verify::Enabled).unwrap();
+
+        // A process killed between its stream and its read: the samples stay put.
+        let (packet, len) = sample_stream(CLK, SAMPLES);
+
+        ch341a.write(&packet[..len]).unwrap();
+        board.set_status(0b0010_0000, Verify::Disabled).unwrap();
+
+        assert_eq!(emulator.queued(), SAMPLES);
+
+        // The lengths match, so nothing notices the read answering for the one
+        // before it, and its restore puts that stale answer in the register.
+        assert_eq!(board.status().unwrap(), 0b0000_0001);
+        assert_eq!(emulator.register(), Relay::One.into());
+        assert_eq!(emulator.outputs(), Relay::Six.into());
+
+        // Only a reset clears it.
+        ch341a.reset().unwrap();
+
+        assert_eq!(emulator.queued(), 0);
+    }
+
+    #[test]
+    fn a_read_that_timed_out_is_drained_before_the_next() {
+        let (board, emulator) = board(Drive::Gpio);
+
+        board.set_status(0b0000_0100, Verify::Enabled).unwrap();
+        emulator.stall_next_read();
+
+        assert!(matches!(
+            board.status(),
+            Err(Error::RegisterOutOfSync { .. })
+        ));
+        assert_eq!(emulator.queued(), 0);
+
+        board.set_status(0b0000_1000, Verify::Enabled).unwrap();
+
+        assert_eq!(board.status().unwrap(), 0b0000_1000);
+    }
+
+    #[test]
+    fn a_stream_cut_short_runs_what_arrived_and_is_drained() {
+        let (board, emulator) = board(Drive::Gpio);
+
+        board.set_status(0b0000_0010, Verify::Enabled).unwrap();
+
+        // Through the opening states and the first two samples, and no further.
+        emulator.cut_next_write(9);
+
+        let ch341a = Ch341a::new(emulator.clone());
+
+        assert!(matches!(
+            ch341a.sample_clocked(CLK),
+            Err(Error::UnexpectedTransferLength {
+                expected: 28,
+                actual: 9
+            })
+        ));
+        assert_eq!(emulator.queued(), 0);
+
+        // Two bits clocked out, and two zeros in behind them.
+        assert_eq!(board.status().unwrap(), 0b0000_1000);
+    }
+}
@@ -0,0 +1,423 @@
+//! Names for boards and labels for relays, read from a TOML file.
+//!
+//! Relay 3 on `port 3 (1-1.3)` means nothing to the person at the pump, so a
+//! configuration names each board by its path and labels its relays:
+//!
+//! ```toml
+//! [boards.garden]
+//! path = "1-1.3"
+//! relays = { 1 = "pump", 3 = "sprinkler" }
+//!
+//! [boards.hall]
+//! path = "1-2"
+//! relays = { 2 = "door" }
+//! ```
+//!
+//! A label names a relay on one board, so labels are unique across the whole file:
+//! `pump` alone is enough to find both. The path is the `lsusb -t` spelling
+//! `arb --list` prints, and only a path will do: a port number can name two
+//! boards, and a label that switched either of them would be worse than none.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use toml::{Table, Value};
+
+use crate::find::{ParsePathError, Path};
+use crate::relays::Relay;
+use crate::{Board, Usb};
+
+/// Board names and relay labels, resolved against the boards a [`Usb`] finds.
+///
+/// Resolving a name looks nothing up: the [`Board`] it returns is as lazy as any
+/// other, so a configuration can name boards that are not plugged in, and using
+/// one that is not fails as any call on a missing board does.
+///
+/// ```
+/// use arb::{Config, Relay};
+///
+/// let config: Config = r#"
+///     [boards.garden]
+///     path = "1-1.3"
+///     relays = { 1 = "pump" }
+/// "#
+/// .parse()
+/// .unwrap();
+///
+/// let path = "1-1.3".parse().unwrap();
+///
+/// assert_eq!(config.name(&path), Some("garden"));
+/// assert_eq!(config.label(&path, Relay::One), Some("pump"));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    boards: BTreeMap<String, Named>,
+}
+
+/// One named board: where it is, and what its relays are called.
+#[derive(Clone, Debug)]
+struct Named {
+    path: Path,
+    labels: BTreeMap<Relay, String>,
+}
+
+impl Config {
+    /// Reads the configuration in the TOML file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// * [`ConfigError::Read`] — the file could not be read
+    /// * Any other [`ConfigError`] — the file is not a valid configuration
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+
+        fs::read_to_string(path)
+            .map_err(|source| ConfigError::Read {
+                path: path.to_owned(),
+                source,
+            })?
+            .parse()
+    }
+
+    /// Returns the board named `name`, if the configuration names one.
+    pub fn board(&self, usb: &Usb, name: &str) -> Option<Board> {
+        let named = self.boards.get(name)?;
+
+        Some(board(usb, &named.path))
+    }
+
+    /// Returns the relay labelled `label`, and the board it is on.
+    pub fn relay(&self, usb: &Usb, label: &str) -> Option<(Board, Relay)> {
+        self.boards.values().find_map(|named| {
+            let relay = named
+                .labels
+                .iter()
+                .find(|(_, labelled)| *labelled == label)
+                .map(|(relay, _)| *relay)?;
+
+            Some((board(usb, &named.path), relay))
+        })
+    }
+
+    /// Returns the name of the board at `path`, if the configuration names it.
+    pub fn name(&self, path: &Path) -> Option<&str> {
+        self.boards
+            .iter()
+            .find(|(_, named)| named.path == *path)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Returns the label of `relay` on the board at `path`, if it has one.
+    pub fn label(&self, path: &Path, relay: Relay) -> Option<&str> {
+        self.boards
+            .values()
+            .find(|named| named.path == *path)?
+            .labels
+            .get(&relay)
+            .map(String::as_str)
+    }
+}
+
+/// The board at `path`, however it was named.
+fn board(usb: &Usb, path: &Path) -> Board {
+    usb.board_at(path.clone())
+}
+
+/// Reads a configuration from TOML text.
+///
+/// Strict on purpose: a key it does not know is an error rather than ignored, since
+/// a misspelt `relay` table would otherwise leave every label silently missing.
+impl FromStr for Config {
+    type Err = ConfigError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut table: Table = text.parse()?;
+
+        let boards = match table.remove("boards") {
+            None => Table::new(),
+            Some(Value::Table(boards)) => boards,
+            Some(_) => return Err(invalid("`boards` must be a table of boards")),
+        };
+
+        if let Some(key) = table.keys().next() {
+            return Err(invalid(format!("unknown key `{key}`")));
+        }
+
+        let mut config = Config::default();
+        // Which board each path and label already belongs to, for the error that
+        // names both when another claims it.
+        let mut paths = BTreeMap::new();
+        let mut labels = BTreeMap::new();
+
+        for (name, entry) in boards {
+            let named = parse_board(&name, entry)?;
+
+            if let Some(other) = paths.insert(named.path.clone(), name.clone()) {
+                return Err(invalid(format!(
+                    "boards `{other}` and `{name}` both have the path {}",
+                    named.path
+                )));
+            }
+
+            for label in named.labels.values() {
+                if let Some(other) = labels.insert(label.clone(), name.clone()) {
+                    return Err(invalid(format!(
+                        "the label `{label}` is used twice, on boards `{other}` and `{name}`"
+                    )));
+                }
+            }
+
+            config.boards.insert(name, named);
+        }
+
+        Ok(config)
+    }
+}
+
+fn parse_board(name: &str, entry: Value) -> Result<Named, ConfigError> {
+    let Value::Table(mut entry) = entry else {
+        return Err(invalid(format!("board `{name}` must be a table")));
+    };
+
+    let path = match entry.remove("path") {
+        Some(Value::String(path)) => path.parse()?,
+        _ => {
+            return Err(invalid(format!(
+                "board `{name}` needs a `path`, such as \"1-1.3\""
+            )));
+        }
+    };
+
+    let labels = match entry.remove("relays") {
+        None => BTreeMap::new(),
+        Some(Value::Table(relays)) => parse_labels(name, relays)?,
+        Some(_) => {
+            return Err(invalid(format!(
+                "the relays of board `{name}` must be a table, such as {{ 1 = \"pump\" }}"
+            )));
+        }
+    };
+
+    if let Some(key) = entry.keys().next() {
+        return Err(invalid(format!(
+            "board `{name}` has an unknown key `{key}`"
+        )));
+    }
+
+    Ok(Named { path, labels })
+}
+
+fn parse_labels(name: &str, relays: Table) -> Result<BTreeMap<Relay, String>, ConfigError> {
+    let mut labels = BTreeMap::new();
+
+    for (number, label) in relays {
+        let relay = number
+            .parse()
+            .ok()
+            .and_then(|number: u8| Relay::try_from(number).ok())
+            .ok_or_else(|| {
+                invalid(format!(
+                    "board `{name}` labels relay `{number}`, but relays are numbered 1 to 8"
+                ))
+            })?;
+
+        let Value::String(label) = label else {
+            return Err(invalid(format!(
+                "the label of relay {relay} on board `{name}` must be a string"
+            )));
+        };
+
+        // A label that is a number would be read as one wherever relays are given
+        // by number or by name, as they are on the command line.
+        if label.is_empty() || label.bytes().all(|byte| byte.is_ascii_digit()) {
+            return Err(invalid(format!(
+                "relay {relay} on board `{name}` has the label {label:?}, which is not a name"
+            )));
+        }
+
+        labels.insert(relay, label);
+    }
+
+    Ok(labels)
+}
+
+fn invalid(reason: impl Into<String>) -> ConfigError {
+    ConfigError::Invalid(reason.into())
+}
+
+/// Errors reading a [`Config`].
+///
+/// Apart from [`Error`](crate::Error) because none of them has anything to do with
+/// a board: they happen before one is named.
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum ConfigError {
+    /// The file could not be read.
+    #[error("could not read {}: {source}", path.display())]
+    Read {
+        /// The file that was to be read.
+        path: PathBuf,
+        /// Why it could not be.
+        source: io::Error,
+    },
+
+    /// The file is not TOML.
+    #[error(transparent)]
+    Syntax(#[from] toml::de::Error),
+
+    /// A board's path is not spelled as `lsusb -t` spells one.
+    #[error(transparent)]
+    Path(#[from] ParsePathError),
+
+    /// The file is TOML, but not a configuration.
+    #[error("{0}")]
+    Invalid(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Relays, Verify};
+
+    const GARDEN: &str = r#"
+        [boards.garden]
+        path = "1-2"
+        relays = { 1 = "pump", 3 = "sprinkler" }
+
+        [boards.hall]
+        path = "1-1"
+        relays = { 2 = "door" }
+
+        [boards.spare]
+        path = "2-4.1"
+    "#;
+
+    #[test]
+    fn labels_resolve_to_the_board_and_relay_they_name() {
+        let config: Config = GARDEN.parse().unwrap();
+        let (usb, sims) = Usb::simulated(2);
+
+        let (board, relay) = config.relay(&usb, "sprinkler").unwrap();
+
+        assert_eq!(relay, Relay::Three);
+        assert_eq!(board.path(), Some(&Path::new(1, [2])));
+
+        board.switch_on(relay.into(), Verify::Enabled).unwrap();
+
+        assert_eq!(sims[1].outputs(), Relay::Three.into());
+        assert_eq!(sims[0].outputs(), Relays::NONE);
+
+        assert!(config.relay(&usb, "garden").is_none());
+        assert!(config.relay(&usb, "lawnmower").is_none());
+    }
+
+    #[test]
+    fn names_resolve_to_the_board_they_name() {
+        let config: Config = GARDEN.parse().unwrap();
+        let (usb, _sims) = Usb::simulated(2);
+
+        let board = config.board(&usb, "hall").unwrap();
+
+        assert_eq!(board.path(), Some(&Path::new(1, [1])));
+        assert!(config.board(&usb, "door").is_none());
+
+        // Named and not attached: lazy, like every board, until it is used.
+        let spare = config.board(&usb, "spare").unwrap();
+
+        assert!(matches!(spare.relays(), Err(crate::Error::NotFound)));
+    }
+
+    #[test]
+    fn paths_are_named_and_relays_labelled() {
+        let config: Config = GARDEN.parse().unwrap();
+        let garden = Path::new(1, [2]);
+
+        assert_eq!(config.name(&garden), Some("garden"));
+        assert_eq!(config.name(&Path::new(2, [4, 1])), Some("spare"));
+        assert_eq!(config.name(&Path::new(3, [1])), None);
+
+        assert_eq!(config.label(&garden, Relay::One), Some("pump"));
+        assert_eq!(config.label(&garden, Relay::Two), None);
+        assert_eq!(config.label(&Path::new(2, [4, 1]), Relay::One), None);
+    }
+
+    #[test]
+    fn an_empty_file_is_an_empty_configuration() {
+        let config: Config = "".parse().unwrap();
+
+        assert_eq!(config.name(&Path::new(1, [1])), None);
+    }
+
+    #[test]
+    fn a_configuration_that_does_not_make_sense_is_rejected() {
+        for (text, reason) in [
+            ("boards = 3", "`boards` must be a table"),
+            ("[board.garden]\npath = \"1-2\"", "unknown key `board`"),
+            ("[boards.garden]", "needs a `path`"),
+            ("[boards.garden]\npath = 12", "needs a `path`"),
+            (
+                "[boards.garden]\npath = \"1-2\"\nrelay = {}",
+                "unknown key `relay`",
+            ),
+            (
+                "[boards.garden]\npath = \"1-2\"\nrelays = 1",
+                "must be a table",
+            ),
+            (
+                "[boards.garden]\npath = \"1-2\"\nrelays = { 9 = \"pump\" }",
+                "numbered 1 to 8",
+            ),
+            (
+                "[boards.garden]\npath = \"1-2\"\nrelays = { one = \"pump\" }",
+                "numbered 1 to 8",
+            ),
+            (
+                "[boards.garden]\npath = \"1-2\"\nrelays = { 1 = 2 }",
+                "must be a string",
+            ),
+            (
+                "[boards.garden]\npath = \"1-2\"\nrelays = { 1 = \"3\" }",
+                "not a name",
+            ),
+            (
+                "[boards.a]\npath = \"1-2\"\n[boards.b]\npath = \"1-2\"",
+                "both have the path 1-2",
+            ),
+            (
+                "[boards.a]\npath = \"1-1\"\nrelays = { 1 = \"pump\" }\n\
+                 [boards.b]\npath = \"1-2\"\nrelays = { 2 = \"pump\" }",
+                "`pump` is used twice",
+            ),
+        ] {
+            let error = text.parse::<Config>().unwrap_err();
+
+            assert!(matches!(error, ConfigError::Invalid(_)), "{text}: {error}");
+            assert!(error.to_string().contains(reason), "{text}: {error}");
+        }
+    }
+
+    #[test]
+    fn a_bad_path_or_bad_toml_says_which() {
+        let error = "[boards.garden]\npath = \"1.2\""
+            .parse::<Config>()
+            .unwrap_err();
+
+        assert!(matches!(error, ConfigError::Path(_)));
+        assert!(error.to_string().contains("\"1.2\""));
+
+        let error = "[boards.garden".parse::<Config>().unwrap_err();
+
+        assert!(matches!(error, ConfigError::Syntax(_)));
+    }
+
+    #[test]
+    fn a_missing_file_names_itself() {
+        let error = Config::load("/nonexistent/arb.toml").unwrap_err();
+
+        assert!(matches!(error, ConfigError::Read { .. }));
+        assert!(error.to_string().contains("/nonexistent/arb.toml"));
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt;
+
 use thiserror::Error;
 
 use crate::relays::Relays;
@@ -60,6 +62,28 @@ pub enum Error {
         actual: Relays,
     },
 
+    /// [`Verify::Retry`](crate::Verify::Retry) wrote the relays as many times as it
+    /// was allowed to, and every read-back disagreed.
+    ///
+    /// The relays' physical state is as unknown as after
+    /// [`Error::VerificationFailed`], but there is more to go on: every value read
+    /// back, in order, and what the self-test run afterwards made of the board. A
+    /// read-back that wanders from one attempt to the next points at a marginal
+    /// connection; one that comes back the same every time, at something stuck.
+    #[error(
+        "verification failed {} times: expected {expected}, read back {}; the {suspect} is suspect",
+        .actual.len(),
+        listed(.actual)
+    )]
+    VerificationRetriesExhausted {
+        /// The relays that were requested.
+        expected: Relays,
+        /// What the shift register reported after each attempt, first attempt first.
+        actual: Vec<Relays>,
+        /// Which half of the board the self-test points at.
+        suspect: Suspect,
+    },
+
     /// A relay number outside the 1–8 range the board provides.
     #[error("invalid relay: expected a number between 1 and 8, got {0}")]
     InvalidRelay(u8),
@@ -104,7 +128,117 @@ pub enum Error {
     },
 }
 
+/// Which half of a board [`Error::VerificationRetriesExhausted`] points at, by
+/// what the self-test made of it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Suspect {
+    /// The self-test failed: the register cannot carry a pattern back out, so it
+    /// is the reads that were wrong, and the relays most likely hold what was
+    /// asked of them.
+    ReadPath,
+    /// The self-test passed: the register carries a pattern in and back out on its
+    /// own, so the fault shows only when the relays are written and latched, and
+    /// they may hold anything.
+    WritePath,
+}
+
+impl fmt::Display for Suspect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Suspect::ReadPath => "read path",
+            Suspect::WritePath => "write path",
+        })
+    }
+}
+
+/// Every read-back of a retried verification, in order.
+fn listed(reads: &[Relays]) -> String {
+    reads
+        .iter()
+        .map(ToString::to_string)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
 impl Error {
+    /// Whether the same call could succeed if tried again, as it is.
+    ///
+    /// True for [`Error::Busy`], which is another application holding the board,
+    /// and for a USB transfer that timed out without costing the register: one that
+    /// did cost it arrives as [`Error::RegisterOutOfSync`], for which this is false.
+    /// These are the failures a [`RetryPolicy`](crate::RetryPolicy) retries.
+    pub fn is_retryable(&self) -> bool {
+        matches!(self, Error::Busy | Error::Usb(rusb::Error::Timeout))
+    }
+
+    /// Whether the way back to a board that can be trusted is writing its relays
+    /// again with [`set_relays`](crate::Board::set_relays).
+    ///
+    /// True where the board's account of its relays can no longer be taken at its
+    /// word: [`Error::RegisterOutOfSync`], after which a read reports what the
+    /// register holds rather than what the relays do, and
+    /// [`Error::VerificationFailed`] and [`Error::VerificationRetriesExhausted`],
+    /// after which nothing says which of the values written and read back the
+    /// relays hold. Reading again helps with none of them.
+    pub fn requires_rewrite(&self) -> bool {
+        matches!(
+            self,
+            Error::RegisterOutOfSync { .. }
+                | Error::VerificationFailed { .. }
+                | Error::VerificationRetriesExhausted { .. }
+        )
+    }
+
+    /// Whether the relays are known to hold what they held before the failed call,
+    /// or for a write, what it latched.
+    ///
+    /// False for [`Error::VerificationFailed`], which latched before it found the
+    /// mismatch, and for any transfer failure, which cannot say whether it stopped
+    /// before the latch or after it. Conservative on purpose: a failure that cannot
+    /// say where the call stopped is taken to have stopped anywhere.
+    ///
+    /// True for [`Error::RegisterOutOfSync`], which moves no relay of its own,
+    /// though the board can no longer report them: see
+    /// [`requires_rewrite`](Error::requires_rewrite).
+    pub fn relay_state_known(&self) -> bool {
+        match self {
+            Error::NotFound
+            | Error::MultipleFound
+            | Error::Busy
+            | Error::InvalidRelay(_)
+            | Error::SelfTestFailed
+            | Error::RegisterOutOfSync { .. } => true,
+            Error::Usb(_)
+            | Error::VerificationFailed { .. }
+            | Error::VerificationRetriesExhausted { .. }
+            | Error::UnexpectedTransferLength { .. } => false,
+        }
+    }
+
+    /// A short, stable name for this kind of failure, for logs, protocols and
+    /// anything else that has to tell failures apart without parsing messages.
+    ///
+    /// One per variant, in snake case: `usb`, `not_found`, `multiple_found`,
+    /// `busy`, `verification_failed`, `verification_retries_exhausted`,
+    /// `invalid_relay`, `unexpected_transfer_length`, `self_test_failed` and
+    /// `register_out_of_sync`. A code, once given, is never changed or reused; new
+    /// variants get new ones.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::Usb(_) => "usb",
+            Error::NotFound => "not_found",
+            Error::MultipleFound => "multiple_found",
+            Error::Busy => "busy",
+            Error::VerificationFailed { .. } => "verification_failed",
+            Error::VerificationRetriesExhausted { .. } => "verification_retries_exhausted",
+            Error::InvalidRelay(_) => "invalid_relay",
+            Error::UnexpectedTransferLength { .. } => "unexpected_transfer_length",
+            Error::SelfTestFailed => "self_test_failed",
+            Error::RegisterOutOfSync { .. } => "register_out_of_sync",
+        }
+    }
+
     /// Reports `source` as having left the shift register out of sync.
     ///
     /// Conservative on purpose: a stream that failed on its way out may never have
@@ -116,3 +250,76 @@ impl Error {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn every_variant() -> Vec<Error> {
+        vec![
+            Error::Usb(rusb::Error::Timeout),
+            Error::NotFound,
+            Error::MultipleFound,
+            Error::Busy,
+            Error::VerificationFailed {
+                expected: Relays::ALL,
+                actual: Relays::NONE,
+            },
+            Error::VerificationRetriesExhausted {
+                expected: Relays::ALL,
+                actual: vec![Relays::NONE],
+                suspect: Suspect::ReadPath,
+            },
+            Error::InvalidRelay(9),
+            Error::UnexpectedTransferLength {
+                expected: 1,
+                actual: 0,
+            },
+            Error::SelfTestFailed,
+            Error::out_of_sync(Error::Usb(rusb::Error::Timeout)),
+        ]
+    }
+
+    #[test]
+    fn every_variant_has_a_code_of_its_own() {
+        let mut codes: Vec<_> = every_variant().iter().map(Error::code).collect();
+        codes.sort_unstable();
+        codes.dedup();
+
+        assert_eq!(codes.len(), every_variant().len());
+    }
+
+    #[test]
+    fn a_lost_register_is_rewritten_rather_than_retried() {
+        let lost = Error::out_of_sync(Error::Usb(rusb::Error::Timeout));
+
+        assert!(!lost.is_retryable());
+        assert!(lost.requires_rewrite());
+        assert!(lost.relay_state_known());
+    }
+
+    #[test]
+    fn only_a_busy_board_or_a_timeout_is_worth_retrying() {
+        let retryable: Vec<_> = every_variant()
+            .into_iter()
+            .filter(Error::is_retryable)
+            .map(|e| e.code())
+            .collect();
+
+        assert_eq!(retryable, ["usb", "busy"]);
+        assert!(!Error::Usb(rusb::Error::Access).is_retryable());
+    }
+
+    #[test]
+    fn a_failed_verification_leaves_the_relays_in_doubt() {
+        let failed = Error::VerificationFailed {
+            expected: Relays::ALL,
+            actual: Relays::NONE,
+        };
+
+        assert!(failed.requires_rewrite());
+        assert!(!failed.relay_state_known());
+        assert!(Error::SelfTestFailed.relay_state_known());
+        assert!(!Error::SelfTestFailed.requires_rewrite());
+    }
+}
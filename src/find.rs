@@ -6,18 +6,21 @@
 
 use std::collections::BTreeMap;
 use std::fmt;
-
-use rusb::UsbContext;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
 
 use crate::ch341a;
 use crate::errors::{Error, Result};
+use crate::transport::{Changes, Device, Link, Transport};
 
 /// Where a board sits on the USB tree: its bus, and the hub ports leading down to it.
 ///
-/// The last hop is the board's port number, which is what [`Select::Port`] matches
-/// on. That number is only unique among the ports of one hub, so two boards behind
-/// two hubs can share it; the whole path never collides, which is what lets
-/// enumeration hand back selectors that always resolve to the board they came from.
+/// The last hop is the board's port number, which is what `usb.board(Some(port))`
+/// matches on. That number is only unique among the ports of one hub, so two
+/// boards behind two hubs can share it; the whole path never collides, which is
+/// what lets enumeration hand back selectors that always resolve to the board
+/// they came from.
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Path {
     bus: u8,
@@ -28,8 +31,15 @@ pub struct Path {
 }
 
 impl Path {
-    /// Builds a path on `bus` from the hub ports leading down to the device.
-    fn new(bus: u8, hops: impl Into<Vec<u8>>) -> Self {
+    /// Builds a path on `bus` from the hub ports leading down to the device, root
+    /// hub first: `Path::new(1, [1, 3])` is `1-1.3`.
+    ///
+    /// ```
+    /// use arb::Path;
+    ///
+    /// assert_eq!(Path::new(1, [1, 3]).to_string(), "1-1.3");
+    /// ```
+    pub fn new(bus: u8, hops: impl Into<Vec<u8>>) -> Self {
         Self {
             bus,
             hops: hops.into(),
@@ -37,14 +47,25 @@ impl Path {
     }
 
     /// Where `device` sits on the USB tree.
-    fn of(device: &ch341a::Device) -> Result<Self> {
+    pub(crate) fn of(device: &ch341a::Device) -> Result<Self> {
         Ok(Self::new(device.bus_number(), device.port_numbers()?))
     }
 
-    /// The board's port on the hub it is plugged into.
+    /// Returns the number of the bus the board is on.
+    pub fn bus(&self) -> u8 {
+        self.bus
+    }
+
+    /// Returns the hub ports leading down to the board, root hub first. The last
+    /// is the board's own [`port`](Path::port).
+    pub fn hops(&self) -> &[u8] {
+        &self.hops
+    }
+
+    /// Returns the board's port on the hub it is plugged into.
     ///
     /// Only a root hub has no port at all, and a root hub is never a relay board.
-    fn port(&self) -> Option<u8> {
+    pub fn port(&self) -> Option<u8> {
         self.hops.last().copied()
     }
 }
@@ -67,6 +88,59 @@ impl fmt::Display for Path {
     }
 }
 
+/// Reads the `lsusb -t` spelling back: `1-1.3` is [`Path::new(1, [1, 3])`](Path::new).
+///
+/// The inverse of [`Display`](fmt::Display), so a path printed by `arb --list` can
+/// be written into a configuration file and name the same board. Only that
+/// spelling is read: a field with a sign or a leading zero, such as `01-+3`, would
+/// not print back the way it was written, and is refused.
+///
+/// ```
+/// use arb::Path;
+///
+/// let path: Path = "1-1.3".parse().unwrap();
+///
+/// assert_eq!(path, Path::new(1, [1, 3]));
+/// assert!("1.3".parse::<Path>().is_err());
+/// ```
+impl FromStr for Path {
+    type Err = ParsePathError;
+
+    fn from_str(text: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || ParsePathError(text.to_owned());
+
+        // Digits only, as `Display` writes them: `u8::from_str` would also take a
+        // sign and leading zeros.
+        let number = |field: &str| match field.as_bytes() {
+            [b'0', _, ..] => Err(invalid()),
+            digits if !digits.is_empty() && digits.iter().all(u8::is_ascii_digit) => {
+                field.parse().map_err(|_| invalid())
+            }
+            _ => Err(invalid()),
+        };
+
+        let (bus, hops) = text.split_once('-').ok_or_else(invalid)?;
+        let bus = number(bus)?;
+        let hops = hops
+            .split('.')
+            .map(number)
+            .collect::<std::result::Result<Vec<u8>, _>>()?;
+
+        // As deep as libusb goes: a longer path names nothing it could report.
+        if hops.len() > 7 {
+            return Err(invalid());
+        }
+
+        Ok(Self::new(bus, hops))
+    }
+}
+
+/// A [`Path`] that could not be read, because it was not written as `lsusb -t`
+/// writes one.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("invalid board path {0:?}: expected the `lsusb -t` spelling, such as 1-1.3")]
+pub struct ParsePathError(String);
+
 /// Which board a [`Board`](crate::Board) is a handle to.
 ///
 /// Crate-private, which is what let it widen to whole paths without touching the
@@ -77,8 +151,8 @@ pub enum Select {
     Any,
     /// The board on this port of whatever hub it hangs off — `usb.board(Some(port))`.
     Port(u8),
-    /// One specific board, wherever it is. Only [`Usb::boards`](crate::Usb::boards)
-    /// builds these.
+    /// One specific board, wherever it is — `usb.board_at(path)`, and every board
+    /// [`Usb::boards`](crate::Usb::boards) returns.
     Path(Path),
 }
 
@@ -92,6 +166,14 @@ impl Select {
         }
     }
 
+    /// The path this names, if it names one.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Select::Path(path) => Some(path),
+            Select::Any | Select::Port(_) => None,
+        }
+    }
+
     /// The port this names, if it names one.
     pub fn port(&self) -> Option<u8> {
         match self {
@@ -122,21 +204,19 @@ impl fmt::Display for Select {
 /// enumeration that reshuffled between calls would make `boards()[0]` a different
 /// board each time. Keying by path makes that ordering a property of the type
 /// rather than a sort that can be deleted without a test noticing.
-pub fn find_devices(context: &rusb::Context) -> Result<BTreeMap<Path, ch341a::Device>> {
+pub fn find_devices(transport: &dyn Transport) -> Result<BTreeMap<Path, Box<dyn Device>>> {
     let mut found = BTreeMap::new();
 
-    for device in context.devices()?.iter() {
-        if ch341a::is_ch341a(&device)? {
-            found.insert(Path::of(&device)?, device);
-        }
+    for device in transport.devices()? {
+        found.insert(device.path()?, device);
     }
 
     Ok(found)
 }
 
 /// The one attached board `select` names.
-pub fn find_device(context: &rusb::Context, select: &Select) -> Result<ch341a::Device> {
-    let mut matching = find_devices(context)?
+pub fn find_device(transport: &dyn Transport, select: &Select) -> Result<Box<dyn Device>> {
+    let mut matching = find_devices(transport)?
         .into_iter()
         .filter(|(path, _)| select.matches(path))
         .map(|(_, device)| device);
@@ -150,6 +230,102 @@ pub fn find_device(context: &rusb::Context, select: &Select) -> Result<ch341a::D
     Ok(device)
 }
 
+/// The device a board was last found at, shared by every clone of that board.
+///
+/// Finding a board walks every USB device on the host, which on a busy host costs
+/// more than the call it serves. Once it has been found there is nothing more
+/// enumeration could say until the bus changes — and claiming a device that has
+/// gone away fails with `NoDevice` or `NotFound`, which is the cue to look again.
+///
+/// A path names at most one device, so for a board named by path that is the
+/// whole story. A board named by port, or by nothing, can also become ambiguous:
+/// a second board answering to the same name arrives, and only enumerating tells
+/// [`Error::MultipleFound`]. So those are only cached where the transport reports
+/// [changes](Transport::changes), and are looked for again after any; on a
+/// transport that cannot report them, they enumerate on every call.
+#[derive(Clone, Default)]
+pub struct Resolved(Arc<Mutex<Option<Cached>>>);
+
+/// A device found, and for a board that needs it, word of the bus changing since.
+struct Cached {
+    device: Arc<dyn Device>,
+    changes: Option<Box<dyn Changes>>,
+}
+
+impl Resolved {
+    /// Claims the board `select` names, through the cached device if there is one.
+    pub fn claim(&self, transport: &dyn Transport, select: &Select) -> Result<Box<dyn Link>> {
+        // Cloned out rather than claimed under the lock: a claim is a USB round
+        // trip, and clones on other threads have no reason to queue behind it.
+        if let Some(device) = self.current() {
+            match device.claim() {
+                Err(Error::Usb(rusb::Error::NoDevice | rusb::Error::NotFound)) => {}
+                claimed => return claimed,
+            }
+        }
+
+        // Listening starts before the enumeration, so a board arriving in between
+        // is still heard of. A transport that cannot listen leaves nothing to cache.
+        let changes = match select {
+            Select::Path(_) => None,
+            Select::Any | Select::Port(_) => match transport.changes() {
+                Ok(Some(changes)) => Some(changes),
+                Ok(None) | Err(_) => {
+                    *self.cache() = None;
+
+                    return find_device(transport, select)?.claim();
+                }
+            },
+        };
+
+        let device: Arc<dyn Device> = match find_device(transport, select) {
+            Ok(device) => device.into(),
+            Err(e) => {
+                *self.cache() = None;
+                return Err(e);
+            }
+        };
+
+        *self.cache() = Some(Cached {
+            device: device.clone(),
+            changes,
+        });
+
+        device.claim()
+    }
+
+    /// The cached device, unless the bus has changed since it was found. A change
+    /// that cannot be asked about is taken as one.
+    fn current(&self) -> Option<Arc<dyn Device>> {
+        let mut cache = self.cache();
+
+        let changed = match cache.as_mut().and_then(|cached| cached.changes.as_mut()) {
+            Some(changes) => !matches!(changes.wait(Duration::ZERO), Ok(false)),
+            None => false,
+        };
+
+        if changed {
+            *cache = None;
+        }
+
+        cache.as_ref().map(|cached| cached.device.clone())
+    }
+
+    /// The cache. Nothing is ever left half written under the lock, so poisoning
+    /// is ignored.
+    fn cache(&self) -> MutexGuard<'_, Option<Cached>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl fmt::Debug for Resolved {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Resolved")
+            .field(&self.cache().is_some())
+            .finish()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -232,10 +408,74 @@ mod tests {
         assert_eq!(Path::new(2, [1, 2, 3]).to_string(), "2-1.2.3");
     }
 
+    #[test]
+    fn a_path_reports_its_parts() {
+        let path = Path::new(2, [1, 3]);
+
+        assert_eq!(path.bus(), 2);
+        assert_eq!(path.hops(), [1, 3]);
+        assert_eq!(path.port(), Some(3));
+    }
+
+    #[test]
+    fn a_path_reads_back_the_way_it_renders() {
+        for path in [
+            Path::new(1, [3]),
+            Path::new(1, [1, 3]),
+            Path::new(2, [1, 2, 3]),
+            Path::new(255, [1, 2, 3, 4, 5, 6, 7]),
+        ] {
+            assert_eq!(path.to_string().parse(), Ok(path));
+        }
+    }
+
+    #[test]
+    fn a_path_not_spelled_as_lsusb_spells_it_is_rejected() {
+        for text in [
+            "",
+            "1",
+            "1-",
+            "-3",
+            "1-1.",
+            "1-.3",
+            "1-1..3",
+            "1.3",
+            "1-1-3",
+            "x-3",
+            "1-3x",
+            "256-1",
+            "1-256",
+            " 1-3",
+            "1-+3",
+            "+1-3",
+            "01-3",
+            "1-03",
+            "1-1.03",
+            "1-00",
+            "1-1.2.3.4.5.6.7.8",
+        ] {
+            assert_eq!(
+                text.parse::<Path>(),
+                Err(ParsePathError(text.to_owned())),
+                "{text:?}"
+            );
+        }
+    }
+
     #[test]
     fn a_board_reports_the_port_it_names() {
         assert_eq!(Select::Any.port(), None);
         assert_eq!(Select::Port(3).port(), Some(3));
         assert_eq!(Select::Path(Path::new(1, [1, 3])).port(), Some(3));
     }
+
+    #[test]
+    fn only_a_path_selector_reports_a_path() {
+        assert_eq!(Select::Any.path(), None);
+        assert_eq!(Select::Port(3).path(), None);
+        assert_eq!(
+            Select::Path(Path::new(1, [1, 3])).path(),
+            Some(&Path::new(1, [1, 3]))
+        );
+    }
 }
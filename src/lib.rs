@@ -57,20 +57,50 @@
 //! board.set_relays(Relays::NONE, Verify::Enabled).unwrap();
 //! ```
 
+use std::collections::BTreeMap;
 use std::fmt;
+use std::panic;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
 mod a6275;
+#[cfg(feature = "async")]
+mod asynchronous;
 mod ch341a;
+#[cfg(feature = "config")]
+mod config;
 mod errors;
 mod find;
+mod reconcile;
+mod recovery;
 mod relays;
+mod retry;
+mod session;
+#[cfg(feature = "sim")]
+pub mod sim;
+#[cfg(all(test, not(feature = "sim")))]
+mod sim;
+pub mod trace;
+pub mod transport;
+mod watch;
 
-use self::a6275::A6275;
-use self::ch341a::Ch341a;
-use self::find::{Select, find_device, find_devices};
+use self::ch341a::Libusb;
+use self::find::{Resolved, Select, find_device, find_devices};
+use self::transport::{Link, Transport};
 
-pub use self::errors::{Error, Result};
+#[cfg(feature = "async")]
+pub use self::asynchronous::{AsyncBoard, AsyncUsb};
+#[cfg(feature = "config")]
+pub use self::config::{Config, ConfigError};
+pub use self::errors::{Error, Result, Suspect};
+pub use self::find::{ParsePathError, Path};
+pub use self::reconcile::{Drift, Reconciler};
+pub use self::recovery::{Outcome, Recovering};
 pub use self::relays::{Relay, RelayIter, Relays};
+pub use self::retry::RetryPolicy;
+pub use self::session::Session;
+pub use self::watch::{BoardEvent, Watch};
 
 /// Whether [`Board::set_relays`] reads the shift register back to confirm the write.
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
@@ -80,6 +110,50 @@ pub enum Verify {
     Enabled,
     /// Latch the relays without confirming.
     Disabled,
+    /// Read the shift register back, and on a mismatch write and read back again,
+    /// up to `attempts` times in all.
+    ///
+    /// For a board whose connection is marginal rather than dead, where a second
+    /// write often lands. Once the attempts run out, runs
+    /// [`self_test`](Board::self_test) to say whether the reads or the writes were
+    /// at fault, and fails with [`Error::VerificationRetriesExhausted`], carrying
+    /// every value read back. Zero attempts is taken as one.
+    Retry {
+        /// How many times to write and read back, at most, the first included.
+        attempts: u32,
+    },
+}
+
+/// How the CH341A drives a board's shift register.
+///
+/// Every drive speaks the same A6275 protocol, reports the same errors and leaves
+/// the board in the same state; what differs is what a call costs on the wire.
+/// Chosen for every board of a context with [`Usb::with_drive`], or for one board
+/// with [`Board::with_drive`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Drive {
+    /// One `0xA1` transfer per line change: 28 transfers to write the register and
+    /// latch it, 56 to verify the write as well, 28 to read it.
+    #[default]
+    Gpio,
+    /// The CH341A's hardware SPI engine, on the same D3, D5 and D7 lines: 4
+    /// transfers to write and latch, 6 to verify as well, 4 to read.
+    ///
+    /// Needs a transport that implements [`Gpio::exchange`](transport::Gpio::exchange);
+    /// on one that does not, every call fails with `Error::Usb(NotSupported)`. Not
+    /// yet measured against real hardware, so check it with [`Board::self_test`]
+    /// before trusting a board to it.
+    Spi,
+    /// The same line changes as [`Drive::Gpio`], sent as one UIO stream that waits
+    /// for the lines to settle between them: 1 transfer to write and latch, 4 to
+    /// verify as well, 3 to read.
+    ///
+    /// Needs a transport that implements [`Gpio::stream`](transport::Gpio::stream),
+    /// and otherwise fails as [`Drive::Spi`] does. Keeps each line change as far
+    /// from the next as the bit-banged writes have always had them, but the waits
+    /// have not been tried against real hardware either.
+    Stream,
 }
 
 /// A libusb context: how relay boards are found.
@@ -89,6 +163,10 @@ pub enum Verify {
 /// share across threads. It claims nothing and opens nothing, so contexts never
 /// conflict with each other or with another application using the same board.
 ///
+/// libusb is only the default: [`Usb::with_transport`] finds boards through any
+/// other [`Transport`], and `Usb::simulated`, with the `sim` feature, through
+/// simulated ones.
+///
 /// # Recovering from a soured context
 ///
 /// A held context is not self-healing: if the USB controller resets or the host
@@ -115,7 +193,11 @@ pub enum Verify {
 /// let relays = usb.board(None).relays().unwrap();
 /// ```
 #[derive(Clone, Debug)]
-pub struct Usb(rusb::Context);
+pub struct Usb {
+    transport: Arc<dyn Transport>,
+    drive: Drive,
+    retry: RetryPolicy,
+}
 
 impl Usb {
     /// Initialises libusb.
@@ -124,7 +206,76 @@ impl Usb {
     ///
     /// * [`Error::Usb`] — libusb could not be initialised
     pub fn new() -> Result<Self> {
-        Ok(Self(rusb::Context::new()?))
+        Ok(Self::with_transport(Libusb::new()?))
+    }
+
+    /// Initialises libusb, as [`Usb::new`] does, with every transfer to and from
+    /// every board it finds recorded to `trace`.
+    ///
+    /// See [`trace`] for what is recorded, and for playing it back.
+    ///
+    /// # Errors
+    ///
+    /// * [`Error::Usb`] — libusb could not be initialised
+    pub fn traced(trace: trace::Trace) -> Result<Self> {
+        Ok(Self::with_transport(Libusb::new()?.with_trace(trace)))
+    }
+
+    /// Returns a context that finds its boards through `transport` instead of libusb.
+    ///
+    /// Every [`Board`] from it behaves as one from [`Usb::new`] does, errors
+    /// included, so long as the transport keeps the contract set out in
+    /// [`transport`].
+    pub fn with_transport(transport: impl Transport + 'static) -> Self {
+        Self {
+            transport: Arc::new(transport),
+            drive: Drive::default(),
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Returns this context with every board it hands out driven through `drive`.
+    ///
+    /// Boards already handed out keep the drive they had.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use arb::{Drive, Usb};
+    ///
+    /// let usb = Usb::new().unwrap().with_drive(Drive::Spi);
+    ///
+    /// let relays = usb.board(None).relays().unwrap();
+    /// ```
+    pub fn with_drive(self, drive: Drive) -> Self {
+        Self { drive, ..self }
+    }
+
+    /// Returns this context with every board it hands out retrying calls as
+    /// `retry` says, when the board was busy or a transfer timed out.
+    ///
+    /// Boards already handed out keep the policy they had. See [`RetryPolicy`] for
+    /// which calls are retried, and how much of each.
+    pub fn with_retry(self, retry: RetryPolicy) -> Self {
+        Self { retry, ..self }
+    }
+
+    /// Returns a context whose `boards` relay boards are simulated, along with the
+    /// far side of each of them.
+    ///
+    /// The boards sit on ports 1 to `boards` of bus 1, every relay off, and behave
+    /// towards every [`Board`] method as the hardware does — contention and
+    /// [`Error::Busy`] included. See [`sim`] for what a [`SimBoard`](sim::SimBoard)
+    /// can do to them.
+    ///
+    /// # Panics
+    ///
+    /// If `boards` is more than 255, the most ports a hub can number.
+    #[cfg(any(test, feature = "sim"))]
+    pub fn simulated(boards: usize) -> (Self, Vec<sim::SimBoard>) {
+        let (sim, handles) = sim::Sim::new(boards);
+
+        (Self::with_transport(sim), handles)
     }
 
     /// Returns the board on `port`, or the only attached board if `None`.
@@ -136,7 +287,13 @@ impl Usb {
     /// A port number is the board's port on the hub it is plugged into, so it is
     /// unique only among that hub's ports: two boards behind two hubs can both be
     /// on port 3, and this then resolves to [`Error::MultipleFound`].
-    /// [`Usb::boards`] is the way out, and names each board unambiguously.
+    /// [`Usb::board_at`] is the way out, naming a board by where it is, and
+    /// [`Usb::boards`] names each board that way.
+    ///
+    /// Once found, the board is remembered until the transport reports a device
+    /// arriving or leaving — libusb's hotplug support, where the platform has it —
+    /// so that a second board answering to the same name is still caught. On a
+    /// transport that reports no such thing, every call enumerates the bus again.
     ///
     /// # Arguments
     ///
@@ -147,10 +304,27 @@ impl Usb {
             None => Select::Any,
         };
 
-        Board {
-            usb: self.clone(),
-            select,
-        }
+        self.selecting(select)
+    }
+
+    /// Returns the board at `path`, wherever on the USB tree that is.
+    ///
+    /// Resolves nothing, as [`Usb::board`] does not, and cannot be ambiguous: no
+    /// two devices share a path. So this is how to address one of two boards that
+    /// share a port number behind different hubs, without enumerating first.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use arb::{Path, Usb};
+    ///
+    /// let usb = Usb::new().unwrap();
+    /// let path: Path = "1-1.3".parse().unwrap();
+    ///
+    /// let relays = usb.board_at(path).relays().unwrap();
+    /// ```
+    pub fn board_at(&self, path: Path) -> Board {
+        self.selecting(Select::Path(path))
     }
 
     /// Returns every attached relay board, in a stable order.
@@ -178,22 +352,151 @@ impl Usb {
     /// }
     /// ```
     pub fn boards(&self) -> Result<Vec<Board>> {
-        Ok(find_devices(&self.0)?
+        Ok(find_devices(&*self.transport)?
             .into_keys()
-            .map(|path| Board {
-                usb: self.clone(),
-                select: Select::Path(path),
-            })
+            .map(|path| self.board_at(path))
             .collect())
     }
+
+    /// Returns every attached relay board that answers like one, in a stable order.
+    ///
+    /// A CH341A is known by its USB IDs alone, and SPI flash programmers and I²C
+    /// adapters built on the same chip share them, so [`Usb::boards`] lists those as
+    /// well — and [`Board::set_relays`] on one would clock its lines. This
+    /// [probes](Board::probe) each device first and leaves out any without an A6275
+    /// behind it.
+    ///
+    /// Costs a claim and a self-test per device, which is why it is not what
+    /// [`Usb::boards`] does. A device that cannot be probed — busy with another
+    /// application, or failing its transfers — is left out as well, since it did not
+    /// answer like a board; so is a relay board whose read path has failed.
+    ///
+    /// # Errors
+    ///
+    /// * [`Error::Usb`] — the USB device list could not be read
+    pub fn boards_verified(&self) -> Result<Vec<Board>> {
+        Ok(self
+            .boards()?
+            .into_iter()
+            .filter(|board| board.probe().unwrap_or(false))
+            .collect())
+    }
+
+    /// Runs `op` on every attached relay board at once, and returns what it came to
+    /// on each, by where the board is.
+    ///
+    /// The boards are those [`Usb::boards`] returns, each on a thread of its own: a
+    /// call costs about what one board's does, rather than one per board, and a
+    /// board that fails or is slow to answer holds up none of the others. That is
+    /// the point for "every relay off" across a rack, which should reach every board
+    /// it can rather than stop at the first that fails. Each failure is kept beside
+    /// its board's [`Path`], and no board is tried twice.
+    ///
+    /// `Usb::boards` also lists flash programmers and other adapters built on the
+    /// same chip, so where one may be plugged in, run `op` only on the boards for
+    /// which [`Board::probe`] answers `true`, or keep it unplugged. An empty map
+    /// means no board is attached, as for `Usb::boards`. A panic in `op` is raised
+    /// again here once every board is done.
+    ///
+    /// # Errors
+    ///
+    /// * [`Error::Usb`] — the USB device list could not be read
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use arb::{Relays, Usb, Verify};
+    ///
+    /// let usb = Usb::new().unwrap();
+    /// let done = usb
+    ///     .for_each_board(|board| board.set_relays(Relays::NONE, Verify::Enabled))
+    ///     .unwrap();
+    ///
+    /// for (path, result) in done {
+    ///     if let Err(e) = result {
+    ///         eprintln!("{path} is still switched: {e}");
+    ///     }
+    /// }
+    /// ```
+    pub fn for_each_board<T, F>(&self, op: F) -> Result<BTreeMap<Path, Result<T>>>
+    where
+        T: Send,
+        F: Fn(&Board) -> Result<T> + Sync,
+    {
+        let boards = self.boards()?;
+        let op = &op;
+
+        Ok(thread::scope(|scope| {
+            let running: Vec<_> = boards
+                .iter()
+                .map(|board| scope.spawn(move || op(board)))
+                .collect();
+
+            // Joined in order, but every thread is already running: the slowest board
+            // sets how long this takes, not the sum of them.
+            boards
+                .iter()
+                .zip(running)
+                .map(|(board, running)| {
+                    let path = board.path().expect("an enumerated board names its path");
+                    let done = running.join().unwrap_or_else(|e| panic::resume_unwind(e));
+
+                    (path.clone(), done)
+                })
+                .collect()
+        }))
+    }
+
+    /// Starts watching for boards being plugged in and unplugged.
+    ///
+    /// See [`Watch`] for what it reports and when. Starting one claims nothing and
+    /// opens nothing, so it can run beside any number of boards being driven.
+    ///
+    /// # Errors
+    ///
+    /// * [`Error::Usb`] — libusb has hotplug support but could not register for it
+    pub fn watch(&self) -> Result<Watch> {
+        Ok(Watch::new(self.clone(), self.transport.changes()?))
+    }
+
+    /// Starts a reconciler that holds boards at the relays they are meant to have.
+    ///
+    /// See [`Reconciler`] for how often it looks and what it reports. It holds no
+    /// board to begin with: [`Reconciler::hold`] names each.
+    ///
+    /// # Errors
+    ///
+    /// * [`Error::Usb`] — libusb has hotplug support but could not register for it
+    pub fn reconciler(&self) -> Result<Reconciler> {
+        Ok(Reconciler::new(self.clone(), self.transport.changes()?))
+    }
+
+    /// The board `select` names, driven as this context drives its boards.
+    pub(crate) fn selecting(&self, select: Select) -> Board {
+        Board {
+            usb: self.clone(),
+            select,
+            drive: self.drive,
+            retry: self.retry,
+            resolved: Resolved::default(),
+        }
+    }
 }
 
-/// One relay board, found and claimed afresh for the duration of every call.
+/// One relay board, claimed afresh for the duration of every call.
 ///
-/// Holds no device and no claim between calls, so several `Board`s — in this
+/// Holds no claim and nothing open between calls, so several `Board`s — in this
 /// process or in another application — can drive the same hardware. Two calls
 /// that do overlap are not serialised: the loser gets [`Error::Busy`] and should
-/// retry.
+/// retry, which a [`RetryPolicy`] from [`Usb::with_retry`] or
+/// [`Board::with_retry`] does for it.
+///
+/// A board that names a path — every one from [`Usb::boards`] — remembers the
+/// device it found there, so only its first call enumerates the bus, and it looks
+/// again only once that device has gone away. One named by port, or by nothing,
+/// enumerates on every call, because that is the only way to notice a second
+/// board arriving that answers to the same name and report
+/// [`Error::MultipleFound`]. Clones share what they remember.
 ///
 /// # Atomicity
 ///
@@ -208,15 +511,24 @@ impl Usb {
 /// // Racy on a shared board: a write between these two calls is overwritten.
 /// let active = board.relays().unwrap();
 /// board.set_relays(active | arb::Relay::Three, arb::Verify::Enabled).unwrap();
+///
+/// // One claim, so nothing can land in between.
+/// board.switch_on(arb::Relay::Three.into(), arb::Verify::Enabled).unwrap();
 /// ```
 ///
-/// The board latches all eight relays at once, so there is no partial update to
-/// reach for instead. Where the hardware is shared and read-modify-write is
-/// unavoidable, coordinate outside this library.
+/// The board latches all eight relays at once, so there is no partial update in
+/// the hardware: [`switch_on`](Board::switch_on), [`switch_off`](Board::switch_off)
+/// and [`toggle`](Board::toggle) are that read-modify-write, done within one claim.
+/// For anything longer, a [`Session`] holds the claim across as many calls as it
+/// needs, at the cost of locking everyone else out while it lives. Anything
+/// longer-lived still has to be coordinated outside this library.
 #[derive(Clone, Debug)]
 pub struct Board {
     usb: Usb,
     select: Select,
+    drive: Drive,
+    retry: RetryPolicy,
+    resolved: Resolved,
 }
 
 impl Board {
@@ -230,6 +542,62 @@ impl Board {
         self.select.port()
     }
 
+    /// Returns where on the USB tree this board is, if it names one place.
+    ///
+    /// The identifier [`port`](Board::port) is not: `Some` for every board from
+    /// [`Usb::boards`], and two of those never share one. `None` for the boards
+    /// [`Usb::board`] returns, which name a port or no board in particular and
+    /// resolve to wherever that turns out to be.
+    pub fn path(&self) -> Option<&Path> {
+        self.select.path()
+    }
+
+    /// Returns how this board's shift register is driven.
+    pub fn drive(&self) -> Drive {
+        self.drive
+    }
+
+    /// Returns this board driven through `drive`, whatever its context drives
+    /// other boards with.
+    pub fn with_drive(self, drive: Drive) -> Self {
+        Self { drive, ..self }
+    }
+
+    /// Returns how this board's calls are retried.
+    pub fn retry(&self) -> RetryPolicy {
+        self.retry
+    }
+
+    /// Returns this board retrying calls as `retry` says, whatever its context
+    /// does for other boards.
+    pub fn with_retry(self, retry: RetryPolicy) -> Self {
+        Self { retry, ..self }
+    }
+
+    /// Returns this board putting its shift register right by itself whenever a
+    /// call loses it, and saying so: see [`Recovering`].
+    ///
+    /// Opt-in, because what it falls back on is what this process last latched,
+    /// which is only the truth on a board nothing else writes to.
+    pub fn recovering(&self) -> Recovering {
+        Recovering::new(self.clone())
+    }
+
+    /// Finds the board and returns where on the USB tree it is.
+    ///
+    /// What [`path`](Board::path) cannot say for a board named by port: which
+    /// device that port resolves to right now. Claims nothing, so unlike every
+    /// other lookup it works while another application is talking to the board.
+    ///
+    /// # Errors
+    ///
+    /// * [`Error::NotFound`] — no relay board detected
+    /// * [`Error::MultipleFound`] — more than one board answers to this one
+    /// * [`Error::Usb`] — the USB device list could not be read
+    pub fn locate(&self) -> Result<Path> {
+        find_device(&*self.usb.transport, &self.select)?.path()
+    }
+
     /// Returns the relays that are currently active.
     ///
     /// Takes the shift register at its word: [`Board::self_test`] is the separate
@@ -258,7 +626,7 @@ impl Board {
     /// }
     /// ```
     pub fn relays(&self) -> Result<Relays> {
-        A6275::new(self.claim()?).status().map(Relays::from_bits)
+        self.retry.run(|| self.claimed()?.relays())
     }
 
     /// Checks that the board answers correctly, without moving any relay, and
@@ -296,7 +664,28 @@ impl Board {
     /// * [`Error::RegisterOutOfSync`] — the check was interrupted and could not put
     ///   the register's contents back
     pub fn self_test(&self) -> Result<Relays> {
-        A6275::new(self.claim()?).self_test().map(Relays::from_bits)
+        self.retry.run(|| self.claimed()?.self_test())
+    }
+
+    /// Returns whether the device answers like a relay board: whether there is a
+    /// shift register behind it that hands back what was shifted in.
+    ///
+    /// The round trip [`self_test`](Board::self_test) makes, which never latches,
+    /// so no relay moves on a board that is one. It does clock D3 and D5 with D0
+    /// held low, so on a device that is not a board — a flash programmer, whose
+    /// chip select that is — it is not quite nothing; probe only devices that may
+    /// be relay boards, and leave the others to [`Usb::boards_verified`] once.
+    ///
+    /// # Errors
+    ///
+    /// As [`Board::self_test`], except [`Error::SelfTestFailed`], which is the
+    /// answer `false`.
+    pub fn probe(&self) -> Result<bool> {
+        match self.self_test() {
+            Ok(_) => Ok(true),
+            Err(Error::SelfTestFailed) => Ok(false),
+            Err(e) => Err(e),
+        }
     }
 
     /// Activates `relays`, deactivating every relay not in the set.
@@ -313,6 +702,8 @@ impl Board {
     /// * [`Error::MultipleFound`] — more than one board answers to this one
     /// * [`Error::Busy`] — another application is talking to the board
     /// * [`Error::VerificationFailed`] — the read-back did not match `relays`
+    /// * [`Error::VerificationRetriesExhausted`] — with [`Verify::Retry`], no
+    ///   read-back matched `relays`
     /// * [`Error::RegisterOutOfSync`] — the read-back was interrupted and could not
     ///   put the latched value back into the register
     ///
@@ -329,7 +720,109 @@ impl Board {
     /// usb.board(None).set_relays(relays, Verify::Enabled).unwrap();
     /// ```
     pub fn set_relays(&self, relays: Relays, verify: Verify) -> Result<()> {
-        A6275::new(self.claim()?).set_status(relays.bits(), verify)
+        self.retry
+            .run(|| self.claimed()?.set_relays(relays, verify))
+    }
+
+    /// Activates `relays`, leaving every other relay as it is, and returns the
+    /// relays active afterwards.
+    ///
+    /// Reads the board and latches the result within a single claim, so unlike a
+    /// [`relays`](Board::relays) followed by a [`set_relays`](Board::set_relays), no
+    /// other application's write can land in between and be overwritten. Costs a
+    /// read and a write: 56 transfers, or 84 with [`Verify::Enabled`].
+    ///
+    /// # Errors
+    ///
+    /// * [`Error::NotFound`] — no relay board detected
+    /// * [`Error::MultipleFound`] — more than one board answers to this one
+    /// * [`Error::Busy`] — another application is talking to the board
+    /// * [`Error::VerificationFailed`] — the read-back did not match the new state
+    /// * [`Error::RegisterOutOfSync`] — the read, or a read-back, was interrupted and
+    ///   could not put the register back. Nothing was latched if it was the read.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use arb::{Relay, Usb, Verify};
+    ///
+    /// let usb = Usb::new().unwrap();
+    ///
+    /// // Relay 3 on, whatever else happens to be on
+    /// let active = usb.board(None).switch_on(Relay::Three.into(), Verify::Enabled).unwrap();
+    ///
+    /// assert!(active.contains(Relay::Three));
+    /// ```
+    pub fn switch_on(&self, relays: Relays, verify: Verify) -> Result<Relays> {
+        self.session()?.switch_on(relays, verify)
+    }
+
+    /// Deactivates `relays`, leaving every other relay as it is, and returns the
+    /// relays active afterwards.
+    ///
+    /// Within a single claim, as [`switch_on`](Board::switch_on) is.
+    ///
+    /// # Errors
+    ///
+    /// As [`switch_on`](Board::switch_on).
+    pub fn switch_off(&self, relays: Relays, verify: Verify) -> Result<Relays> {
+        self.session()?.switch_off(relays, verify)
+    }
+
+    /// Flips every relay in `relays`, leaving every other relay as it is, and
+    /// returns the relays active afterwards.
+    ///
+    /// Within a single claim, as [`switch_on`](Board::switch_on) is.
+    ///
+    /// # Errors
+    ///
+    /// As [`switch_on`](Board::switch_on).
+    pub fn toggle(&self, relays: Relays, verify: Verify) -> Result<Relays> {
+        self.session()?.toggle(relays, verify)
+    }
+
+    /// Activates `relays` for `duration` and then deactivates them, leaving every
+    /// other relay as it is, and returns the relays active afterwards.
+    ///
+    /// For door strikes, reset buttons and anything else that must be energized for
+    /// a set time and then let go. A relay in `relays` that was already active is
+    /// deactivated at the end all the same: the pulse owns the relays it names.
+    ///
+    /// Holds the claim for the whole pulse, so that the release cannot lose a race
+    /// for the board and leave the relays energized. Other applications get
+    /// [`Error::Busy`] for `duration` instead: keep pulses short on a shared board.
+    ///
+    /// The release is attempted on every path that may have energized anything,
+    /// without waiting out `duration` if the hold already failed. Where the hold
+    /// failed and the release did not, the hold's error is returned and the relays
+    /// are released; where the release failed, its error is returned whatever the
+    /// hold did, and the relays in `relays` may still be energized.
+    ///
+    /// # Errors
+    ///
+    /// * [`Error::NotFound`] — no relay board detected
+    /// * [`Error::MultipleFound`] — more than one board answers to this one
+    /// * [`Error::Busy`] — another application is talking to the board
+    /// * [`Error::VerificationFailed`] — the read-back of the hold or of the release
+    ///   did not match
+    /// * [`Error::RegisterOutOfSync`] — the read, or a read-back, was interrupted and
+    ///   could not put the register back. Nothing was latched if it was the read.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// use arb::{Relay, Usb, Verify};
+    ///
+    /// let usb = Usb::new().unwrap();
+    ///
+    /// // Open the door on relay 4 for half a second
+    /// let board = usb.board(None);
+    /// board.pulse(Relay::Four.into(), Duration::from_millis(500), Verify::Enabled).unwrap();
+    /// ```
+    pub fn pulse(&self, relays: Relays, duration: Duration, verify: Verify) -> Result<Relays> {
+        self.session()?.pulse(relays, duration, verify)
     }
 
     /// Performs a USB reset on the relay board.
@@ -342,12 +835,32 @@ impl Board {
     /// * [`Error::MultipleFound`] — more than one board answers to this one
     /// * [`Error::Busy`] — another application is talking to the board
     pub fn reset_device(&self) -> Result<()> {
-        self.claim()?.reset()
+        self.retry.run(|| self.claim()?.reset())
+    }
+
+    /// Finds the board and claims it until the returned [`Session`] is dropped.
+    ///
+    /// Saves the lookup and the claim every other method pays per call, which is
+    /// what a loop switching relays many times a second wants. Everyone else is
+    /// locked out of the board meanwhile: see [`Session`].
+    ///
+    /// # Errors
+    ///
+    /// * [`Error::NotFound`] — no relay board detected
+    /// * [`Error::MultipleFound`] — more than one board answers to this one
+    /// * [`Error::Busy`] — another application is talking to the board
+    pub fn session(&self) -> Result<Session> {
+        self.retry.run(|| self.claimed())
+    }
+
+    /// Finds the board and claims it, once, whatever the retry policy says.
+    fn claimed(&self) -> Result<Session> {
+        Ok(Session::new(self.claim()?, self.select.clone(), self.drive))
     }
 
     /// Finds the board and claims its CH341A interface for the duration of one call.
-    fn claim(&self) -> Result<Ch341a> {
-        Ch341a::open(&find_device(&self.usb.0, &self.select)?)
+    fn claim(&self) -> Result<Box<dyn Link>> {
+        self.resolved.claim(&*self.usb.transport, &self.select)
     }
 }
 
@@ -375,4 +888,75 @@ mod tests {
         assert_send_sync::<Usb>();
         assert_send_sync::<Board>();
     }
+
+    #[test]
+    fn a_board_named_by_path_is_the_one_at_that_path() {
+        let (usb, sims) = Usb::simulated(2);
+        let path: Path = "1-2".parse().unwrap();
+        let board = usb.board_at(path.clone());
+
+        board
+            .set_relays(Relay::Five.into(), Verify::Enabled)
+            .unwrap();
+
+        assert_eq!(board.path(), Some(&path));
+        assert_eq!(sims[1].outputs(), Relay::Five.into());
+        assert_eq!(sims[0].outputs(), Relays::NONE);
+        assert!(matches!(
+            usb.board_at(Path::new(2, [2])).relays(),
+            Err(Error::NotFound)
+        ));
+    }
+
+    #[test]
+    fn only_devices_that_answer_like_a_board_are_verified() {
+        let (usb, sims) = Usb::simulated(3);
+
+        // A CH341A with nothing on D7: what a flash programmer with no chip in its
+        // socket looks like from here.
+        sims[1].set_fault(Some(sim::Fault::StuckLow));
+        sims[2].latch(Relay::Four.into());
+
+        let ports: Vec<_> = usb
+            .boards_verified()
+            .unwrap()
+            .iter()
+            .map(Board::port)
+            .collect();
+
+        assert_eq!(ports, [Some(1), Some(3)]);
+        assert!(!usb.board(Some(2)).probe().unwrap());
+
+        // Probing latched nothing, and left every register as it found it.
+        assert_eq!(sims[2].outputs(), Relay::Four.into());
+        assert_eq!(sims[2].register(), Relay::Four.into());
+    }
+
+    #[test]
+    fn every_board_is_switched_but_the_one_that_fails() {
+        let (usb, sims) = Usb::simulated(3);
+
+        for sim in &sims {
+            sim.latch(Relay::One | Relay::Two);
+        }
+
+        let session = usb.board(Some(2)).session().unwrap();
+        let done = usb
+            .for_each_board(|board| board.set_relays(Relays::NONE, Verify::Enabled))
+            .unwrap();
+
+        let paths: Vec<_> = done.keys().cloned().collect();
+
+        assert_eq!(
+            paths,
+            [Path::new(1, [1]), Path::new(1, [2]), Path::new(1, [3])]
+        );
+        assert!(matches!(done[&Path::new(1, [2])], Err(Error::Busy)));
+        assert!(done[&Path::new(1, [1])].is_ok() && done[&Path::new(1, [3])].is_ok());
+        assert_eq!(sims[0].outputs(), Relays::NONE);
+        assert_eq!(sims[1].outputs(), Relay::One | Relay::Two);
+        assert_eq!(sims[2].outputs(), Relays::NONE);
+
+        drop(session);
+    }
 }
@@ -0,0 +1,396 @@
+//! Keeping boards holding the relays they are meant to.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::errors::Error;
+use crate::find::Path;
+use crate::relays::Relays;
+use crate::transport::Changes;
+use crate::{Board, Usb, Verify};
+
+/// How long a [`Reconciler`] waits between passes over its boards, unless
+/// [`Reconciler::with_interval`] says otherwise.
+const INTERVAL: Duration = Duration::from_secs(5);
+
+/// Something a [`Reconciler`] noticed on one of its boards, and when.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Drift {
+    /// The board held other relays than it should, and was written back.
+    Corrected {
+        /// Where the board is.
+        path: Path,
+        /// When the drift was put right.
+        at: SystemTime,
+        /// What the board held: `None` where the read lost the register, which is
+        /// put right the same way.
+        found: Option<Relays>,
+        /// What it holds again now.
+        desired: Relays,
+    },
+    /// The board could not be read, or had drifted and could not be written back.
+    /// The next pass tries again.
+    Failed {
+        /// Where the board is.
+        path: Path,
+        /// When the attempt failed.
+        at: SystemTime,
+        /// What it failed with.
+        error: Error,
+    },
+    /// The board is no longer on the bus. Reported once, however many passes it
+    /// stays away for; it is put right as soon as it is back.
+    Missing {
+        /// Where the board was.
+        path: Path,
+        /// When it was found missing.
+        at: SystemTime,
+    },
+}
+
+impl Drift {
+    /// Returns where the board this happened to is.
+    pub fn path(&self) -> &Path {
+        match self {
+            Drift::Corrected { path, .. }
+            | Drift::Failed { path, .. }
+            | Drift::Missing { path, .. } => path,
+        }
+    }
+
+    /// Returns when it happened.
+    pub fn at(&self) -> SystemTime {
+        match self {
+            Drift::Corrected { at, .. } | Drift::Failed { at, .. } | Drift::Missing { at, .. } => {
+                *at
+            }
+        }
+    }
+}
+
+/// Holds boards at the relays they are meant to have, from [`Usb::reconciler`],
+/// as a blocking iterator of what it had to put right that never ends.
+///
+/// A board that browns out or is reset comes back with every relay off, and
+/// nothing else notices. A reconciler reads each board it [holds](Reconciler::hold)
+/// once an interval — every five seconds unless
+/// [`with_interval`](Reconciler::with_interval) says otherwise — and writes back
+/// any that has drifted, reporting each as a [`Drift`]. Where the transport
+/// reports boards arriving, a pass also runs as soon as one does, so a board
+/// plugged back in is put right within moments rather than within the interval.
+///
+/// It writes whatever it finds different, whoever changed it: hold a board only
+/// where nothing else is meant to drive it. A read that costs the register is
+/// answered with a write as well, since that is its remedy.
+///
+/// Each pass reads every board it holds, so on a board shared with other
+/// applications a pass can meet [`Error::Busy`]; that is reported as
+/// [`Drift::Failed`] and tried again next pass, unless a
+/// [`RetryPolicy`](crate::RetryPolicy) on the context gets past it first.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use arb::{Drift, Path, Relay, Usb};
+///
+/// let usb = Usb::new().unwrap();
+/// let mut reconciler = usb.reconciler().unwrap().with_interval(Duration::from_secs(1));
+///
+/// reconciler.hold("1-1.3".parse::<Path>().unwrap(), Relay::One | Relay::Three);
+///
+/// for drift in reconciler {
+///     if let Drift::Corrected { path, found, .. } = drift {
+///         println!("{path} had drifted to {found:?}, and was written back");
+///     }
+/// }
+/// ```
+pub struct Reconciler {
+    usb: Usb,
+    changes: Option<Box<dyn Changes>>,
+    interval: Duration,
+    verify: Verify,
+    held: BTreeMap<Path, (Board, Relays)>,
+    missing: BTreeSet<Path>,
+    pending: VecDeque<Drift>,
+    started: bool,
+}
+
+impl Reconciler {
+    pub(crate) fn new(usb: Usb, changes: Option<Box<dyn Changes>>) -> Self {
+        Self {
+            usb,
+            changes,
+            interval: INTERVAL,
+            verify: Verify::default(),
+            held: BTreeMap::new(),
+            missing: BTreeSet::new(),
+            pending: VecDeque::new(),
+            started: false,
+        }
+    }
+
+    /// Returns this reconciler passing over its boards every `interval`.
+    pub fn with_interval(self, interval: Duration) -> Self {
+        Self { interval, ..self }
+    }
+
+    /// Returns this reconciler writing drifted boards back with `verify`, rather
+    /// than with [`Verify::Enabled`].
+    pub fn with_verify(self, verify: Verify) -> Self {
+        Self { verify, ..self }
+    }
+
+    /// Holds the board at `path` at `relays` from the next pass on, in place of
+    /// whatever it was held at before.
+    ///
+    /// Writes nothing by itself: a board already holding `relays` is never
+    /// written, and one that is not is reported as having drifted.
+    pub fn hold(&mut self, path: Path, relays: Relays) {
+        let board = self.usb.board_at(path.clone());
+
+        self.held.insert(path, (board, relays));
+    }
+
+    /// Stops holding the board at `path`, and returns what it was held at.
+    pub fn release(&mut self, path: &Path) -> Option<Relays> {
+        self.missing.remove(path);
+        self.held.remove(path).map(|(_, relays)| relays)
+    }
+
+    /// Returns what the board at `path` is held at, if it is held.
+    pub fn desired(&self, path: &Path) -> Option<Relays> {
+        self.held.get(path).map(|(_, relays)| *relays)
+    }
+
+    /// Makes one pass over every board now, and returns what it had to put right.
+    ///
+    /// For a caller running its own loop; iterating runs these passes itself.
+    pub fn reconcile(&mut self) -> Vec<Drift> {
+        let mut drifts = Vec::new();
+
+        for (path, (board, desired)) in &self.held {
+            let found = match board.relays() {
+                Ok(found) if found == *desired => {
+                    self.missing.remove(path);
+
+                    continue;
+                }
+                Ok(found) => Some(found),
+                Err(e) if e.requires_rewrite() => None,
+                Err(Error::NotFound) => {
+                    if self.missing.insert(path.clone()) {
+                        drifts.push(Drift::Missing {
+                            path: path.clone(),
+                            at: SystemTime::now(),
+                        });
+                    }
+
+                    continue;
+                }
+                Err(error) => {
+                    drifts.push(Drift::Failed {
+                        path: path.clone(),
+                        at: SystemTime::now(),
+                        error,
+                    });
+
+                    continue;
+                }
+            };
+
+            self.missing.remove(path);
+
+            drifts.push(match board.set_relays(*desired, self.verify) {
+                Ok(()) => Drift::Corrected {
+                    path: path.clone(),
+                    at: SystemTime::now(),
+                    found,
+                    desired: *desired,
+                },
+                Err(error) => Drift::Failed {
+                    path: path.clone(),
+                    at: SystemTime::now(),
+                    error,
+                },
+            });
+        }
+
+        drifts
+    }
+
+    /// Waits out one interval, or less if the transport says something changed on
+    /// the bus meanwhile.
+    ///
+    /// A transport that fails to say falls back to waiting the whole interval from
+    /// then on: the passes are what keep the boards right, and they do not need it.
+    fn wait(&mut self) {
+        let Some(changes) = &mut self.changes else {
+            thread::sleep(self.interval);
+
+            return;
+        };
+
+        let deadline = Instant::now() + self.interval;
+
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+
+            match changes.wait(left) {
+                Ok(true) => return,
+                Ok(false) if left.is_zero() => return,
+                Ok(false) => {}
+                Err(_) => {
+                    self.changes = None;
+                    thread::sleep(deadline.saturating_duration_since(Instant::now()));
+
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for Reconciler {
+    type Item = Drift;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(drift) = self.pending.pop_front() {
+                return Some(drift);
+            }
+
+            if self.started {
+                self.wait();
+            }
+
+            self.started = true;
+
+            let drifts = self.reconcile();
+
+            self.pending.extend(drifts);
+        }
+    }
+}
+
+impl fmt::Debug for Reconciler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let held: BTreeMap<_, _> = self
+            .held
+            .iter()
+            .map(|(path, (_, relays))| (path, relays))
+            .collect();
+
+        f.debug_struct("Reconciler")
+            .field("usb", &self.usb)
+            .field("hotplug", &self.changes.is_some())
+            .field("interval", &self.interval)
+            .field("verify", &self.verify)
+            .field("held", &held)
+            .field("missing", &self.missing)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Relay;
+    use crate::sim::Fault;
+
+    fn holding(boards: usize, relays: Relays) -> (Reconciler, Vec<crate::sim::SimBoard>) {
+        let (usb, sims) = Usb::simulated(boards);
+        let mut reconciler = usb.reconciler().unwrap().with_interval(Duration::ZERO);
+
+        for sim in &sims {
+            reconciler.hold(Path::new(1, [sim.port()]), relays);
+        }
+
+        (reconciler, sims)
+    }
+
+    #[test]
+    fn a_board_that_drifted_is_written_back_and_reported() {
+        let (mut reconciler, sims) = holding(2, Relay::One | Relay::Three);
+        sims[0].latch(Relay::One | Relay::Three);
+
+        let drift = reconciler.next().unwrap();
+
+        assert!(matches!(
+            &drift,
+            Drift::Corrected { found: Some(found), desired, .. }
+                if *found == Relays::NONE && *desired == Relay::One | Relay::Three
+        ));
+        assert_eq!(drift.path(), &Path::new(1, [2]));
+        assert!(drift.at() <= SystemTime::now());
+        assert_eq!(sims[1].outputs(), Relay::One | Relay::Three);
+
+        // The board that held its relays was read and left alone.
+        assert!(reconciler.reconcile().is_empty());
+    }
+
+    #[test]
+    fn a_board_whose_register_was_lost_is_written_back() {
+        let (mut reconciler, sims) = holding(1, Relay::Seven.into());
+        sims[0].latch(Relay::Seven.into());
+        sims[0].set_fault(Some(Fault::GlitchAfter {
+            reads: 0,
+            writes: usize::MAX,
+        }));
+
+        let drifts = reconciler.reconcile();
+
+        assert!(matches!(drifts[..], [Drift::Corrected { found: None, .. }]));
+        assert_eq!(sims[0].register(), Relay::Seven.into());
+    }
+
+    #[test]
+    fn a_board_unplugged_is_reported_once_and_put_right_when_it_returns() {
+        let (mut reconciler, sims) = holding(1, Relay::Two.into());
+        sims[0].latch(Relay::Two.into());
+        sims[0].unplug();
+
+        assert!(matches!(reconciler.next(), Some(Drift::Missing { .. })));
+        assert!(reconciler.reconcile().is_empty());
+
+        // Back from a brown-out, with every relay off.
+        sims[0].latch(Relays::NONE);
+        sims[0].plug();
+
+        assert!(matches!(
+            reconciler.next(),
+            Some(Drift::Corrected {
+                found: Some(Relays::NONE),
+                ..
+            })
+        ));
+        assert_eq!(sims[0].outputs(), Relay::Two.into());
+    }
+
+    #[test]
+    fn a_board_that_cannot_be_read_is_reported_and_left_alone() {
+        let (mut reconciler, sims) = holding(1, Relay::Two.into());
+        let session = reconciler.usb.board(None).session().unwrap();
+
+        assert!(matches!(
+            reconciler.reconcile()[..],
+            [Drift::Failed {
+                error: Error::Busy,
+                ..
+            }]
+        ));
+
+        drop(session);
+
+        assert_eq!(
+            reconciler.release(&Path::new(1, [1])),
+            Some(Relay::Two.into())
+        );
+        assert!(reconciler.reconcile().is_empty());
+        assert_eq!(sims[0].outputs(), Relays::NONE);
+    }
+}
@@ -0,0 +1,274 @@
+//! Putting a lost shift register right without the caller's help.
+
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::errors::{Error, Result};
+use crate::relays::Relays;
+use crate::{Board, Verify};
+
+/// What a [`Recovering`] call came to.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Outcome<T> {
+    /// The call went through as it would have on a plain [`Board`].
+    Done(T),
+    /// The call lost the shift register, and recovery put it right.
+    Recovered {
+        /// What the call would have returned, or for a read, the relays this
+        /// process last latched.
+        value: T,
+        /// The [`Error::RegisterOutOfSync`] that recovery got past.
+        lost: Error,
+    },
+}
+
+impl<T> Outcome<T> {
+    /// Returns the value, whichever way it was come by.
+    pub fn into_value(self) -> T {
+        match self {
+            Outcome::Done(value) | Outcome::Recovered { value, .. } => value,
+        }
+    }
+
+    /// Whether recovery had to step in.
+    pub fn is_recovered(&self) -> bool {
+        matches!(self, Outcome::Recovered { .. })
+    }
+}
+
+/// A [`Board`] that puts its shift register right by itself when it is lost, from
+/// [`Board::recovering`].
+///
+/// [`Error::RegisterOutOfSync`] has one remedy, writing a known state, and
+/// this applies it:
+///
+/// * A [`set_relays`](Recovering::set_relays) that loses the register writes the
+///   same relays again, verified whatever `verify` says, since it is the
+///   verification that proves the register is back.
+/// * A [`relays`](Recovering::relays) that loses the register reports the relays
+///   last latched through this board and puts them back in the register, without
+///   latching, so that later reads agree with them again.
+///
+/// Either way the call returns [`Outcome::Recovered`] rather than
+/// [`Outcome::Done`], carrying the error it got past. Where recovery cannot
+/// help — a read before anything was latched, or a second failure on the way —
+/// the error is returned as a plain `Board` would have returned it.
+///
+/// What it remembers is only what *this* board, or a clone of it, latched. A
+/// write by another `Board`, another process or a person at the board goes
+/// unseen, and a read recovered after one reports relays the board no longer
+/// holds. So recover only on a board this process owns, and keep to one
+/// `Recovering` per board; a failed write that leaves the relays in doubt makes
+/// it forget, and only a successful write teaches it again.
+///
+/// # Example
+///
+/// ```no_run
+/// use arb::{Outcome, Relay, Usb, Verify};
+///
+/// let usb = Usb::new().unwrap();
+/// let board = usb.board(None).recovering();
+///
+/// let outcome = board.set_relays(Relay::Two.into(), Verify::Enabled).unwrap();
+///
+/// if let Outcome::Recovered { lost, .. } = outcome {
+///     eprintln!("{board}: put the register back after: {lost}");
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Recovering {
+    board: Board,
+    latched: Arc<Mutex<Option<Relays>>>,
+}
+
+impl Recovering {
+    pub(crate) fn new(board: Board) -> Self {
+        Self {
+            board,
+            latched: Arc::default(),
+        }
+    }
+
+    /// Returns the board underneath, for anything this does not wrap.
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    /// Returns the relays last latched through this board, if it knows them.
+    pub fn latched(&self) -> Option<Relays> {
+        *self.remembered()
+    }
+
+    /// Returns the relays that are currently active, as [`Board::relays`] does,
+    /// or the relays last latched through this board if the read lost the
+    /// register.
+    ///
+    /// # Errors
+    ///
+    /// As [`Board::relays`]. [`Error::RegisterOutOfSync`] only where nothing has
+    /// been latched through this board yet, or putting the register back failed
+    /// too.
+    pub fn relays(&self) -> Result<Outcome<Relays>> {
+        match self.board.relays() {
+            Err(lost @ Error::RegisterOutOfSync { .. }) => {
+                let Some(latched) = self.latched() else {
+                    return Err(lost);
+                };
+
+                // The outputs did not move, so what was last latched is still what
+                // they hold; failing to say so again leaves the register as lost as
+                // it was, and the error is the one that lost it.
+                match self.board.session().and_then(|s| s.load(latched)) {
+                    Ok(()) => Ok(Outcome::Recovered {
+                        value: latched,
+                        lost,
+                    }),
+                    Err(_) => Err(lost),
+                }
+            }
+            read => read.map(Outcome::Done),
+        }
+    }
+
+    /// Activates `relays`, deactivating every relay not in the set, as
+    /// [`Board::set_relays`] does, and writes them again, verified, if the write
+    /// lost the register.
+    ///
+    /// # Errors
+    ///
+    /// As [`Board::set_relays`]. [`Error::RegisterOutOfSync`], or any other
+    /// error, only where the second write failed too.
+    pub fn set_relays(&self, relays: Relays, verify: Verify) -> Result<Outcome<()>> {
+        let outcome = match self.board.set_relays(relays, verify) {
+            Err(lost @ Error::RegisterOutOfSync { .. }) => self
+                .board
+                .set_relays(relays, Verify::Enabled)
+                .map(|()| Outcome::Recovered { value: (), lost }),
+            write => write.map(Outcome::Done),
+        };
+
+        match &outcome {
+            Ok(_) => *self.remembered() = Some(relays),
+            Err(e) if !e.relay_state_known() => *self.remembered() = None,
+            Err(_) => {}
+        }
+
+        outcome
+    }
+
+    fn remembered(&self) -> MutexGuard<'_, Option<Relays>> {
+        self.latched.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl fmt::Debug for Recovering {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Recovering")
+            .field("board", &self.board)
+            .field("latched", &self.latched())
+            .finish()
+    }
+}
+
+/// As [`Board`]'s.
+impl fmt::Display for Recovering {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.board.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::Fault;
+    use crate::{Relay, Usb};
+
+    /// A fault that lets a write and its latch through, then times out the
+    /// restore after the verifying read, once: 28 line changes, then one more.
+    const LOSES_THE_RESTORE: Fault = Fault::GlitchAfter {
+        reads: 1,
+        writes: 28,
+    };
+
+    #[test]
+    fn a_write_that_loses_the_register_is_written_again() {
+        let (usb, sims) = Usb::simulated(1);
+        let board = usb.board(None).recovering();
+        sims[0].set_fault(Some(LOSES_THE_RESTORE));
+
+        let outcome = board.set_relays(Relay::Three.into(), Verify::Enabled);
+
+        assert!(matches!(
+            outcome,
+            Ok(Outcome::Recovered {
+                lost: Error::RegisterOutOfSync { .. },
+                ..
+            })
+        ));
+        assert_eq!(sims[0].outputs(), Relay::Three.into());
+        assert_eq!(sims[0].register(), sims[0].outputs());
+        assert_eq!(board.latched(), Some(Relay::Three.into()));
+    }
+
+    #[test]
+    fn a_read_that_loses_the_register_reports_what_was_latched_and_puts_it_back() {
+        let (usb, sims) = Usb::simulated(1);
+        let board = usb.board(None).recovering();
+
+        board
+            .set_relays(Relay::One | Relay::Six, Verify::Enabled)
+            .unwrap();
+        sims[0].set_fault(Some(Fault::GlitchAfter {
+            reads: 0,
+            writes: usize::MAX,
+        }));
+
+        let outcome = board.relays().unwrap();
+
+        assert!(outcome.is_recovered());
+        assert_eq!(outcome.into_value(), Relay::One | Relay::Six);
+        assert_eq!(sims[0].register(), Relay::One | Relay::Six);
+        assert!(
+            matches!(board.relays(), Ok(Outcome::Done(relays)) if relays == Relay::One | Relay::Six)
+        );
+    }
+
+    #[test]
+    fn a_read_with_nothing_latched_to_fall_back_on_is_not_recovered() {
+        let (usb, sims) = Usb::simulated(1);
+        let board = usb.board(None).recovering();
+        sims[0].latch(Relay::Two.into());
+        sims[0].set_fault(Some(Fault::GlitchAfter {
+            reads: 0,
+            writes: usize::MAX,
+        }));
+
+        assert!(matches!(
+            board.relays(),
+            Err(Error::RegisterOutOfSync { .. })
+        ));
+        assert_eq!(sims[0].outputs(), Relay::Two.into());
+    }
+
+    #[test]
+    fn a_write_that_leaves_the_relays_in_doubt_is_forgotten() {
+        let (usb, sims) = Usb::simulated(1);
+        let board = usb.board(None).recovering();
+
+        board
+            .set_relays(Relay::Four.into(), Verify::Enabled)
+            .unwrap();
+        sims[0].set_fault(Some(Fault::TimeoutAfter {
+            reads: 0,
+            writes: usize::MAX,
+        }));
+
+        assert!(
+            board
+                .set_relays(Relay::Five.into(), Verify::Enabled)
+                .is_err()
+        );
+        assert_eq!(board.latched(), None);
+    }
+}
@@ -7,7 +7,7 @@
 use std::array;
 use std::fmt;
 use std::iter::FusedIterator;
-use std::ops::{BitOr, BitOrAssign};
+use std::ops::{BitOr, BitOrAssign, BitXor, Sub};
 
 use crate::errors::{Error, Result};
 
@@ -241,6 +241,52 @@ impl BitOrAssign for Relays {
     }
 }
 
+/// The relays in the first set that are not in the second.
+///
+/// ```
+/// use arb::{Relay, Relays};
+///
+/// assert_eq!(Relays::ALL - (Relay::One | Relay::Two), Relays::from_bits(0b1111_1100));
+/// ```
+impl Sub for Relays {
+    type Output = Relays;
+
+    fn sub(self, rhs: Relays) -> Relays {
+        Relays(self.0 & !rhs.0)
+    }
+}
+
+impl Sub<Relay> for Relays {
+    type Output = Relays;
+
+    fn sub(self, rhs: Relay) -> Relays {
+        self - Relays::from(rhs)
+    }
+}
+
+/// The relays in exactly one of the two sets: the first with the second toggled.
+///
+/// ```
+/// use arb::Relay;
+///
+/// assert_eq!((Relay::One | Relay::Two) ^ (Relay::Two | Relay::Three), Relay::One | Relay::Three);
+/// ```
+impl BitXor for Relays {
+    type Output = Relays;
+
+    fn bitxor(self, rhs: Relays) -> Relays {
+        Relays(self.0 ^ rhs.0)
+    }
+}
+
+impl BitXor<Relay> for Relays {
+    type Output = Relays;
+
+    fn bitxor(self, rhs: Relay) -> Relays {
+        self ^ Relays::from(rhs)
+    }
+}
+
 /// Renders the active relay numbers, separated by spaces, or `none` if no relay
 /// is active.
 ///
@@ -327,6 +373,32 @@ mod tests {
         assert_eq!(relays | Relays::ALL, Relays::ALL);
     }
 
+    #[test]
+    fn removing_a_set_leaves_only_what_it_did_not_name() {
+        let relays = Relay::One | Relay::Three | Relay::Five;
+
+        assert_eq!(
+            relays - (Relay::Three | Relay::Four),
+            Relay::One | Relay::Five
+        );
+        assert_eq!(relays - Relay::One, Relay::Three | Relay::Five);
+        assert_eq!(relays - Relays::ALL, Relays::NONE);
+        assert_eq!(relays - Relays::NONE, relays);
+    }
+
+    #[test]
+    fn toggling_a_set_flips_exactly_its_members() {
+        let relays = Relay::One | Relay::Three;
+
+        assert_eq!(
+            relays ^ (Relay::Three | Relay::Four),
+            Relay::One | Relay::Four
+        );
+        assert_eq!(relays ^ Relay::Two, Relay::One | Relay::Two | Relay::Three);
+        assert_eq!(relays ^ relays, Relays::NONE);
+        assert_eq!(Relays::NONE ^ Relays::ALL, Relays::ALL);
+    }
+
     #[test]
     fn iterating_reports_its_exact_remaining_length() {
         let mut iter = Relays::from_bits(0b0011_1011).iter();
@@ -0,0 +1,279 @@
+//! Trying a call again when the board was only briefly out of reach.
+
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::errors::Result;
+
+/// How often, and how patiently, a [`Board`](crate::Board) call is tried again
+/// when the board was only briefly out of reach.
+///
+/// Applies to the failures [`Error::is_retryable`](crate::Error::is_retryable)
+/// names and no others: [`Error::Busy`](crate::Error::Busy), which is another
+/// application holding the board, and a USB transfer timing out before anything
+/// was lost. Neither says anything is wrong with the board, and both are usually
+/// gone a few milliseconds later.
+///
+/// Never to [`Error::RegisterOutOfSync`](crate::Error::RegisterOutOfSync). That
+/// one means the board's account of its relays is gone, and trying the read again
+/// is the one thing that does not bring it back; nor to a verification or
+/// self-test failure, which would only fail the same way again.
+///
+/// Retries a whole call only where running it twice is the same as running it
+/// once: [`relays`](crate::Board::relays), [`set_relays`](crate::Board::set_relays),
+/// [`self_test`](crate::Board::self_test) and
+/// [`reset_device`](crate::Board::reset_device). The read-modify-write calls —
+/// [`switch_on`](crate::Board::switch_on), [`switch_off`](crate::Board::switch_off),
+/// [`toggle`](crate::Board::toggle) and [`pulse`](crate::Board::pulse) — retry
+/// only the claim: once one has reached the board, a write that timed out part
+/// way may have left the register holding something a second read would take as
+/// the truth, and a toggle that did latch would toggle back. The same goes for a
+/// [`Session`](crate::Session), whose calls are never retried; only
+/// [`Board::session`](crate::Board::session) claiming it is.
+///
+/// The default, [`RetryPolicy::NEVER`], tries every call once, as the library
+/// always has.
+///
+/// # Example
+///
+/// ```no_run
+/// use std::time::Duration;
+///
+/// use arb::{RetryPolicy, Usb};
+///
+/// // Up to five tries, 10, 20, 40 then 80 ms apart, give or take 5 ms, and
+/// // never more than half a second in all.
+/// let retry = RetryPolicy::attempts(5)
+///     .with_backoff(Duration::from_millis(10))
+///     .with_jitter(Duration::from_millis(5))
+///     .with_deadline(Duration::from_millis(500));
+///
+/// let usb = Usb::new().unwrap().with_retry(retry);
+///
+/// let relays = usb.board(None).relays().unwrap();
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    attempts: u32,
+    backoff: Duration,
+    jitter: Duration,
+    deadline: Option<Duration>,
+}
+
+impl RetryPolicy {
+    /// Tries every call once, and reports whatever it failed with.
+    pub const NEVER: Self = Self {
+        attempts: 1,
+        backoff: Duration::ZERO,
+        jitter: Duration::ZERO,
+        deadline: None,
+    };
+
+    /// Tries a call up to `attempts` times in all, one straight after another
+    /// unless [`with_backoff`](RetryPolicy::with_backoff) says otherwise.
+    ///
+    /// Zero attempts is taken as one: a call is always tried.
+    pub fn attempts(attempts: u32) -> Self {
+        Self {
+            attempts: attempts.max(1),
+            ..Self::NEVER
+        }
+    }
+
+    /// Returns this policy waiting `backoff` before the first retry, and twice as
+    /// long again before each one after it.
+    pub fn with_backoff(self, backoff: Duration) -> Self {
+        Self { backoff, ..self }
+    }
+
+    /// Returns this policy adding up to `jitter` more to each wait, chosen afresh
+    /// every time, so that callers that collided once do not collide again on the
+    /// same schedule.
+    pub fn with_jitter(self, jitter: Duration) -> Self {
+        Self { jitter, ..self }
+    }
+
+    /// Returns this policy giving up once `deadline` has passed since the first
+    /// try, whatever attempts it has left.
+    ///
+    /// A retry whose wait would end past the deadline is not started, so a call
+    /// returns its last failure within `deadline` of starting, plus however long
+    /// its tries themselves took.
+    pub fn with_deadline(self, deadline: Duration) -> Self {
+        Self {
+            deadline: Some(deadline),
+            ..self
+        }
+    }
+
+    /// Returns how many times a call is tried, at most.
+    pub fn max_attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Runs `op` until it succeeds, fails with something not worth retrying, or
+    /// this policy runs out of attempts or time.
+    pub(crate) fn run<R>(&self, mut op: impl FnMut() -> Result<R>) -> Result<R> {
+        let start = Instant::now();
+        let mut backoff = self.backoff;
+        let mut attempt = 1;
+
+        loop {
+            let error = match op() {
+                Err(e) if e.is_retryable() && attempt < self.attempts => e,
+                result => return result,
+            };
+
+            let wait = backoff + self.jitter();
+
+            if let Some(deadline) = self.deadline {
+                if start.elapsed() + wait > deadline {
+                    return Err(error);
+                }
+            }
+
+            thread::sleep(wait);
+
+            backoff = backoff.saturating_mul(2);
+            attempt += 1;
+        }
+    }
+
+    /// A wait somewhere between nothing and the jitter.
+    ///
+    /// Nothing here needs randomness anyone could not predict, only waits that do
+    /// not line up, and a fresh `RandomState` hashes to a different number every
+    /// time without a dependency.
+    fn jitter(&self) -> Duration {
+        if self.jitter.is_zero() {
+            return Duration::ZERO;
+        }
+
+        let random = RandomState::new().build_hasher().finish();
+
+        self.jitter.mul_f64(random as f64 / u64::MAX as f64)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::NEVER
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::{Error, Relay, Usb, Verify};
+
+    /// Runs `policy` over a call that fails with each of `failures` in turn, then
+    /// succeeds, and returns what it made of them along with how many tries it took.
+    fn tried(policy: RetryPolicy, failures: Vec<Error>) -> (Result<()>, usize) {
+        let tries = Cell::new(0);
+        let mut failures = failures.into_iter();
+
+        let result = policy.run(|| {
+            tries.set(tries.get() + 1);
+
+            failures.next().map_or(Ok(()), Err)
+        });
+
+        (result, tries.get())
+    }
+
+    #[test]
+    fn busy_boards_and_timeouts_are_tried_again() {
+        let failures = vec![Error::Busy, Error::Usb(rusb::Error::Timeout), Error::Busy];
+
+        let (result, tries) = tried(RetryPolicy::attempts(4), failures);
+
+        assert!(result.is_ok());
+        assert_eq!(tries, 4);
+    }
+
+    #[test]
+    fn a_lost_register_is_never_tried_again() {
+        let failures = vec![Error::out_of_sync(Error::Usb(rusb::Error::Timeout))];
+
+        let (result, tries) = tried(RetryPolicy::attempts(4), failures);
+
+        assert!(matches!(result, Err(Error::RegisterOutOfSync { .. })));
+        assert_eq!(tries, 1);
+    }
+
+    #[test]
+    fn the_last_failure_is_reported_once_the_attempts_run_out() {
+        let failures = vec![Error::Busy, Error::Busy, Error::Usb(rusb::Error::Timeout)];
+
+        let (result, tries) = tried(RetryPolicy::attempts(3), failures);
+
+        assert!(matches!(result, Err(Error::Usb(rusb::Error::Timeout))));
+        assert_eq!(tries, 3);
+    }
+
+    #[test]
+    fn the_default_policy_tries_once() {
+        let (result, tries) = tried(RetryPolicy::default(), vec![Error::Busy]);
+
+        assert!(matches!(result, Err(Error::Busy)));
+        assert_eq!(tries, 1);
+        assert_eq!(RetryPolicy::attempts(0), RetryPolicy::NEVER);
+    }
+
+    #[test]
+    fn a_retry_that_would_end_past_the_deadline_is_not_started() {
+        let policy = RetryPolicy::attempts(10)
+            .with_backoff(Duration::from_secs(60))
+            .with_deadline(Duration::from_millis(10));
+        let start = Instant::now();
+
+        let (result, tries) = tried(policy, vec![Error::Busy, Error::Busy]);
+
+        assert!(matches!(result, Err(Error::Busy)));
+        assert_eq!(tries, 1);
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn jitter_stays_within_its_bound() {
+        let policy = RetryPolicy::attempts(2).with_jitter(Duration::from_millis(3));
+
+        for _ in 0..100 {
+            assert!(policy.jitter() <= Duration::from_millis(3));
+        }
+    }
+
+    #[test]
+    fn a_board_waits_out_another_claim_instead_of_reporting_it_busy() {
+        let (usb, sims) = Usb::simulated(1);
+        let retry = RetryPolicy::attempts(50).with_backoff(Duration::from_millis(2));
+        let board = usb.with_retry(retry).board(None);
+        let (claimed, wait) = mpsc::channel();
+
+        let holder = {
+            let board = board.clone().with_retry(RetryPolicy::NEVER);
+
+            thread::spawn(move || {
+                let session = board.session().unwrap();
+
+                claimed.send(()).unwrap();
+                thread::sleep(Duration::from_millis(20));
+                drop(session);
+            })
+        };
+
+        wait.recv().unwrap();
+
+        board
+            .set_relays(Relay::Five.into(), Verify::Enabled)
+            .unwrap();
+        holder.join().unwrap();
+
+        assert_eq!(sims[0].outputs(), Relay::Five.into());
+    }
+}
@@ -0,0 +1,373 @@
+//! Holding a board's claim across several operations.
+
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+use crate::a6275::A6275;
+use crate::errors::Result;
+use crate::find::Select;
+use crate::relays::Relays;
+use crate::transport::Link;
+use crate::{Drive, Verify};
+
+/// A relay board claimed for as long as this lives, from [`Board::session`](crate::Board::session).
+///
+/// Every [`Board`](crate::Board) call finds, opens and claims the board afresh and
+/// lets it go again, which is what keeps a shared board usable by everyone. A
+/// session pays that once: the board is found and claimed when it is created, its
+/// operations go straight to the claimed device, and the claim is released when
+/// it is dropped.
+///
+/// The price is the one the per-call claim exists to avoid: **for the whole of a
+/// session, every other application — and every other `Board` in this process —
+/// gets [`Error::Busy`](crate::Error::Busy)**. Hold one for a control loop that owns
+/// the board, not across waits that other users of it could have filled.
+///
+/// What a session has that separate calls lack is atomicity: nothing else can
+/// reach the board between two of its operations, so a read followed by a write
+/// is a read-modify-write no other application can interleave with.
+///
+/// # Example
+///
+/// ```no_run
+/// use arb::{Relay, Relays, Verify};
+///
+/// let usb = arb::Usb::new().unwrap();
+/// let session = usb.board(None).session().unwrap();
+///
+/// for _ in 0..100 {
+///     session.set_relays(Relay::One.into(), Verify::Enabled).unwrap();
+///     session.set_relays(Relays::NONE, Verify::Enabled).unwrap();
+/// }
+///
+/// // The claim is released here, and other applications can reach the board again.
+/// drop(session);
+/// ```
+pub struct Session {
+    a6275: A6275<Box<dyn Link>>,
+    select: Select,
+}
+
+impl Session {
+    pub(crate) fn new(link: Box<dyn Link>, select: Select, drive: Drive) -> Self {
+        Self {
+            a6275: A6275::new(link).with_drive(drive),
+            select,
+        }
+    }
+
+    /// Returns the relays that are currently active.
+    ///
+    /// As [`Board::relays`](crate::Board::relays), on the claim this session holds.
+    ///
+    /// # Errors
+    ///
+    /// * [`Error::RegisterOutOfSync`](crate::Error::RegisterOutOfSync) — the read was
+    ///   interrupted, or the write that puts back what it consumed was
+    pub fn relays(&self) -> Result<Relays> {
+        self.a6275.status().map(Relays::from_bits)
+    }
+
+    /// Checks that the board answers correctly, without moving any relay, and
+    /// returns the relays it found active.
+    ///
+    /// As [`Board::self_test`](crate::Board::self_test), on the claim this session
+    /// holds.
+    ///
+    /// # Errors
+    ///
+    /// * [`Error::SelfTestFailed`](crate::Error::SelfTestFailed) — the test pattern
+    ///   did not survive the round trip
+    /// * [`Error::RegisterOutOfSync`](crate::Error::RegisterOutOfSync) — the check
+    ///   was interrupted and could not put the register's contents back
+    pub fn self_test(&self) -> Result<Relays> {
+        self.a6275.self_test().map(Relays::from_bits)
+    }
+
+    /// Activates `relays`, deactivating every relay not in the set.
+    ///
+    /// As [`Board::set_relays`](crate::Board::set_relays), on the claim this
+    /// session holds.
+    ///
+    /// # Errors
+    ///
+    /// * [`Error::VerificationFailed`](crate::Error::VerificationFailed) — the
+    ///   read-back did not match `relays`
+    /// * [`Error::VerificationRetriesExhausted`](crate::Error::VerificationRetriesExhausted)
+    ///   — with [`Verify::Retry`], no read-back matched `relays`
+    /// * [`Error::RegisterOutOfSync`](crate::Error::RegisterOutOfSync) — the
+    ///   read-back was interrupted and could not put the latched value back into
+    ///   the register
+    pub fn set_relays(&self, relays: Relays, verify: Verify) -> Result<()> {
+        self.a6275.set_status(relays.bits(), verify)
+    }
+
+    /// Activates `relays`, leaving every other relay as it is, and returns the
+    /// relays active afterwards.
+    ///
+    /// As [`Board::switch_on`](crate::Board::switch_on), on the claim this session
+    /// holds.
+    ///
+    /// # Errors
+    ///
+    /// As [`Session::relays`] for the read and [`Session::set_relays`] for the write.
+    pub fn switch_on(&self, relays: Relays, verify: Verify) -> Result<Relays> {
+        self.update(|active| active | relays, verify)
+    }
+
+    /// Deactivates `relays`, leaving every other relay as it is, and returns the
+    /// relays active afterwards.
+    ///
+    /// As [`Board::switch_off`](crate::Board::switch_off), on the claim this session
+    /// holds.
+    ///
+    /// # Errors
+    ///
+    /// As [`Session::relays`] for the read and [`Session::set_relays`] for the write.
+    pub fn switch_off(&self, relays: Relays, verify: Verify) -> Result<Relays> {
+        self.update(|active| active - relays, verify)
+    }
+
+    /// Flips every relay in `relays`, leaving every other relay as it is, and
+    /// returns the relays active afterwards.
+    ///
+    /// As [`Board::toggle`](crate::Board::toggle), on the claim this session holds.
+    ///
+    /// # Errors
+    ///
+    /// As [`Session::relays`] for the read and [`Session::set_relays`] for the write.
+    pub fn toggle(&self, relays: Relays, verify: Verify) -> Result<Relays> {
+        self.update(|active| active ^ relays, verify)
+    }
+
+    /// Activates `relays` for `duration` and then deactivates them, leaving every
+    /// other relay as it is, and returns the relays active afterwards.
+    ///
+    /// As [`Board::pulse`](crate::Board::pulse), on the claim this session holds.
+    ///
+    /// # Errors
+    ///
+    /// As [`Board::pulse`](crate::Board::pulse), less the errors of finding and
+    /// claiming the board.
+    pub fn pulse(&self, relays: Relays, duration: Duration, verify: Verify) -> Result<Relays> {
+        let before = self.relays()?;
+        let released = before - relays;
+
+        // Released on every path that may have latched anything, without reading
+        // the register again: the claim has been held throughout, so `before` is
+        // still the truth about every relay the pulse does not own, and a failed
+        // hold may have left the register disagreeing with the outputs anyway.
+        let held = self.set_relays(before | relays, verify);
+
+        if held.is_ok() {
+            thread::sleep(duration);
+        }
+
+        // A failed release outranks a failed hold: it is the one that may have left
+        // a relay energized.
+        self.set_relays(released, verify)?;
+        held?;
+
+        Ok(released)
+    }
+
+    /// Puts `relays` in the shift register without latching them, so that reads
+    /// report them again after the register was lost.
+    pub(crate) fn load(&self, relays: Relays) -> Result<()> {
+        self.a6275.load(relays.bits())
+    }
+
+    /// Reads the relays, writes back what `change` makes of them, and returns that.
+    ///
+    /// Writes even when nothing changes. A register that has drifted from the
+    /// outputs reads back wrong, and latching what it read is what puts the two
+    /// back in step; skipping the write would save 28 transfers by trusting exactly
+    /// the value in doubt.
+    fn update(&self, change: impl FnOnce(Relays) -> Relays, verify: Verify) -> Result<Relays> {
+        let relays = change(self.relays()?);
+
+        self.set_relays(relays, verify)?;
+
+        Ok(relays)
+    }
+}
+
+impl fmt::Debug for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Session")
+            .field("select", &self.select)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Names the board the session holds, as [`Board`](crate::Board)'s `Display` does.
+impl fmt::Display for Session {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.select.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sim::Fault;
+    use crate::{Error, Relay, Usb};
+
+    #[test]
+    fn a_session_holds_the_claim_until_it_is_dropped() {
+        let (usb, _sims) = Usb::simulated(1);
+        let board = usb.board(None);
+
+        let session = board.session().unwrap();
+
+        assert!(matches!(board.relays(), Err(Error::Busy)));
+        assert!(matches!(board.session(), Err(Error::Busy)));
+
+        drop(session);
+
+        assert!(board.relays().is_ok());
+    }
+
+    #[test]
+    fn a_session_drives_the_board_it_claimed() {
+        let (usb, sims) = Usb::simulated(2);
+        let session = usb.board(Some(2)).session().unwrap();
+
+        session
+            .set_relays(Relay::One | Relay::Eight, Verify::Enabled)
+            .unwrap();
+
+        assert_eq!(sims[1].outputs(), Relay::One | Relay::Eight);
+        assert_eq!(session.relays().unwrap(), Relay::One | Relay::Eight);
+        assert_eq!(session.self_test().unwrap(), Relay::One | Relay::Eight);
+        assert_eq!(session.to_string(), "port 2");
+    }
+
+    #[test]
+    fn switching_changes_only_the_relays_it_names() {
+        let (usb, sims) = Usb::simulated(1);
+        let session = usb.board(None).session().unwrap();
+        sims[0].latch(Relay::One | Relay::Two);
+
+        let on = session
+            .switch_on(Relay::Three | Relay::Four, Verify::Enabled)
+            .unwrap();
+        assert_eq!(on, Relay::One | Relay::Two | Relay::Three | Relay::Four);
+
+        let off = session
+            .switch_off(Relay::Two | Relay::Eight, Verify::Enabled)
+            .unwrap();
+        assert_eq!(off, Relay::One | Relay::Three | Relay::Four);
+
+        let toggled = session
+            .toggle(Relay::One | Relay::Five, Verify::Enabled)
+            .unwrap();
+        assert_eq!(toggled, Relay::Three | Relay::Four | Relay::Five);
+
+        assert_eq!(sims[0].outputs(), toggled);
+        assert_eq!(sims[0].register(), toggled);
+    }
+
+    #[test]
+    fn a_switch_that_cannot_read_writes_nothing() {
+        // The read is what the new state is built from, so without it there is
+        // nothing safe to latch.
+        let (usb, sims) = Usb::simulated(1);
+        let session = usb.board(None).session().unwrap();
+        sims[0].latch(Relay::Six.into());
+        sims[0].set_fault(Some(Fault::TimeoutAfter {
+            reads: 0,
+            writes: usize::MAX,
+        }));
+
+        assert!(matches!(
+            session.switch_on(Relay::One.into(), Verify::Enabled),
+            Err(Error::RegisterOutOfSync { .. })
+        ));
+        assert_eq!(sims[0].outputs(), Relay::Six.into());
+    }
+
+    #[test]
+    fn a_pulse_releases_the_relays_it_energized_and_only_those() {
+        let (usb, sims) = Usb::simulated(1);
+        let session = usb.board(None).session().unwrap();
+        sims[0].latch(Relay::One | Relay::Two);
+
+        let after = session
+            .pulse(Relay::Two | Relay::Four, Duration::ZERO, Verify::Enabled)
+            .unwrap();
+
+        // Relay 2 was already on and is off afterwards: the pulse owns the relays it
+        // names for its whole length, end included.
+        assert_eq!(after, Relay::One.into());
+        assert_eq!(sims[0].outputs(), Relay::One.into());
+        assert_eq!(sims[0].register(), Relay::One.into());
+    }
+
+    #[test]
+    fn a_pulse_that_fails_to_hold_still_releases() {
+        // A stuck read fails verification of the hold while the relays are
+        // energized; releasing to the empty set reads back as zeros and passes.
+        let (usb, sims) = Usb::simulated(1);
+        let session = usb.board(None).session().unwrap();
+        sims[0].set_fault(Some(Fault::StuckLow));
+
+        let err = session
+            .pulse(
+                Relay::Four.into(),
+                Duration::from_secs(3600),
+                Verify::Enabled,
+            )
+            .unwrap_err();
+
+        assert!(matches!(err, Error::VerificationFailed { .. }));
+        assert_eq!(sims[0].outputs(), Relays::NONE);
+    }
+
+    #[test]
+    fn a_pulse_that_fails_to_release_says_so() {
+        // Enough line changes for the read's restore, the hold and its read-back's
+        // restore (26 + 28 + 26), and a few of the release's.
+        let (usb, sims) = Usb::simulated(1);
+        let session = usb.board(None).session().unwrap();
+        sims[0].set_fault(Some(Fault::TimeoutAfter {
+            reads: usize::MAX,
+            writes: 85,
+        }));
+
+        let err = session
+            .pulse(Relay::Four.into(), Duration::ZERO, Verify::Enabled)
+            .unwrap_err();
+
+        assert!(matches!(err, Error::Usb(rusb::Error::Timeout)));
+        assert_eq!(sims[0].outputs(), Relay::Four.into());
+    }
+
+    #[test]
+    fn a_session_reports_the_errors_a_board_does() {
+        let (usb, sims) = Usb::simulated(1);
+        let session = usb.board(None).session().unwrap();
+
+        sims[0].set_fault(Some(Fault::StuckLow));
+
+        assert!(matches!(
+            session.set_relays(Relay::Two.into(), Verify::Enabled),
+            Err(Error::VerificationFailed { .. })
+        ));
+        assert!(matches!(session.self_test(), Err(Error::SelfTestFailed)));
+    }
+
+    #[test]
+    fn a_session_on_an_unplugged_board_fails_its_transfers() {
+        let (usb, sims) = Usb::simulated(1);
+        let session = usb.board(None).session().unwrap();
+
+        sims[0].unplug();
+
+        assert!(matches!(
+            session.relays(),
+            Err(Error::RegisterOutOfSync { .. })
+        ));
+    }
+}
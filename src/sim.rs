@@ -0,0 +1,739 @@
+//! Simulated relay boards, for exercising a [`Board`](crate::Board) without one.
+//!
+//! [`Usb::simulated`](crate::Usb::simulated) builds a context whose boards are
+//! models rather than hardware. Everything above the GPIO lines is the code that
+//! drives a real board — the same shift register protocol, the same restores, the
+//! same errors — so a test written against a simulated board exercises the
+//! library's behaviour and not a stand-in for it. Each model is the one the
+//! crate's own protocol tests run against.
+//!
+//! A [`SimBoard`] is the other side of one of those boards: what its relays
+//! physically hold, what its shift register holds, and the [`Fault`]s it can be
+//! told to develop.
+//!
+//! # Example
+//!
+//! ```
+//! use arb::sim::Fault;
+//! use arb::{Error, Relay, Usb, Verify};
+//!
+//! let (usb, sims) = Usb::simulated(1);
+//! let board = usb.board(None);
+//!
+//! board.set_relays(Relay::One | Relay::Three, Verify::Enabled).unwrap();
+//! assert_eq!(sims[0].outputs(), Relay::One | Relay::Three);
+//!
+//! // A read path that drops the first bit it clocks out
+//! sims[0].set_fault(Some(Fault::DropMsb));
+//!
+//! let err = board.set_relays(Relay::Eight.into(), Verify::Enabled).unwrap_err();
+//! assert!(matches!(err, Error::VerificationFailed { .. }));
+//! ```
+
+use std::cell::Cell;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+use crate::a6275::{CLK, DATA, LATCH, READ};
+use crate::ch341a::{Gpio, SAMPLES, Step};
+use crate::errors::{Error, Result};
+use crate::find::Path;
+use crate::relays::Relays;
+use crate::transport::{Device, Link, Transport};
+
+/// The register bit presented on the A6275 serial output.
+const MSB: u8 = 0b1000_0000;
+
+/// The bus every simulated board hangs off.
+const BUS: u8 = 1;
+
+/// A simulated A6275 driven through the same GPIO lines as the real one.
+///
+/// The register shifts left on each rising CLK edge, taking in the DATA line,
+/// and presents its most significant bit on the serial output (D7). A rising
+/// LATCH edge copies the register to the relay outputs.
+#[derive(Default)]
+pub(crate) struct FakeA6275 {
+    pub(crate) lines: Cell<u8>,
+    pub(crate) register: Cell<u8>,
+    pub(crate) outputs: Cell<u8>,
+}
+
+impl Gpio for FakeA6275 {
+    fn set_output(&self, data: u8) -> Result<()> {
+        let previous = self.lines.replace(data);
+        let rising = |pin: u8| previous & pin == 0 && data & pin != 0;
+
+        if rising(CLK) {
+            let bit = u8::from(data & DATA != 0);
+            self.register.set(self.register.get() << 1 | bit);
+        }
+
+        if rising(LATCH) {
+            self.outputs.set(self.register.get());
+        }
+
+        Ok(())
+    }
+
+    /// Drives the sampling one state at a time, exactly as the pin states a
+    /// UIO stream runs would: batching it on the wire must not change what the
+    /// device sees.
+    fn sample_clocked(&self, clock: u8) -> Result<[u8; SAMPLES]> {
+        let mut samples = [0; SAMPLES];
+
+        self.set_output(0)?;
+
+        for sample in &mut samples {
+            let serial_out = self.register.get() & MSB != 0;
+
+            *sample = if serial_out { READ } else { 0 };
+
+            self.set_output(clock)?;
+            self.set_output(0)?;
+        }
+
+        Ok(samples)
+    }
+
+    /// Clocks the byte through one bit at a time on the same lines, so that the
+    /// SPI drive is checked against the very model the bit-banged one is.
+    fn exchange(&self, data: u8) -> Result<u8> {
+        let mut answer = 0;
+
+        self.set_output(0)?;
+
+        for bit in (0..8).rev() {
+            let line = if data & (1 << bit) != 0 { DATA } else { 0 };
+
+            answer = answer << 1 | u8::from(self.register.get() & MSB != 0);
+
+            self.set_output(line)?;
+            self.set_output(CLK | line)?;
+        }
+
+        self.set_output(0)?;
+
+        Ok(answer)
+    }
+
+    /// Takes no notice of the waits: the model's lines settle at once, so what
+    /// a stream without them would get wrong on the wire cannot show here.
+    fn stream(&self, steps: &[Step]) -> Result<Vec<u8>> {
+        let mut samples = Vec::new();
+
+        self.set_output(0)?;
+
+        for step in steps {
+            match *step {
+                Step::Output(data) => self.set_output(data)?,
+                Step::Sample => {
+                    let serial_out = self.register.get() & MSB != 0;
+
+                    samples.push(if serial_out { READ } else { 0 });
+                }
+                Step::Wait(_) => {}
+            }
+        }
+
+        Ok(samples)
+    }
+}
+
+/// A fault a [`SimBoard`] can be told to develop.
+///
+/// Each is one the crate's own tests drive the protocol through, and each leaves
+/// the relay outputs exactly where the hardware fault would.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Fault {
+    /// The serial output is never driven, so every read returns zeros. Writes still
+    /// reach the register and the latch: the relays move, the board cannot say so.
+    StuckLow,
+    /// Every read drops the first bit the register presents, its most significant.
+    /// The register underneath stays real, so a read-back disagrees with what was
+    /// written without anything else being wrong.
+    DropMsb,
+    /// The transport gives out with a USB timeout once `reads` clocked reads and
+    /// `writes` line changes have gone through, and stays out until the fault is
+    /// cleared. An SPI exchange clocks the register, so it spends a read, and so
+    /// does a stream that samples; one that does not spends a single write, however
+    /// many lines it changes.
+    ///
+    /// The clocked read that fails has still clocked the register, as it does on
+    /// the wire: the stream is what clocks it, so a response that never arrives has
+    /// already cost the contents. [`SimBoard::fault`] reports what is left of the
+    /// budget.
+    TimeoutAfter {
+        /// Clocked reads, and SPI exchanges, that still succeed.
+        reads: usize,
+        /// Line changes that still succeed.
+        writes: usize,
+    },
+    /// As [`Fault::TimeoutAfter`], but the transport comes back at once: the one
+    /// transfer that times out clears the fault, and the next goes through. What a
+    /// loose cable or a busy hub looks like, and what recovery has to get past.
+    GlitchAfter {
+        /// Clocked reads, and SPI exchanges, that still succeed.
+        reads: usize,
+        /// Line changes that still succeed.
+        writes: usize,
+    },
+}
+
+/// One simulated board's state, behind the lock its [`SimBoard`] and its claims share.
+#[derive(Default)]
+struct Chip {
+    a6275: FakeA6275,
+    fault: Option<Fault>,
+    attached: bool,
+    claimed: bool,
+}
+
+impl Chip {
+    /// Fails the transfer if the board has gone away.
+    fn reachable(&self) -> Result<()> {
+        if self.attached {
+            Ok(())
+        } else {
+            Err(Error::Usb(rusb::Error::NoDevice))
+        }
+    }
+
+    /// Spends one line change of a [`Fault::TimeoutAfter`] or
+    /// [`Fault::GlitchAfter`] budget.
+    fn spend_write(&mut self) -> Result<()> {
+        match &mut self.fault {
+            Some(Fault::TimeoutAfter { writes: 0, .. }) => Err(Error::Usb(rusb::Error::Timeout)),
+            Some(Fault::GlitchAfter { writes: 0, .. }) => self.glitch(),
+            Some(Fault::TimeoutAfter { writes, .. } | Fault::GlitchAfter { writes, .. }) => {
+                *writes -= 1;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Spends one clocked read of a [`Fault::TimeoutAfter`] or
+    /// [`Fault::GlitchAfter`] budget.
+    fn spend_read(&mut self) -> Result<()> {
+        match &mut self.fault {
+            Some(Fault::TimeoutAfter { reads: 0, .. }) => Err(Error::Usb(rusb::Error::Timeout)),
+            Some(Fault::GlitchAfter { reads: 0, .. }) => self.glitch(),
+            Some(Fault::TimeoutAfter { reads, .. } | Fault::GlitchAfter { reads, .. }) => {
+                *reads -= 1;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Times out the one transfer a [`Fault::GlitchAfter`] fires on, and clears it.
+    fn glitch(&mut self) -> Result<()> {
+        self.fault = None;
+
+        Err(Error::Usb(rusb::Error::Timeout))
+    }
+}
+
+/// One simulated board: the transport's view and the test's view share it.
+struct Slot {
+    path: Path,
+    chip: Mutex<Chip>,
+}
+
+impl Slot {
+    /// The board's state. A test that panicked holding the lock left nothing half
+    /// written that the next caller could trip over, so poisoning is ignored.
+    fn chip(&self) -> MutexGuard<'_, Chip> {
+        self.chip.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// The far side of a simulated board: its relays, its register and its faults.
+///
+/// Cheap to clone, and every clone is the same board.
+#[derive(Clone)]
+pub struct SimBoard(Arc<Slot>);
+
+impl SimBoard {
+    /// Returns the port the board is plugged into, as [`Board::port`](crate::Board::port)
+    /// reports it.
+    pub fn port(&self) -> u8 {
+        self.0
+            .path
+            .port()
+            .expect("a simulated board is never a root hub")
+    }
+
+    /// Returns the relays the board's outputs physically hold.
+    ///
+    /// What a read cannot tell you: the A6275 hands back its shift register, not
+    /// its outputs, so this is the ground truth a test checks the library against.
+    pub fn outputs(&self) -> Relays {
+        Relays::from_bits(self.0.chip().a6275.outputs.get())
+    }
+
+    /// Returns the relays the board's shift register holds, which is what a read
+    /// reports and what the next latch would copy to the outputs.
+    pub fn register(&self) -> Relays {
+        Relays::from_bits(self.0.chip().a6275.register.get())
+    }
+
+    /// Puts `relays` in both the register and the outputs, as a write latched before
+    /// the test began would have left them.
+    pub fn latch(&self, relays: Relays) {
+        let chip = self.0.chip();
+
+        chip.a6275.register.set(relays.bits());
+        chip.a6275.outputs.set(relays.bits());
+    }
+
+    /// Returns the fault the board currently has, with what is left of any budget.
+    pub fn fault(&self) -> Option<Fault> {
+        self.0.chip().fault
+    }
+
+    /// Gives the board `fault`, or clears it with `None`. Takes effect from the next
+    /// transfer, including on a claim already held.
+    pub fn set_fault(&self, fault: Option<Fault>) {
+        self.0.chip().fault = fault;
+    }
+
+    /// Unplugs the board: enumeration stops finding it, and a claim already held
+    /// fails every transfer with `NoDevice`. The register and outputs are kept.
+    pub fn unplug(&self) {
+        self.0.chip().attached = false;
+    }
+
+    /// Plugs the board back into the port it came from.
+    pub fn plug(&self) {
+        self.0.chip().attached = true;
+    }
+}
+
+impl fmt::Debug for SimBoard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SimBoard")
+            .field("path", &self.0.path)
+            .field("outputs", &self.outputs())
+            .field("register", &self.register())
+            .field("fault", &self.fault())
+            .finish()
+    }
+}
+
+/// A bus of simulated boards, each on its own port of bus 1.
+pub(crate) struct Sim(Vec<Arc<Slot>>);
+
+impl Sim {
+    /// Plugs in `boards` boards, every relay off, on ports 1 to `boards`.
+    ///
+    /// # Panics
+    ///
+    /// If `boards` is more than a hub has ports to number.
+    pub(crate) fn new(boards: usize) -> (Self, Vec<SimBoard>) {
+        let slots: Vec<_> = (1..=boards)
+            .map(|port| {
+                let port = u8::try_from(port).expect("at most 255 simulated boards");
+
+                Arc::new(Slot {
+                    path: Path::new(BUS, [port]),
+                    chip: Mutex::new(Chip {
+                        attached: true,
+                        ..Chip::default()
+                    }),
+                })
+            })
+            .collect();
+
+        let handles = slots.iter().cloned().map(SimBoard).collect();
+
+        (Self(slots), handles)
+    }
+}
+
+impl fmt::Debug for Sim {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Sim").field(&self.0.len()).finish()
+    }
+}
+
+impl Transport for Sim {
+    fn devices(&self) -> Result<Vec<Box<dyn Device>>> {
+        Ok(self
+            .0
+            .iter()
+            .filter(|slot| slot.chip().attached)
+            .map(|slot| Box::new(SimDevice(slot.clone())) as Box<dyn Device>)
+            .collect())
+    }
+}
+
+/// A simulated board as enumeration finds it.
+struct SimDevice(Arc<Slot>);
+
+impl Device for SimDevice {
+    fn path(&self) -> Result<Path> {
+        Ok(self.0.path.clone())
+    }
+
+    fn claim(&self) -> Result<Box<dyn Link>> {
+        let mut chip = self.0.chip();
+
+        chip.reachable()?;
+
+        if chip.claimed {
+            return Err(Error::Busy);
+        }
+
+        chip.claimed = true;
+
+        Ok(Box::new(SimLink(self.0.clone())))
+    }
+}
+
+/// A claimed simulated board. Dropping it releases the claim.
+struct SimLink(Arc<Slot>);
+
+impl Gpio for SimLink {
+    fn set_output(&self, data: u8) -> Result<()> {
+        let mut chip = self.0.chip();
+
+        chip.reachable()?;
+        chip.spend_write()?;
+
+        chip.a6275.set_output(data)
+    }
+
+    fn sample_clocked(&self, clock: u8) -> Result<[u8; SAMPLES]> {
+        let mut chip = self.0.chip();
+
+        chip.reachable()?;
+
+        let mut samples = chip.a6275.sample_clocked(clock)?;
+
+        chip.spend_read()?;
+
+        match chip.fault {
+            Some(Fault::StuckLow) => samples = [0; SAMPLES],
+            Some(Fault::DropMsb) => samples[0] &= !READ,
+            _ => {}
+        }
+
+        Ok(samples)
+    }
+
+    fn exchange(&self, data: u8) -> Result<u8> {
+        let mut chip = self.0.chip();
+
+        chip.reachable()?;
+
+        let mut answer = chip.a6275.exchange(data)?;
+
+        chip.spend_read()?;
+
+        match chip.fault {
+            Some(Fault::StuckLow) => answer = 0,
+            Some(Fault::DropMsb) => answer &= !MSB,
+            _ => {}
+        }
+
+        Ok(answer)
+    }
+
+    fn stream(&self, steps: &[Step]) -> Result<Vec<u8>> {
+        let mut chip = self.0.chip();
+
+        chip.reachable()?;
+
+        if !steps.contains(&Step::Sample) {
+            chip.spend_write()?;
+
+            return chip.a6275.stream(steps);
+        }
+
+        let mut samples = chip.a6275.stream(steps)?;
+
+        chip.spend_read()?;
+
+        match chip.fault {
+            Some(Fault::StuckLow) => samples.fill(0),
+            Some(Fault::DropMsb) => {
+                if let Some(first) = samples.first_mut() {
+                    *first &= !READ;
+                }
+            }
+            _ => {}
+        }
+
+        Ok(samples)
+    }
+}
+
+impl Link for SimLink {
+    fn reset(&self) -> Result<()> {
+        self.0.chip().reachable()
+    }
+}
+
+impl Drop for SimLink {
+    fn drop(&mut self) {
+        self.0.chip().claimed = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Drive, Relay, Usb, Verify};
+
+    #[test]
+    fn a_simulated_board_is_driven_end_to_end() {
+        let (usb, sims) = Usb::simulated(1);
+        let board = usb.board(None);
+
+        board
+            .set_relays(Relay::Two | Relay::Seven, Verify::Enabled)
+            .unwrap();
+
+        assert_eq!(sims[0].outputs(), Relay::Two | Relay::Seven);
+        assert_eq!(board.relays().unwrap(), Relay::Two | Relay::Seven);
+        assert_eq!(board.self_test().unwrap(), Relay::Two | Relay::Seven);
+
+        // Neither the read nor the check may leave the register disagreeing with
+        // the outputs.
+        assert_eq!(sims[0].register(), sims[0].outputs());
+    }
+
+    #[test]
+    fn a_simulated_board_is_driven_over_spi() {
+        let (usb, sims) = Usb::simulated(2);
+        let usb = usb.with_drive(Drive::Spi);
+        let board = usb.board(Some(2));
+
+        board
+            .set_relays(Relay::Three | Relay::Eight, Verify::Enabled)
+            .unwrap();
+
+        assert_eq!(board.drive(), Drive::Spi);
+        assert_eq!(sims[1].outputs(), Relay::Three | Relay::Eight);
+        assert_eq!(board.self_test().unwrap(), Relay::Three | Relay::Eight);
+        assert_eq!(sims[1].register(), sims[1].outputs());
+
+        // The faults reach the exchange as they reach a clocked read.
+        sims[1].set_fault(Some(Fault::DropMsb));
+
+        let err = board
+            .set_relays(Relay::Eight.into(), Verify::Enabled)
+            .unwrap_err();
+
+        assert!(matches!(err, Error::VerificationFailed { .. }));
+        assert_eq!(
+            usb.board(Some(1)).with_drive(Drive::Gpio).drive(),
+            Drive::Gpio
+        );
+    }
+
+    #[test]
+    fn a_simulated_board_is_driven_by_streams() {
+        let (usb, sims) = Usb::simulated(1);
+        let board = usb.board(None).with_drive(Drive::Stream);
+
+        board
+            .set_relays(Relay::Five.into(), Verify::Enabled)
+            .unwrap();
+
+        assert_eq!(sims[0].outputs(), Relay::Five.into());
+        assert_eq!(board.relays().unwrap(), Relay::Five.into());
+        assert_eq!(sims[0].register(), sims[0].outputs());
+
+        // A write that samples nothing spends a write; the read-back a read.
+        sims[0].set_fault(Some(Fault::TimeoutAfter {
+            reads: 0,
+            writes: 1,
+        }));
+
+        board.set_relays(Relays::NONE, Verify::Disabled).unwrap();
+
+        let err = board.set_relays(Relays::NONE, Verify::Enabled).unwrap_err();
+
+        assert!(matches!(err, Error::RegisterOutOfSync { .. }));
+        assert_eq!(sims[0].outputs(), Relays::NONE);
+    }
+
+    #[test]
+    fn simulated_boards_enumerate_by_port() {
+        let (usb, sims) = Usb::simulated(3);
+
+        let boards = usb.boards().unwrap();
+        let labels: Vec<_> = boards.iter().map(ToString::to_string).collect();
+
+        assert_eq!(labels, ["port 1 (1-1)", "port 2 (1-2)", "port 3 (1-3)"]);
+        assert_eq!(boards[2].path(), Some(&Path::new(1, [3])));
+        assert_eq!(usb.board(Some(3)).path(), None);
+        assert_eq!(usb.board(Some(3)).locate().unwrap(), Path::new(1, [3]));
+
+        boards[1].set_relays(Relays::ALL, Verify::Enabled).unwrap();
+
+        assert_eq!(sims[0].outputs(), Relays::NONE);
+        assert_eq!(sims[1].outputs(), Relays::ALL);
+        assert_eq!(sims[2].outputs(), Relays::NONE);
+        assert_eq!(sims[1].port(), 2);
+    }
+
+    #[test]
+    fn selecting_among_simulated_boards_reports_what_it_finds() {
+        let (usb, sims) = Usb::simulated(2);
+
+        assert!(matches!(
+            usb.board(None).relays(),
+            Err(Error::MultipleFound)
+        ));
+        assert!(matches!(usb.board(Some(3)).relays(), Err(Error::NotFound)));
+
+        sims[0].unplug();
+
+        assert!(usb.board(None).relays().is_ok());
+        assert_eq!(usb.boards().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn a_stuck_serial_output_fails_verification_but_still_latches() {
+        let (usb, sims) = Usb::simulated(1);
+        sims[0].set_fault(Some(Fault::StuckLow));
+
+        let err = usb
+            .board(None)
+            .set_relays(Relay::Four.into(), Verify::Enabled)
+            .unwrap_err();
+
+        assert!(matches!(
+            err,
+            Error::VerificationFailed { expected, actual }
+                if expected == Relay::Four.into() && actual == Relays::NONE
+        ));
+        assert_eq!(sims[0].outputs(), Relay::Four.into());
+        assert!(matches!(
+            usb.board(None).self_test(),
+            Err(Error::SelfTestFailed)
+        ));
+    }
+
+    #[test]
+    fn a_dropped_msb_fails_the_self_test_without_moving_a_relay() {
+        let (usb, sims) = Usb::simulated(1);
+        sims[0].latch(Relay::One | Relay::Three);
+        sims[0].set_fault(Some(Fault::DropMsb));
+
+        // The relays' own top bit is clear, so a plain read survives the fault and
+        // only the inverted test pattern gives it away.
+        assert_eq!(usb.board(None).relays().unwrap(), Relay::One | Relay::Three);
+        assert!(matches!(
+            usb.board(None).self_test(),
+            Err(Error::SelfTestFailed)
+        ));
+        assert_eq!(sims[0].outputs(), Relay::One | Relay::Three);
+        assert_eq!(sims[0].register(), Relay::One | Relay::Three);
+    }
+
+    #[test]
+    fn a_read_that_times_out_reports_a_lost_register() {
+        let (usb, sims) = Usb::simulated(1);
+        sims[0].latch(Relay::One | Relay::Six);
+        sims[0].set_fault(Some(Fault::TimeoutAfter {
+            reads: 0,
+            writes: usize::MAX,
+        }));
+
+        let err = usb.board(None).relays().unwrap_err();
+
+        assert!(matches!(err, Error::RegisterOutOfSync { .. }));
+        assert_eq!(sims[0].outputs(), Relay::One | Relay::Six);
+        assert_eq!(sims[0].register(), Relays::NONE);
+
+        // The documented way out: write a known state.
+        sims[0].set_fault(None);
+        usb.board(None)
+            .set_relays(Relay::One | Relay::Six, Verify::Enabled)
+            .unwrap();
+
+        assert_eq!(sims[0].register(), sims[0].outputs());
+    }
+
+    #[test]
+    fn a_timeout_spends_its_budget_before_it_fires() {
+        let (usb, sims) = Usb::simulated(1);
+        sims[0].set_fault(Some(Fault::TimeoutAfter {
+            reads: 1,
+            writes: 30,
+        }));
+
+        // Writing and latching costs 28 line changes, which the budget covers; the
+        // restore after the verifying read does not fit in the two that are left.
+        let err = usb
+            .board(None)
+            .set_relays(Relay::Two.into(), Verify::Enabled)
+            .unwrap_err();
+
+        assert!(matches!(err, Error::RegisterOutOfSync { .. }));
+        assert_eq!(sims[0].outputs(), Relay::Two.into());
+        assert_eq!(
+            sims[0].fault(),
+            Some(Fault::TimeoutAfter {
+                reads: 0,
+                writes: 0
+            })
+        );
+    }
+
+    #[test]
+    fn a_claimed_board_is_busy_until_released() {
+        let (usb, _sims) = Usb::simulated(1);
+        let device = find_one(&usb);
+
+        let link = device.claim().unwrap();
+
+        assert!(matches!(usb.board(None).relays(), Err(Error::Busy)));
+
+        drop(link);
+
+        assert!(usb.board(None).relays().is_ok());
+    }
+
+    #[test]
+    fn a_board_is_located_without_being_claimed() {
+        let (usb, _sims) = Usb::simulated(2);
+
+        let _held = usb.board(Some(2)).session().unwrap();
+
+        assert_eq!(usb.board(Some(2)).locate().unwrap(), Path::new(1, [2]));
+        assert!(matches!(
+            usb.board(None).locate(),
+            Err(Error::MultipleFound)
+        ));
+        assert!(matches!(usb.board(Some(3)).locate(), Err(Error::NotFound)));
+    }
+
+    #[test]
+    fn an_unplugged_board_fails_a_claim_already_held() {
+        let (usb, sims) = Usb::simulated(1);
+        let link = find_one(&usb).claim().unwrap();
+
+        sims[0].unplug();
+
+        assert!(matches!(
+            link.set_output(0),
+            Err(Error::Usb(rusb::Error::NoDevice))
+        ));
+
+        drop(link);
+        sims[0].plug();
+
+        assert!(usb.board(None).relays().is_ok());
+    }
+
+    fn find_one(usb: &Usb) -> Box<dyn Device> {
+        crate::find::find_device(&*usb.transport, &crate::find::Select::Any).unwrap()
+    }
+}
@@ -0,0 +1,722 @@
+//! Recording what goes over the wire to a board, and playing it back.
+//!
+//! A board that misbehaves in the field usually does so where nobody can watch
+//! it. A [`Trace`] records every bulk transfer the CH341A driver makes — each write
+//! and read, its endpoint and bytes, how long it took and what libusb made of it
+//! — from a context built with [`Usb::traced`](crate::Usb::traced), or with
+//! `arb --trace FILE`. A [`Replay`] is a [`Transport`] that answers from such a
+//! recording: make the same calls again in a test, and they meet the same answers
+//! and the same failures, byte for byte, on every run.
+//!
+//! # Format
+//!
+//! Plain text, one transfer a line, so that a trace can be read as well as played
+//! back:
+//!
+//! ```text
+//! # arb trace
+//! 0.000412 1-1.3 write 02 ab807f00888000888000888000888000888000888000888000888020 38us ok 28
+//! 0.000497 1-1.3 read 82 8 951us ok 8000000000000080
+//! 0.001733 1-1.3 read 82 8 1000214us err Timeout
+//! 0.002001 1-1.3 reset 1520us ok
+//! ```
+//!
+//! Each line gives when the transfer started, in seconds since the trace was
+//! created; the board's path; the transfer — a write's endpoint and bytes, a
+//! read's endpoint and how many bytes it asked for, or a port reset; how long it
+//! took; and what it came to: how many bytes a write sent, the bytes a read got
+//! back, `-` for none, or the libusb error either failed with. Lines starting
+//! with `#` are comments.
+//!
+//! Only transfers are recorded. Enumeration and claims are not, so a replayed
+//! trace has every board it mentions attached, and free, throughout.
+//!
+//! # Example
+//!
+//! ```no_run
+//! use arb::Usb;
+//! use arb::trace::{Replay, Trace};
+//!
+//! // In the field:
+//! let usb = Usb::traced(Trace::create("board.trace").unwrap()).unwrap();
+//! let relays = usb.board(None).relays();
+//!
+//! // Then in a test, with the file that came back:
+//! let usb = Usb::with_transport(Replay::open("board.trace").unwrap());
+//! assert_eq!(usb.board(None).relays().ok(), relays.ok());
+//! ```
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use crate::ch341a::{Bulk, Ch341a};
+use crate::errors::Result;
+use crate::find::Path;
+use crate::transport::{Device, Link, Transport};
+
+/// What a trace opens with, so that a file can be told for one at a glance.
+const HEADER: &str = "# arb trace";
+
+/// Every error libusb reports, by the name a trace spells it with.
+const ERRORS: [rusb::Error; 14] = [
+    rusb::Error::Io,
+    rusb::Error::InvalidParam,
+    rusb::Error::Access,
+    rusb::Error::NoDevice,
+    rusb::Error::NotFound,
+    rusb::Error::Busy,
+    rusb::Error::Timeout,
+    rusb::Error::Overflow,
+    rusb::Error::Pipe,
+    rusb::Error::Interrupted,
+    rusb::Error::NoMem,
+    rusb::Error::NotSupported,
+    rusb::Error::BadDescriptor,
+    rusb::Error::Other,
+];
+
+/// Bytes as a trace spells them: two hex digits each, run together, or `-` for
+/// none at all.
+struct Hex<'a>(&'a [u8]);
+
+impl fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("-");
+        }
+
+        self.0.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
+/// Reads bytes back from how [`Hex`] spells them.
+fn unhex(text: &str) -> Option<Vec<u8>> {
+    if text == "-" {
+        return Some(Vec::new());
+    }
+
+    if text.len() % 2 != 0 {
+        return None;
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|at| u8::from_str_radix(text.get(at..at + 2)?, 16).ok())
+        .collect()
+}
+
+/// Where a recording goes: a file, or anything else that can be written to.
+///
+/// Cheap to clone, and every clone writes to the same place, in the order the
+/// transfers happened, from however many boards and threads.
+///
+/// Each transfer is written as soon as it is over, with nothing buffered, so a
+/// process that is killed or hangs leaves everything up to that moment behind.
+/// A transfer that cannot be written is dropped, as is every one after it, rather
+/// than failing the call it records: [`flush`](Trace::flush) reports it.
+#[derive(Clone)]
+pub struct Trace(Arc<Recording>);
+
+struct Recording {
+    started: Instant,
+    sink: Mutex<Sink>,
+}
+
+struct Sink {
+    writer: Box<dyn Write + Send>,
+    failed: Option<io::Error>,
+}
+
+impl Trace {
+    /// Records to `writer`, starting the clock the transfers are timed by.
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        let trace = Self(Arc::new(Recording {
+            started: Instant::now(),
+            sink: Mutex::new(Sink {
+                writer: Box::new(writer),
+                failed: None,
+            }),
+        }));
+
+        trace.write(format_args!("{HEADER}"));
+
+        trace
+    }
+
+    /// Records to the file at `path`, replacing whatever it held.
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be created.
+    pub fn create(path: impl AsRef<std::path::Path>) -> io::Result<Self> {
+        Ok(Self::new(File::create(path)?))
+    }
+
+    /// Flushes what has been recorded, and reports the first transfer that could
+    /// not be written, if one could not.
+    ///
+    /// # Errors
+    ///
+    /// The error the first unwritten transfer failed with, or the flush's own.
+    pub fn flush(&self) -> io::Result<()> {
+        let mut sink = self.sink();
+
+        match sink.failed.take() {
+            Some(e) => Err(e),
+            None => sink.writer.flush(),
+        }
+    }
+
+    fn sink(&self) -> MutexGuard<'_, Sink> {
+        self.0.sink.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Writes one line, unless an earlier one has failed.
+    fn write(&self, line: fmt::Arguments<'_>) {
+        let mut sink = self.sink();
+
+        if sink.failed.is_some() {
+            return;
+        }
+
+        if let Err(e) = writeln!(sink.writer, "{line}") {
+            sink.failed = Some(e);
+        }
+    }
+
+    /// Records a transfer to or from the board at `path` that began at `started`
+    /// and has just ended.
+    fn record(&self, path: &Path, started: Instant, transfer: fmt::Arguments<'_>, outcome: &str) {
+        let at = started.saturating_duration_since(self.0.started);
+        let took = started.elapsed();
+
+        self.write(format_args!(
+            "{:.6} {path} {transfer} {}us {outcome}",
+            at.as_secs_f64(),
+            took.as_micros()
+        ));
+    }
+}
+
+impl fmt::Debug for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Trace")
+            .field("failed", &self.sink().failed.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+/// A board's bulk endpoints, with every transfer through them recorded to a
+/// [`Trace`].
+pub(crate) struct Recorder<B> {
+    bulk: B,
+    path: Path,
+    trace: Trace,
+}
+
+impl<B: Bulk> Recorder<B> {
+    pub(crate) fn new(bulk: B, path: Path, trace: Trace) -> Self {
+        Self { bulk, path, trace }
+    }
+}
+
+impl<B: Bulk> Bulk for Recorder<B> {
+    fn write_bulk(&self, endpoint: u8, buf: &[u8], timeout: Duration) -> rusb::Result<usize> {
+        let started = Instant::now();
+        let result = self.bulk.write_bulk(endpoint, buf, timeout);
+        let outcome = match &result {
+            Ok(written) => format!("ok {written}"),
+            Err(e) => format!("err {e:?}"),
+        };
+
+        self.trace.record(
+            &self.path,
+            started,
+            format_args!("write {endpoint:02x} {}", Hex(buf)),
+            &outcome,
+        );
+
+        result
+    }
+
+    fn read_bulk(&self, endpoint: u8, buf: &mut [u8], timeout: Duration) -> rusb::Result<usize> {
+        let started = Instant::now();
+        let result = self.bulk.read_bulk(endpoint, buf, timeout);
+        let outcome = match &result {
+            Ok(read) => format!("ok {}", Hex(&buf[..*read])),
+            Err(e) => format!("err {e:?}"),
+        };
+
+        self.trace.record(
+            &self.path,
+            started,
+            format_args!("read {endpoint:02x} {}", buf.len()),
+            &outcome,
+        );
+
+        result
+    }
+
+    fn reset(&self) -> rusb::Result<()> {
+        let started = Instant::now();
+        let result = self.bulk.reset();
+        let outcome = match &result {
+            Ok(()) => "ok".to_owned(),
+            Err(e) => format!("err {e:?}"),
+        };
+
+        self.trace
+            .record(&self.path, started, format_args!("reset"), &outcome);
+
+        result
+    }
+}
+
+/// One transfer of a trace, and what it came to.
+#[derive(Debug)]
+enum Transfer {
+    Write {
+        endpoint: u8,
+        data: Vec<u8>,
+        result: rusb::Result<usize>,
+    },
+    Read {
+        endpoint: u8,
+        len: usize,
+        result: rusb::Result<Vec<u8>>,
+    },
+    Reset {
+        result: rusb::Result<()>,
+    },
+}
+
+/// A [`Transfer`], with the line of the trace it came from to point at when a
+/// replay goes another way.
+#[derive(Debug)]
+struct Record {
+    line: usize,
+    transfer: Transfer,
+}
+
+/// A trace line that does not say what a trace line says.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("line {line} of the trace is not a transfer: {text:?}")]
+pub struct ParseTraceError {
+    line: usize,
+    text: String,
+}
+
+/// Reads `[ok VALUE]`, `[ok]` or `[err NAME]` from the end of a trace line, with
+/// `ok` making what it can of the value.
+fn outcome<T>(
+    fields: &[&str],
+    ok: impl FnOnce(Option<&str>) -> Option<T>,
+) -> Option<rusb::Result<T>> {
+    match *fields {
+        ["ok"] => ok(None).map(Ok),
+        ["ok", value] => ok(Some(value)).map(Ok),
+        ["err", name] => ERRORS
+            .into_iter()
+            .find(|e| format!("{e:?}") == name)
+            .map(Err),
+        _ => None,
+    }
+}
+
+/// Reads one trace line: where the transfer went, and what it was.
+fn parse(text: &str) -> Option<(Path, Transfer)> {
+    let fields: Vec<&str> = text.split_whitespace().collect();
+    let endpoint = |text| u8::from_str_radix(text, 16).ok();
+
+    let (path, transfer) = match fields[..] {
+        [_, path, "write", at, data, _, ref rest @ ..] => (
+            path,
+            Transfer::Write {
+                endpoint: endpoint(at)?,
+                data: unhex(data)?,
+                result: outcome(rest, |written| written?.parse().ok())?,
+            },
+        ),
+        [_, path, "read", at, len, _, ref rest @ ..] => {
+            let len = len.parse().ok()?;
+            let result = outcome(rest, |data| unhex(data?))?;
+
+            if result.as_ref().is_ok_and(|data| data.len() > len) {
+                return None;
+            }
+
+            (
+                path,
+                Transfer::Read {
+                    endpoint: endpoint(at)?,
+                    len,
+                    result,
+                },
+            )
+        }
+        [_, path, "reset", _, ref rest @ ..] => (
+            path,
+            Transfer::Reset {
+                result: outcome(rest, |value| value.is_none().then_some(()))?,
+            },
+        ),
+        _ => return None,
+    };
+
+    Some((path.parse().ok()?, transfer))
+}
+
+/// The transfers a [`Replay`] still has to give one board, in order.
+type Queue = Arc<Mutex<VecDeque<Record>>>;
+
+/// A [`Transport`] that plays a recorded [`Trace`] back.
+///
+/// Every board the trace mentions is attached, and each claim of one carries on
+/// from where the last left off, answering each transfer as the trace recorded it:
+/// the same bytes back from a read, the same errors from a write that failed. So
+/// long as the calls above make the same transfers they made in the field, they
+/// meet the same board, and fail the same way.
+///
+/// Cheap to clone, and every clone plays back the same trace, so a test can keep
+/// one to ask what is [left](Replay::remaining) after handing another to
+/// [`Usb::with_transport`](crate::Usb::with_transport).
+///
+/// # Panics
+///
+/// A transfer that is not the one the trace has next — a different write, a read
+/// of a different length, or one past the end — panics, naming the line of the
+/// trace it parted from. The calls no longer do what they did when it was
+/// recorded, and no answer the trace could give would mean anything.
+#[derive(Clone)]
+pub struct Replay(Arc<Vec<(Path, Queue)>>);
+
+impl Replay {
+    /// Reads the trace in the file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be read, or holds a line that is not a transfer, which
+    /// is reported as [`io::ErrorKind::InvalidData`].
+    pub fn open(path: impl AsRef<std::path::Path>) -> io::Result<Self> {
+        fs::read_to_string(path)?
+            .parse()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Returns how many transfers are left to play back, on every board together.
+    pub fn remaining(&self) -> usize {
+        self.0.iter().map(|(_, queue)| lock(queue).len()).sum()
+    }
+}
+
+fn lock(queue: &Queue) -> MutexGuard<'_, VecDeque<Record>> {
+    queue.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl FromStr for Replay {
+    type Err = ParseTraceError;
+
+    fn from_str(trace: &str) -> std::result::Result<Self, Self::Err> {
+        let mut boards: Vec<(Path, VecDeque<Record>)> = Vec::new();
+
+        for (index, text) in trace.lines().enumerate() {
+            let text = text.trim();
+
+            if text.is_empty() || text.starts_with('#') {
+                continue;
+            }
+
+            let line = index + 1;
+            let (path, transfer) = parse(text).ok_or_else(|| ParseTraceError {
+                line,
+                text: text.to_owned(),
+            })?;
+            let record = Record { line, transfer };
+
+            match boards.iter_mut().find(|(seen, _)| *seen == path) {
+                Some((_, records)) => records.push_back(record),
+                None => boards.push((path, VecDeque::from([record]))),
+            }
+        }
+
+        Ok(Self(Arc::new(
+            boards
+                .into_iter()
+                .map(|(path, records)| (path, Arc::new(Mutex::new(records))))
+                .collect(),
+        )))
+    }
+}
+
+impl fmt::Debug for Replay {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Replay")
+            .field("boards", &self.0.len())
+            .field("remaining", &self.remaining())
+            .finish()
+    }
+}
+
+impl Transport for Replay {
+    fn devices(&self) -> Result<Vec<Box<dyn Device>>> {
+        Ok(self
+            .0
+            .iter()
+            .map(|(path, queue)| {
+                Box::new(Replayed {
+                    path: path.clone(),
+                    queue: queue.clone(),
+                }) as Box<dyn Device>
+            })
+            .collect())
+    }
+}
+
+/// One board of a [`Replay`], as enumeration finds it and as a claim drives it.
+#[derive(Clone)]
+struct Replayed {
+    path: Path,
+    queue: Queue,
+}
+
+impl Replayed {
+    /// Takes the next transfer the trace has for this board, or panics with what
+    /// was asked for instead.
+    fn next(&self, asked: &str) -> Record {
+        lock(&self.queue).pop_front().unwrap_or_else(|| {
+            panic!(
+                "the trace of {} ends before the board's next transfer: {asked}",
+                self.path
+            )
+        })
+    }
+}
+
+/// Panics over a transfer that is not the one recorded.
+fn diverged(record: &Record, asked: &str) -> ! {
+    panic!(
+        "the replay diverged from line {} of the trace, which has {:?}, with {asked}",
+        record.line, record.transfer
+    )
+}
+
+impl Device for Replayed {
+    fn path(&self) -> Result<Path> {
+        Ok(self.path.clone())
+    }
+
+    fn claim(&self) -> Result<Box<dyn Link>> {
+        Ok(Box::new(Ch341a::new(self.clone())))
+    }
+}
+
+impl Bulk for Replayed {
+    fn write_bulk(&self, endpoint: u8, buf: &[u8], _timeout: Duration) -> rusb::Result<usize> {
+        let asked = format!("write {endpoint:02x} {}", Hex(buf));
+        let record = self.next(&asked);
+
+        match &record.transfer {
+            Transfer::Write {
+                endpoint: recorded,
+                data,
+                result,
+            } if *recorded == endpoint && data == buf => *result,
+            _ => diverged(&record, &asked),
+        }
+    }
+
+    fn read_bulk(&self, endpoint: u8, buf: &mut [u8], _timeout: Duration) -> rusb::Result<usize> {
+        let asked = format!("read {endpoint:02x} {}", buf.len());
+        let record = self.next(&asked);
+
+        match &record.transfer {
+            Transfer::Read {
+                endpoint: recorded,
+                len,
+                result,
+            } if *recorded == endpoint && *len == buf.len() => match result {
+                Ok(data) => {
+                    buf[..data.len()].copy_from_slice(data);
+
+                    Ok(data.len())
+                }
+                Err(e) => Err(*e),
+            },
+            _ => diverged(&record, &asked),
+        }
+    }
+
+    fn reset(&self) -> rusb::Result<()> {
+        let record = self.next("reset");
+
+        match &record.transfer {
+            Transfer::Reset { result } => *result,
+            _ => diverged(&record, "reset"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ch341a::emulator::Emulator;
+    use crate::{Error, Relay, Usb, Verify};
+
+    /// A board behind an emulated CH341A, whose claims record to a trace.
+    #[derive(Clone)]
+    struct Wired {
+        emulator: Emulator,
+        trace: Trace,
+    }
+
+    impl fmt::Debug for Wired {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("Wired").finish_non_exhaustive()
+        }
+    }
+
+    impl Transport for Wired {
+        fn devices(&self) -> Result<Vec<Box<dyn Device>>> {
+            Ok(vec![Box::new(self.clone())])
+        }
+    }
+
+    impl Device for Wired {
+        fn path(&self) -> Result<Path> {
+            Ok(Path::new(1, [1, 3]))
+        }
+
+        fn claim(&self) -> Result<Box<dyn Link>> {
+            let recorder = Recorder::new(self.emulator.clone(), self.path()?, self.trace.clone());
+
+            Ok(Box::new(Ch341a::new(recorder)))
+        }
+    }
+
+    /// Somewhere to record to that the test can read back.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Shared {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    /// Records a write, and a read that times out and so loses the register.
+    fn recorded() -> String {
+        let shared = Shared::default();
+        let emulator = Emulator::default();
+        let usb = Usb::with_transport(Wired {
+            emulator: emulator.clone(),
+            trace: Trace::new(shared.clone()),
+        });
+        let board = usb.board(None);
+
+        board
+            .set_relays(Relay::One | Relay::Three, Verify::Enabled)
+            .unwrap();
+        emulator.stall_next_read();
+
+        assert!(matches!(
+            board.relays(),
+            Err(Error::RegisterOutOfSync { .. })
+        ));
+
+        shared.text()
+    }
+
+    #[test]
+    fn a_recorded_failure_plays_back_the_same_way() {
+        let replay: Replay = recorded().parse().unwrap();
+        let board = Usb::with_transport(replay.clone()).board(None);
+
+        board
+            .set_relays(Relay::One | Relay::Three, Verify::Enabled)
+            .unwrap();
+
+        assert!(matches!(
+            board.relays(),
+            Err(Error::RegisterOutOfSync { .. })
+        ));
+        assert_eq!(replay.remaining(), 0);
+    }
+
+    #[test]
+    fn a_trace_spells_out_every_transfer() {
+        let trace = recorded();
+        let lines: Vec<_> = trace.lines().collect();
+
+        assert_eq!(lines[0], HEADER);
+
+        // The first write of a bit-banged shift: every line low, and claimed.
+        let first: Vec<_> = lines[1].split(' ').collect();
+
+        assert_eq!(first[1..4], ["1-1.3", "write", "02"]);
+        assert_eq!(first[4], "a16a1f0010003f00000000");
+        assert_eq!(first[6..], ["ok", "11"]);
+
+        // The read that timed out, and the drain behind it that found its samples.
+        let stalled = lines.iter().position(|line| line.ends_with("err Timeout"));
+        let drained = lines[stalled.unwrap() + 1];
+
+        assert!(drained.contains(" read 82 32 "), "{drained}");
+        assert!(drained.ends_with(" ok 0000000000800080"), "{drained}");
+    }
+
+    #[test]
+    #[should_panic(expected = "diverged from line 18 of the trace")]
+    fn a_replay_that_goes_another_way_says_where() {
+        let replay: Replay = recorded().parse().unwrap();
+
+        // Relay 2, where the trace set 1 and 3: the line changes agree until the
+        // sixth bit, whose first change is the trace's 17th transfer.
+        let _ = Usb::with_transport(replay)
+            .board(None)
+            .set_relays(Relay::Two.into(), Verify::Enabled);
+    }
+
+    #[test]
+    fn a_line_that_is_not_a_transfer_is_refused_by_number() {
+        let trace = "# arb trace\n0.1 1-1 reset 5us ok\n0.2 1-1 read 82 8 5us ok 0102\n0.3 1-1 write 02 zz 5us ok 1\n";
+
+        assert_eq!(
+            trace.parse::<Replay>().unwrap_err(),
+            ParseTraceError {
+                line: 4,
+                text: "0.3 1-1 write 02 zz 5us ok 1".to_owned(),
+            }
+        );
+
+        for bad in [
+            "0.1 1-1 read 82 1 5us ok 0102",
+            "0.1 1-1 reset 5us err Gone",
+            "0.1 1-1 reset 5us ok 3",
+            "0.1 1.1 reset 5us ok",
+        ] {
+            assert!(bad.parse::<Replay>().is_err(), "{bad}");
+        }
+
+        let replay: Replay = "0.1 1-1 read 82 8 5us err Timeout\n0.2 1-2 read 82 8 5us ok -"
+            .parse()
+            .unwrap();
+
+        assert_eq!(replay.remaining(), 2);
+        assert_eq!(replay.devices().unwrap().len(), 2);
+    }
+}
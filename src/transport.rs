@@ -0,0 +1,364 @@
+//! What a [`Board`](crate::Board) reaches its hardware through.
+//!
+//! A [`Transport`] finds devices, a [`Device`] is one it found, and a [`Link`] is
+//! one it has claimed. Everything above a `Link` is the A6275 protocol, which only
+//! needs the [`Gpio`] lines, so the same `Board` drives a CH341A behind libusb, a
+//! simulated board, or anything else that can carry those lines — a recorded
+//! trace, a proxy to another host, another USB stack — without telling them apart.
+//!
+//! [`Usb::new`](crate::Usb::new) uses libusb. [`Usb::with_transport`](crate::Usb::with_transport)
+//! takes any other, and every `Board` from it keeps the same API and the same
+//! errors, because the protocol that produces them sits above this seam.
+//!
+//! # Implementing a transport
+//!
+//! The contract is the one the CH341A keeps, and the library's guarantees rest
+//! on it:
+//!
+//! * A claim is exclusive and lasts as long as its `Link`. Claiming a device that
+//!   is already claimed fails with [`Error::Busy`](crate::Error::Busy), which is
+//!   what callers retry on; any other error says something is wrong.
+//! * [`Gpio::sample_clocked`] clocks the device whether or not its answer makes it
+//!   back, and so do [`Gpio::exchange`] and [`Gpio::stream`]. Reads are
+//!   destructive, so a failure there is reported as the register being lost, and a
+//!   transport that fails *before* clocking only makes that conservative.
+//! * A device that has gone away fails [`Device::claim`] with `NoDevice` or
+//!   `NotFound`, and only then: a board named by path keeps the device it found
+//!   until a claim says so, and enumerates again to find its replacement.
+//! * A failed transfer fails the call. Nothing above retries, so a transport that
+//!   retries on its own must be sure the retried transfer is idempotent: line
+//!   changes are, clocked reads are not.
+//!
+//! # Example
+//!
+//! ```
+//! use arb::transport::{Device, Transport};
+//!
+//! /// A transport that knows of no boards at all.
+//! #[derive(Debug)]
+//! struct Empty;
+//!
+//! impl Transport for Empty {
+//!     fn devices(&self) -> arb::Result<Vec<Box<dyn Device>>> {
+//!         Ok(Vec::new())
+//!     }
+//! }
+//!
+//! let usb = arb::Usb::with_transport(Empty);
+//!
+//! assert!(usb.boards().unwrap().is_empty());
+//! assert!(matches!(usb.board(None).relays(), Err(arb::Error::NotFound)));
+//! ```
+
+use std::fmt;
+use std::time::Duration;
+
+use crate::errors::Result;
+
+pub use crate::ch341a::{Gpio, SAMPLES, Step};
+pub use crate::find::Path;
+
+/// A way of finding relay boards.
+///
+/// Shared by every clone of the [`Usb`](crate::Usb) it is given to, and by every
+/// thread those are used from.
+pub trait Transport: fmt::Debug + Send + Sync {
+    /// Every attached device that answers to a relay board's IDs, in no particular
+    /// order.
+    ///
+    /// Finding none is not a failure: return an empty vector, and a board that
+    /// names one will report [`Error::NotFound`](crate::Error::NotFound).
+    fn devices(&self) -> Result<Vec<Box<dyn Device>>>;
+
+    /// Starts listening for devices arriving and leaving, if the transport can.
+    ///
+    /// `None`, the default, is no failure: a [`Watch`](crate::Watch) then polls
+    /// [`devices`](Transport::devices) instead of waiting to be told.
+    fn changes(&self) -> Result<Option<Box<dyn Changes>>> {
+        Ok(None)
+    }
+}
+
+/// Word of devices arriving and leaving, from [`Transport::changes`].
+///
+/// It only has to say *that* something changed: what changed is worked out by
+/// enumerating, so a notification too many costs an enumeration, and one that
+/// never comes holds an event back until the next.
+pub trait Changes: Send {
+    /// Blocks until a device may have arrived or left, or until `timeout` has
+    /// passed, and returns whether one may have.
+    fn wait(&mut self, timeout: Duration) -> Result<bool>;
+}
+
+/// One device a [`Transport`] found. Finding it opens nothing.
+pub trait Device: Send + Sync {
+    /// Where the device sits on the USB tree.
+    ///
+    /// Boards are told apart by this alone, so no two devices a transport returns
+    /// may share one.
+    fn path(&self) -> Result<Path>;
+
+    /// Opens the device and claims it for as long as the returned [`Link`] lives.
+    ///
+    /// The claim is exclusive, so claiming a device another application is
+    /// currently talking to fails with [`Error::Busy`](crate::Error::Busy).
+    fn claim(&self) -> Result<Box<dyn Link>>;
+}
+
+/// A claimed device: its GPIO lines, and the one operation that is not about them.
+pub trait Link: Gpio + Send {
+    /// Performs a USB port reset on the device.
+    fn reset(&self) -> Result<()>;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::sim::{Sim, SimBoard};
+    use crate::{Error, Relay, Usb, Verify};
+
+    /// A transport in front of simulated boards that counts the enumerations it
+    /// answers and the claims it hands out, and records the line changes made
+    /// through them. With `listeners`, it reports changes to the bus too, when
+    /// [`announce`](Recording::announce) says there was one.
+    #[derive(Debug)]
+    struct Recording {
+        sim: Sim,
+        enumerations: AtomicUsize,
+        claims: AtomicUsize,
+        lines: Mutex<Vec<u8>>,
+        listeners: Option<Mutex<Vec<Arc<AtomicBool>>>>,
+    }
+
+    impl Recording {
+        /// Tells everyone listening that the bus changed.
+        fn announce(&self) {
+            for changed in self
+                .listeners
+                .iter()
+                .flat_map(|l| l.lock().unwrap().clone())
+            {
+                changed.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// One listener's word of the bus changing.
+    struct Listener(Arc<AtomicBool>);
+
+    impl Changes for Listener {
+        fn wait(&mut self, _: Duration) -> Result<bool> {
+            Ok(self.0.swap(false, Ordering::Relaxed))
+        }
+    }
+
+    impl Transport for Arc<Recording> {
+        fn devices(&self) -> Result<Vec<Box<dyn Device>>> {
+            self.enumerations.fetch_add(1, Ordering::Relaxed);
+
+            Ok(self
+                .sim
+                .devices()?
+                .into_iter()
+                .map(|device| Box::new(Recorded(self.clone(), device)) as Box<dyn Device>)
+                .collect())
+        }
+
+        fn changes(&self) -> Result<Option<Box<dyn Changes>>> {
+            let Some(listeners) = &self.listeners else {
+                return Ok(None);
+            };
+
+            let changed = Arc::new(AtomicBool::new(false));
+            listeners.lock().unwrap().push(changed.clone());
+
+            Ok(Some(Box::new(Listener(changed))))
+        }
+    }
+
+    /// A device or a link, and the transport recording what goes through it.
+    struct Recorded<T>(Arc<Recording>, T);
+
+    impl Device for Recorded<Box<dyn Device>> {
+        fn path(&self) -> Result<Path> {
+            self.1.path()
+        }
+
+        fn claim(&self) -> Result<Box<dyn Link>> {
+            self.0.claims.fetch_add(1, Ordering::Relaxed);
+
+            Ok(Box::new(Recorded(self.0.clone(), self.1.claim()?)))
+        }
+    }
+
+    impl Gpio for Recorded<Box<dyn Link>> {
+        fn set_output(&self, data: u8) -> Result<()> {
+            self.0.lines.lock().unwrap().push(data);
+
+            self.1.set_output(data)
+        }
+
+        fn sample_clocked(&self, clock: u8) -> Result<[u8; SAMPLES]> {
+            self.1.sample_clocked(clock)
+        }
+    }
+
+    impl Link for Recorded<Box<dyn Link>> {
+        fn reset(&self) -> Result<()> {
+            self.1.reset()
+        }
+    }
+
+    fn recording(boards: usize) -> Arc<Recording> {
+        recording_with_handles(boards).0
+    }
+
+    /// As [`recording`], with the far side of every board.
+    fn recording_with_handles(boards: usize) -> (Arc<Recording>, Vec<SimBoard>) {
+        recorded(boards, None)
+    }
+
+    /// As [`recording_with_handles`], reporting changes to the bus.
+    fn listening_with_handles(boards: usize) -> (Arc<Recording>, Vec<SimBoard>) {
+        recorded(boards, Some(Mutex::default()))
+    }
+
+    fn recorded(
+        boards: usize,
+        listeners: Option<Mutex<Vec<Arc<AtomicBool>>>>,
+    ) -> (Arc<Recording>, Vec<SimBoard>) {
+        let (sim, sims) = Sim::new(boards);
+
+        let transport = Arc::new(Recording {
+            sim,
+            enumerations: AtomicUsize::new(0),
+            claims: AtomicUsize::new(0),
+            lines: Mutex::new(Vec::new()),
+            listeners,
+        });
+
+        (transport, sims)
+    }
+
+    #[test]
+    fn a_board_reaches_its_hardware_through_the_transport_it_was_given() {
+        let transport = recording(1);
+        let board = Usb::with_transport(transport.clone()).board(None);
+
+        board
+            .set_relays(Relay::Five.into(), Verify::Disabled)
+            .unwrap();
+
+        // One claim per call, and every line change went through it.
+        assert_eq!(transport.claims.load(Ordering::Relaxed), 1);
+        assert_eq!(transport.lines.lock().unwrap().len(), 28);
+
+        assert_eq!(board.relays().unwrap(), Relay::Five.into());
+        assert_eq!(transport.claims.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn a_transport_keeps_the_library_s_errors() {
+        let usb = Usb::with_transport(recording(2));
+
+        // Enumeration and selection sit above the seam, so they behave the same
+        // whatever is underneath.
+        assert!(matches!(
+            usb.board(None).relays(),
+            Err(Error::MultipleFound)
+        ));
+        assert!(matches!(usb.board(Some(9)).relays(), Err(Error::NotFound)));
+        assert_eq!(usb.boards().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn a_board_named_by_path_enumerates_only_until_it_is_found() {
+        let (transport, sims) = recording_with_handles(2);
+        let usb = Usb::with_transport(transport.clone());
+        let enumerations = || transport.enumerations.load(Ordering::Relaxed);
+
+        let board = usb.boards().unwrap().remove(1);
+        let copy = board.clone();
+
+        for _ in 0..3 {
+            board.relays().unwrap();
+            copy.relays().unwrap();
+        }
+
+        // One for `boards`, one for the first call: the clone shares what it found.
+        assert_eq!(enumerations(), 2);
+
+        // A device that has gone away is looked for again, and once it is back
+        // the board finds it and stops looking.
+        sims[1].unplug();
+
+        assert!(matches!(board.relays(), Err(Error::NotFound)));
+        assert_eq!(enumerations(), 3);
+
+        sims[1].plug();
+        board.relays().unwrap();
+        board.relays().unwrap();
+
+        assert_eq!(enumerations(), 4);
+    }
+
+    #[test]
+    fn a_board_named_by_port_still_notices_a_second_board_arriving() {
+        let (transport, sims) = recording_with_handles(2);
+        let usb = Usb::with_transport(transport.clone());
+        sims[1].unplug();
+
+        let board = usb.board(None);
+
+        board.relays().unwrap();
+        board.relays().unwrap();
+
+        // A transport that reports no changes leaves every call to enumerate:
+        // nothing short of that tells it a second board has turned up answering to
+        // the same name.
+        assert_eq!(transport.enumerations.load(Ordering::Relaxed), 2);
+
+        sims[1].plug();
+
+        assert!(matches!(board.relays(), Err(Error::MultipleFound)));
+    }
+
+    #[test]
+    fn a_board_named_by_port_enumerates_again_only_when_the_bus_changes() {
+        let (transport, sims) = listening_with_handles(2);
+        let usb = Usb::with_transport(transport.clone());
+        let enumerations = || transport.enumerations.load(Ordering::Relaxed);
+        sims[1].unplug();
+
+        let board = usb.board(None);
+        let copy = board.clone();
+
+        for _ in 0..3 {
+            board.relays().unwrap();
+            copy.relays().unwrap();
+        }
+
+        assert_eq!(enumerations(), 1);
+
+        // A second board answering to the same name is caught once the transport
+        // says something arrived.
+        sims[1].plug();
+        transport.announce();
+
+        assert!(matches!(board.relays(), Err(Error::MultipleFound)));
+        assert_eq!(enumerations(), 2);
+
+        // And the one it found going away is caught by the claim failing, even
+        // before the transport says so.
+        let port = usb.board(Some(1));
+
+        port.relays().unwrap();
+        sims[0].unplug();
+
+        assert!(matches!(port.relays(), Err(Error::NotFound)));
+        assert_eq!(enumerations(), 4);
+    }
+}
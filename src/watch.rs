@@ -0,0 +1,262 @@
+//! Learning of boards as they are plugged in and unplugged.
+
+use std::collections::{BTreeSet, VecDeque};
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+use crate::errors::Result;
+use crate::find::{Path, find_devices};
+use crate::transport::Changes;
+use crate::{Board, Usb};
+
+/// How long a [`Watch`] waits between looks at the bus, unless
+/// [`Watch::with_interval`] says otherwise.
+const INTERVAL: Duration = Duration::from_secs(1);
+
+/// A board arriving or leaving, as a [`Watch`] reports it.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub enum BoardEvent {
+    /// A board was plugged in. The [`Board`] names it by path, as one from
+    /// [`Usb::boards`] does.
+    Arrived(Board),
+    /// The board at this path was unplugged.
+    Left(Path),
+}
+
+/// Boards arriving and leaving, from [`Usb::watch`], as a blocking iterator that
+/// never ends.
+///
+/// The first events are an [`Arrived`](BoardEvent::Arrived) for every board
+/// attached when the watch started, so a caller that sets up each board as it
+/// appears needs no separate pass over [`Usb::boards`] to catch the ones already
+/// there.
+///
+/// Where the transport reports devices coming and going — libusb's hotplug
+/// support, on the platforms that have it — the bus is only enumerated when it
+/// says something changed, and an event follows within moments of the board.
+/// Elsewhere the watch polls, once a second unless
+/// [`with_interval`](Watch::with_interval) says otherwise, and a board unplugged
+/// and plugged back in between two looks is not seen to have gone.
+///
+/// An enumeration that fails yields its error, and the watch carries on from
+/// there: the next call looks again.
+///
+/// # Example
+///
+/// ```no_run
+/// use arb::{BoardEvent, Relays, Usb, Verify};
+///
+/// let usb = Usb::new().unwrap();
+///
+/// for event in usb.watch().unwrap() {
+///     match event.unwrap() {
+///         BoardEvent::Arrived(board) => board.set_relays(Relays::NONE, Verify::Enabled).unwrap(),
+///         BoardEvent::Left(path) => println!("{path} left"),
+///         _ => {}
+///     }
+/// }
+/// ```
+pub struct Watch {
+    usb: Usb,
+    changes: Option<Box<dyn Changes>>,
+    interval: Duration,
+    known: BTreeSet<Path>,
+    pending: VecDeque<BoardEvent>,
+    started: bool,
+}
+
+impl Watch {
+    pub(crate) fn new(usb: Usb, changes: Option<Box<dyn Changes>>) -> Self {
+        Self {
+            usb,
+            changes,
+            interval: INTERVAL,
+            known: BTreeSet::new(),
+            pending: VecDeque::new(),
+            started: false,
+        }
+    }
+
+    /// Returns this watch polling every `interval` where the transport cannot say
+    /// when something changed. Where it can, this is how long each wait for it
+    /// lasts, which changes nothing but how often the watch wakes up to wait again.
+    pub fn with_interval(self, interval: Duration) -> Self {
+        Self { interval, ..self }
+    }
+
+    /// Waits until the bus is worth looking at again: until the transport says
+    /// something changed, or for one interval where it cannot say.
+    fn wait(&mut self) -> Result<()> {
+        let Some(changes) = &mut self.changes else {
+            thread::sleep(self.interval);
+
+            return Ok(());
+        };
+
+        while !changes.wait(self.interval)? {}
+
+        Ok(())
+    }
+
+    /// Enumerates the bus and queues an event for every board that has come or
+    /// gone since the last time. Departures first, so a caller never holds an
+    /// arrival for a path it has yet to hear the previous board leave.
+    fn scan(&mut self) -> Result<()> {
+        let found: BTreeSet<Path> = find_devices(&*self.usb.transport)?.into_keys().collect();
+
+        for path in self.known.difference(&found) {
+            self.pending.push_back(BoardEvent::Left(path.clone()));
+        }
+
+        for path in found.difference(&self.known) {
+            let board = self.usb.board_at(path.clone());
+
+            self.pending.push_back(BoardEvent::Arrived(board));
+        }
+
+        self.known = found;
+
+        Ok(())
+    }
+}
+
+impl Iterator for Watch {
+    type Item = Result<BoardEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Some(Ok(event));
+            }
+
+            if self.started {
+                if let Err(e) = self.wait() {
+                    return Some(Err(e));
+                }
+            }
+
+            self.started = true;
+
+            if let Err(e) = self.scan() {
+                return Some(Err(e));
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Watch")
+            .field("usb", &self.usb)
+            .field("hotplug", &self.changes.is_some())
+            .field("interval", &self.interval)
+            .field("known", &self.known)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::sim::Sim;
+    use crate::transport::{Device, Transport};
+
+    /// Simulated boards behind a transport that says when something changed, by
+    /// handing out the answers a test queued, and counts its enumerations.
+    #[derive(Debug)]
+    struct Told {
+        sim: Sim,
+        answers: Arc<Mutex<VecDeque<bool>>>,
+        enumerations: Arc<AtomicUsize>,
+    }
+
+    struct Answers(Arc<Mutex<VecDeque<bool>>>);
+
+    impl Changes for Answers {
+        fn wait(&mut self, _timeout: Duration) -> Result<bool> {
+            Ok(self.0.lock().unwrap().pop_front().unwrap_or(true))
+        }
+    }
+
+    impl Transport for Told {
+        fn devices(&self) -> Result<Vec<Box<dyn Device>>> {
+            self.enumerations.fetch_add(1, Ordering::Relaxed);
+
+            self.sim.devices()
+        }
+
+        fn changes(&self) -> Result<Option<Box<dyn Changes>>> {
+            Ok(Some(Box::new(Answers(self.answers.clone()))))
+        }
+    }
+
+    fn arrived(event: Option<Result<BoardEvent>>) -> Path {
+        match event.unwrap().unwrap() {
+            BoardEvent::Arrived(board) => board.path().unwrap().clone(),
+            event => panic!("expected an arrival, got {event:?}"),
+        }
+    }
+
+    fn left(event: Option<Result<BoardEvent>>) -> Path {
+        match event.unwrap().unwrap() {
+            BoardEvent::Left(path) => path,
+            event => panic!("expected a departure, got {event:?}"),
+        }
+    }
+
+    #[test]
+    fn a_watch_starts_with_the_boards_already_attached() {
+        let (usb, _sims) = Usb::simulated(2);
+        let mut watch = usb.watch().unwrap();
+
+        assert_eq!(arrived(watch.next()), Path::new(1, [1]));
+        assert_eq!(arrived(watch.next()), Path::new(1, [2]));
+    }
+
+    #[test]
+    fn a_polling_watch_reports_boards_leaving_and_arriving() {
+        let (usb, sims) = Usb::simulated(2);
+        let mut watch = usb.watch().unwrap().with_interval(Duration::ZERO);
+
+        watch.next();
+        watch.next();
+
+        sims[0].unplug();
+
+        assert_eq!(left(watch.next()), Path::new(1, [1]));
+
+        sims[0].plug();
+
+        let path = arrived(watch.next());
+
+        // The arrival names the board by where it is, so it can be driven at once.
+        assert_eq!(path, Path::new(1, [1]));
+        assert!(usb.board(Some(1)).relays().is_ok());
+    }
+
+    #[test]
+    fn a_watch_that_is_told_of_changes_only_looks_when_told() {
+        let (sim, sims) = Sim::new(1);
+        let answers = Arc::new(Mutex::new(VecDeque::from([false, false, true])));
+        let enumerations = Arc::new(AtomicUsize::new(0));
+        let usb = Usb::with_transport(Told {
+            sim,
+            answers: answers.clone(),
+            enumerations: enumerations.clone(),
+        });
+        let mut watch = usb.watch().unwrap();
+
+        arrived(watch.next());
+        sims[0].unplug();
+
+        // Two waits that heard nothing, then one that did: one enumeration for it.
+        assert_eq!(left(watch.next()), Path::new(1, [1]));
+        assert_eq!(enumerations.load(Ordering::Relaxed), 2);
+        assert!(answers.lock().unwrap().is_empty());
+    }
+}